@@ -1,8 +1,6 @@
 #![no_std]
 #![no_main]
 
-!!! FLASH DOES NOT WORK AND IS DISABLE NOW !!!
-
 use core::fmt::Write;
 use embedded_graphics::{
 	mono_font::{ascii::FONT_6X10, MonoTextStyle},
@@ -17,7 +15,7 @@ use pybadge_high::Color;
 #[entry]
 fn main() -> ! {
 	let pybadge = PyBadge::take().unwrap();
-	let mut flash = pybadge.flash;
+	let mut flash = pybadge.flash.unwrap();
 	let mut display = pybadge.display;
 	let mut delay = pybadge.delay;
 	let style = MonoTextStyle::new(&FONT_6X10, Color::WHITE);
@@ -26,10 +24,11 @@ fn main() -> ! {
 	//test page read
 	let addr = 0; // start adress of page i (i << 8)
 	let write_buf = [2_u8, 34, 3, 220, 45, 84, 12, 87];
-	flash.write_page(addr, &write_buf);
+	flash.erase_sector(addr).unwrap();
+	flash.write_page(addr, &write_buf).unwrap();
 	delay.delay_ms(255u8);
 	let mut read_buf = [1_u8; 8];
-	flash.read(addr, &mut read_buf);
+	flash.read(addr, &mut read_buf).unwrap();
 	display.clear(Color::BLACK).unwrap();
 	write!(string, "{read_buf:?}").unwrap();
 	Text::new(&string, Point::new(0, 30), style)
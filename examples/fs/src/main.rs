@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+//! List the files of the `CIRCUITPY` drive at the display.
+//! If the flash is not formatted yet, a new volume is created.
+
+use core::fmt::Write;
+use embedded_graphics::{
+	mono_font::{ascii::FONT_6X10, MonoTextStyle},
+	prelude::*,
+	text::Text
+};
+use heapless::String;
+use pybadge::{
+	fs::{FileSystem, FlashDisk},
+	prelude::*,
+	PyBadge
+};
+use pybadge_high as pybadge;
+use pybadge_high::Color;
+
+#[entry]
+fn main() -> ! {
+	let pybadge = PyBadge::take().unwrap();
+	let mut display = pybadge.display;
+	let style = MonoTextStyle::new(&FONT_6X10, Color::WHITE);
+	let mut string = String::<512>::new();
+
+	let mut fs =
+		FileSystem::mount_or_format(FlashDisk::new(pybadge.flash.unwrap()), "CIRCUITPY")
+			.unwrap();
+	let mut dir = fs.read_dir("/").unwrap();
+	while let Some(entry) = fs.next_entry(&mut dir).unwrap() {
+		writeln!(string, "{:12} {:>8}", entry.name(), entry.size()).ok();
+	}
+
+	display.clear(Color::BLACK).unwrap();
+	Text::new(&string, Point::new(5, 10), style)
+		.draw(&mut display)
+		.unwrap();
+	#[allow(clippy::empty_loop)]
+	loop {}
+}
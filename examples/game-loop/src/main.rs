@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+//! Move a ball with the d-pad at constant speed, independent of the frame rate.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use embedded_graphics::{
+	mono_font::{ascii::FONT_6X10, MonoTextStyle},
+	prelude::*,
+	primitives::{Circle, PrimitiveStyle, Rectangle},
+	text::Text
+};
+use heapless::String;
+use pybadge::{game_loop::GameLoop, Color, PyBadge};
+use pybadge_high as pybadge;
+
+/// pixels per second
+const SPEED: f32 = 60.0;
+
+struct Game {
+	pybadge: PyBadge,
+	x: f32,
+	y: f32,
+	/// position at the last drawn frame
+	drawn: Point
+}
+
+#[entry]
+fn main() -> ! {
+	let mut pybadge = PyBadge::take().unwrap();
+	pybadge.display.clear(Color::BLACK).unwrap();
+	let mut game = Game {
+		pybadge,
+		x: 80.0,
+		y: 64.0,
+		drawn: Point::new(80, 64)
+	};
+	GameLoop::new(60).max_fps(30).run(
+		&mut game,
+		|game, dt| {
+			let buttons = &mut game.pybadge.buttons;
+			buttons.update();
+			let step = SPEED * dt.as_secs_f32();
+			if buttons.left_pressed() {
+				game.x -= step;
+			}
+			if buttons.right_pressed() {
+				game.x += step;
+			}
+			if buttons.up_pressed() {
+				game.y -= step;
+			}
+			if buttons.down_pressed() {
+				game.y += step;
+			}
+			game.x = game.x.clamp(5.0, 155.0);
+			game.y = game.y.clamp(20.0, 123.0);
+		},
+		|game, stats| {
+			let display = &mut game.pybadge.display;
+			//only redraw the changed parts, clearing the whole display is slow
+			let position = Point::new(game.x as i32, game.y as i32);
+			Circle::with_center(game.drawn, 10)
+				.into_styled(PrimitiveStyle::with_fill(Color::BLACK))
+				.draw(display)
+				.unwrap();
+			Circle::with_center(position, 10)
+				.into_styled(PrimitiveStyle::with_fill(Color::GREEN))
+				.draw(display)
+				.unwrap();
+			game.drawn = position;
+
+			let mut text = String::<32>::new();
+			write!(text, "fps: {:2}", stats.fps).unwrap();
+			Rectangle::new(Point::new(0, 0), Size::new(160, 12))
+				.into_styled(PrimitiveStyle::with_fill(Color::BLACK))
+				.draw(display)
+				.unwrap();
+			Text::new(
+				&text,
+				Point::new(5, 10),
+				MonoTextStyle::new(&FONT_6X10, Color::WHITE)
+			)
+			.draw(display)
+			.unwrap();
+		}
+	)
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+//! Show the date and time of the real-time clock.
+//!
+//! The time can be set over usb serial by `date +T%s > /dev/ttyACM0`.
+//! It is kept during a reset, as long as the power is connected.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use embedded_graphics::{
+	mono_font::{ascii::FONT_6X10, MonoTextStyle},
+	prelude::*,
+	text::Text
+};
+use heapless::String;
+use pybadge::{Color, PyBadge};
+use pybadge_high as pybadge;
+
+#[entry]
+fn main() -> ! {
+	let pybadge = PyBadge::take().unwrap();
+	let mut display = pybadge.display;
+	let mut rtc = pybadge.rtc;
+	let mut usb = pybadge.usb_builder.build();
+	let style = MonoTextStyle::new(&FONT_6X10, Color::WHITE);
+	let mut string = String::<32>::new();
+	let mut last = None;
+	loop {
+		usb.poll();
+		if rtc.sync_usb(&mut usb) {
+			writeln!(usb, "time set to {}", rtc.now()).ok();
+		}
+		let now = rtc.now();
+		if last == Some(now) {
+			continue;
+		}
+		last = Some(now);
+		display.clear(Color::BLACK).unwrap();
+		string.clear();
+		write!(string, "{}", now).unwrap();
+		Text::new(&string, Point::new(20, 30), style)
+			.draw(&mut display)
+			.unwrap();
+		string.clear();
+		write!(string, "{:?}", now.weekday()).unwrap();
+		Text::new(&string, Point::new(20, 50), style)
+			.draw(&mut display)
+			.unwrap();
+		if !rtc.is_set() {
+			Text::new("time is not set", Point::new(20, 70), style)
+				.draw(&mut display)
+				.unwrap();
+		}
+	}
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+//! Count how often the pybadge was started and show it at the display.
+
+use core::fmt::Write;
+use embedded_graphics::{
+	mono_font::{ascii::FONT_6X10, MonoTextStyle},
+	prelude::*,
+	text::Text
+};
+use heapless::String;
+use pybadge::{
+	flash,
+	prelude::*,
+	settings::{Key, Settings},
+	PyBadge
+};
+use pybadge_high as pybadge;
+use pybadge_high::Color;
+
+const BOOT_COUNT: Key<u32> = Key::new(1);
+
+#[entry]
+fn main() -> ! {
+	let pybadge = PyBadge::take().unwrap();
+	let mut display = pybadge.display;
+	let style = MonoTextStyle::new(&FONT_6X10, Color::WHITE);
+	let mut string = String::<32>::new();
+
+	// use the last 64K bytes of the flash
+	let mut settings = Settings::new(
+		pybadge.flash.unwrap(),
+		flash::CAPACITY - flash::BLOCK_64K_SIZE..flash::CAPACITY
+	)
+	.unwrap();
+	let boot_count = settings.get(BOOT_COUNT).unwrap().unwrap_or(0) + 1;
+	settings.set(BOOT_COUNT, &boot_count).unwrap();
+
+	display.clear(Color::BLACK).unwrap();
+	write!(string, "started {boot_count} times").unwrap();
+	Text::new(&string, Point::new(20, 30), style)
+		.draw(&mut display)
+		.unwrap();
+	#[allow(clippy::empty_loop)]
+	loop {}
+}
@@ -9,7 +9,10 @@ use embedded_graphics::{
 use heapless::String;
 use pybadge::{prelude::*, PyBadge};
 use pybadge_high as pybadge;
-use pybadge_high::{time::uptime, Color};
+use pybadge_high::{
+	time::{uptime, Instant},
+	Color
+};
 
 #[entry]
 fn main() -> ! {
@@ -26,6 +29,16 @@ fn main() -> ! {
 		Text::new(&string, Point::new(20, 30), style)
 			.draw(&mut display)
 			.unwrap();
+		string.clear();
+		write!(
+			string,
+			"uptime: {} us",
+			Instant::now().since_start().as_micros()
+		)
+		.unwrap();
+		Text::new(&string, Point::new(20, 50), style)
+			.draw(&mut display)
+			.unwrap();
 		delay.delay_ms(500_u16);
 	}
 }
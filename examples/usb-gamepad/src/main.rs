@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+//! Use the pybadge as game controller.
+
+use pybadge::{prelude::*, PyBadge};
+use pybadge_high as pybadge;
+
+#[entry]
+fn main() -> ! {
+	let pybadge = PyBadge::take().unwrap();
+	let mut buttons = pybadge.buttons;
+	let mut usb = pybadge
+		.usb_builder
+		.product("PyBadge Gamepad")
+		.gamepad()
+		.serial(false)
+		.build();
+	usb.enable_interrupt();
+
+	loop {
+		// the new state is reported to the host automatically
+		buttons.update();
+	}
+}
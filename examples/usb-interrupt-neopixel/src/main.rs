@@ -1,14 +1,11 @@
 #![no_std]
 #![no_main]
 
-use pybadge::{prelude::*, usb::Usb, NeoPixelColor, PyBadge};
+use pybadge::{prelude::*, NeoPixelColor, PyBadge};
 use pybadge_high as pybadge;
 
 const NUM_LEDS: u8 = 5;
 
-static mut USB: Option<Usb> = None;
-static mut COLOR: NeoPixelColor = NeoPixelColor { r: 1, g: 1, b: 1 };
-
 #[entry]
 fn main() -> ! {
 	let pybadge = PyBadge::take().unwrap();
@@ -17,21 +14,26 @@ fn main() -> ! {
 
 	//set usb + interrupt
 	let mut usb = pybadge.usb_builder.product("LED-Controller").build();
-	usb.set_interrupt(interrupt); //set the interupt() as interrupt handler
-	unsafe {
-		USB = Some(usb);
-		// set USB firt to Some first,
-		// otherwise a interrup called in the next cycle will not be able to unwrap USB.
-		USB.as_mut().unwrap().enable_interrupt();
-	};
+	// the interrupt does receive the data, while the main loop is sleeping
+	usb.enable_interrupt();
 
 	delay.delay_ms(100u8); //neopixel needs some delay at the start
+	let mut color = NeoPixelColor { r: 1, g: 1, b: 1 };
 	let mut i = 0;
 	loop {
+		//use the last send character
+		while let Some(byte) = usb.try_read_byte() {
+			color = match byte as char {
+				'r' | 'R' => NeoPixelColor { r: 2, g: 0, b: 0 },
+				'g' | 'G' => NeoPixelColor { r: 0, g: 2, b: 0 },
+				'b' | 'B' => NeoPixelColor { r: 0, g: 0, b: 2 },
+				_ => NeoPixelColor::default()
+			};
+		}
 		neopixel
 			.write((0..NUM_LEDS).map(|ii| {
 				if i == ii {
-					unsafe { COLOR }
+					color
 				} else {
 					NeoPixelColor::default()
 				}
@@ -46,19 +48,3 @@ fn main() -> ! {
 		}
 	}
 }
-
-fn interrupt() {
-	let usb = unsafe { USB.as_mut().unwrap() };
-	let mut buf = [0u8; 64];
-	let count = usb.read(&mut buf).ok();
-	if let Some(count) = count {
-		let char = buf[count - 1] as char; //get the lasted sended element
-		let color = match char {
-			'r' | 'R' => NeoPixelColor { r: 2, g: 0, b: 0 },
-			'g' | 'G' => NeoPixelColor { r: 0, g: 2, b: 0 },
-			'b' | 'B' => NeoPixelColor { r: 0, g: 0, b: 2 },
-			_ => NeoPixelColor::default()
-		};
-		unsafe { COLOR = color };
-	}
-}
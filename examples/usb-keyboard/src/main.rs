@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+//! Use the pybadge as keyboard.
+//! The d-pad, A and B are send as arrow keys, `Z` and `X`.
+//! Start types a whole line of text.
+
+use pybadge::{
+	buttons::{Button, KeyEvent},
+	prelude::*,
+	usb::keyboard::{Key, Keymap},
+	PyBadge
+};
+use pybadge_high as pybadge;
+
+#[entry]
+fn main() -> ! {
+	let pybadge = PyBadge::take().unwrap();
+	let mut buttons = pybadge.buttons;
+	let mut usb = pybadge
+		.usb_builder
+		.product("PyBadge Keyboard")
+		.keyboard()
+		.build();
+	let keymap = Keymap {
+		start: Key::NONE,
+		..Default::default()
+	};
+
+	loop {
+		usb.poll();
+		buttons.update();
+		if buttons.events().next().is_none() {
+			continue;
+		}
+		if buttons
+			.events()
+			.any(|event| event == KeyEvent::Pressed(Button::Start))
+		{
+			usb.type_str("Hello from the PyBadge!\n").ok();
+		}
+		usb.press_buttons(&buttons, &keymap).ok();
+	}
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+//! Show the external flash as drive at the host.
+//! Each time the A button is pressed, a line is added to `presses.txt`.
+
+use pybadge::{
+	fs::{FileSystem, FlashDisk},
+	prelude::*,
+	usb::Usb,
+	PyBadge
+};
+use pybadge_high as pybadge;
+
+static mut USB: Option<Usb> = None;
+
+#[entry]
+fn main() -> ! {
+	let pybadge = PyBadge::take().unwrap();
+	let mut buttons = pybadge.buttons;
+
+	// the host can only show a formatted drive
+	let fs =
+		FileSystem::mount_or_format(FlashDisk::new(pybadge.flash.unwrap()), "PYBADGE")
+			.unwrap();
+	let flash = fs.release().unwrap().release().unwrap();
+
+	let usb = pybadge
+		.usb_builder
+		.product("PyBadge Drive")
+		.mass_storage(flash)
+		.build();
+	unsafe {
+		USB = Some(usb);
+		USB.as_mut().unwrap().enable_interrupt();
+	}
+	let usb = unsafe { USB.as_mut().unwrap() };
+
+	loop {
+		// write the files of the host to the flash
+		usb.process_disk();
+		buttons.update();
+		if buttons.a_pressed() {
+			usb.with_disk(|disk| {
+				let mut fs = FileSystem::mount(disk).unwrap();
+				let mut file = match fs.open("/presses.txt") {
+					Ok(file) => file,
+					Err(_) => fs.create("/presses.txt").unwrap()
+				};
+				file.seek(file.size());
+				fs.write(&mut file, b"A pressed\n").unwrap();
+				fs.close(file).unwrap();
+			});
+			while buttons.a_pressed() {
+				buttons.update();
+			}
+		}
+	}
+}
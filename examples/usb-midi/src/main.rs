@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+//! Use the pybadge as tiny MIDI controller and synthesizer.
+//! The buttons send the notes of the C major scale.
+//! Received notes are played at the speaker.
+
+use pybadge::{
+	buttons::{Button, KeyEvent},
+	prelude::*,
+	usb::midi::{MidiMessage, MonoSynth},
+	PyBadge
+};
+use pybadge_high as pybadge;
+
+fn note(button: Button) -> u8 {
+	match button {
+		Button::Left => 60,
+		Button::Up => 62,
+		Button::Right => 64,
+		Button::Down => 65,
+		Button::Select => 67,
+		Button::Start => 69,
+		Button::B => 71,
+		Button::A => 72
+	}
+}
+
+#[entry]
+fn main() -> ! {
+	let pybadge = PyBadge::take().unwrap();
+	let mut buttons = pybadge.buttons;
+	let mut speaker = pybadge.speaker;
+	let mut usb = pybadge
+		.usb_builder
+		.product("PyBadge MIDI")
+		.midi()
+		.serial(false)
+		.build();
+	usb.enable_interrupt();
+	let mut synth = MonoSynth::new();
+
+	loop {
+		buttons.update();
+		for event in buttons.events() {
+			let message = match event {
+				KeyEvent::Pressed(button) => MidiMessage::NoteOn {
+					channel: 0,
+					note: note(button),
+					velocity: 100
+				},
+				KeyEvent::Released(button) => MidiMessage::NoteOff {
+					channel: 0,
+					note: note(button),
+					velocity: 0
+				}
+			};
+			usb.midi_send(&message).ok();
+		}
+		while let Some(packet) = usb.midi_receive() {
+			if let Some(message) = packet.message() {
+				synth.play(&message, &mut speaker);
+			}
+		}
+	}
+}
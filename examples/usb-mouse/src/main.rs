@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+//! Use the pybadge as mouse.
+//! The d-pad does move the pointer, A and B are the left and right mouse button.
+
+use pybadge::{prelude::*, usb::mouse::DpadMouse, PyBadge};
+use pybadge_high as pybadge;
+
+#[entry]
+fn main() -> ! {
+	let pybadge = PyBadge::take().unwrap();
+	let mut buttons = pybadge.buttons;
+	let mut delay = pybadge.delay;
+	let mut usb = pybadge
+		.usb_builder
+		.product("PyBadge Mouse")
+		.mouse()
+		.serial(false)
+		.build();
+	usb.enable_interrupt();
+	let mut mouse = DpadMouse::new();
+
+	loop {
+		buttons.update();
+		let report = mouse.update(buttons.pressed(), 10);
+		usb.move_mouse(&report).ok();
+		delay.delay_ms(10_u8);
+	}
+}
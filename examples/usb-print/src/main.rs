@@ -0,0 +1,33 @@
+#![no_std]
+#![no_main]
+
+//! Print the uptime and the pressed buttons to the usb serial port
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use pybadge::{time::uptime, PyBadge};
+use pybadge_high as pybadge;
+
+#[entry]
+fn main() -> ! {
+	let mut pybadge = PyBadge::take().unwrap();
+	let mut usb = pybadge.usb_builder.build();
+	let mut last_print = uptime();
+	loop {
+		usb.poll();
+		pybadge.buttons.update();
+		if !usb.dtr() {
+			// no terminal is open, so nobody would read the output
+			continue;
+		}
+		for event in pybadge.buttons.events() {
+			// the write does fail, if the terminal stops reading
+			writeln!(usb, "{:?}", event).ok();
+		}
+		let now = uptime();
+		if now.0 - last_print.0 >= 1000 {
+			last_print = now;
+			writeln!(usb, "uptime: {} ms", now.0).ok();
+		}
+	}
+}
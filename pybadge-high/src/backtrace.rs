@@ -0,0 +1,112 @@
+//! Backtrace by following the chain of frame pointers.
+//!
+//! In thumb mode llvm uses `r7` as frame pointer.
+//! Each function, which has a frame, pushes the old `r7` and the link register next to each other
+//! and let `r7` point to them.
+//! Functions without frame do not change `r7`, so they are skipped.
+//! Use `-C force-frame-pointers=yes` to get a frame for each function.
+
+use core::{
+	fmt,
+	fmt::{Display, Formatter},
+	ops::Range
+};
+
+/// maximum number of return addresses, which are collected
+const MAX_FRAMES: usize = 8;
+/// addresses printed at one line of the bluescreen
+const PER_LINE: usize = 4;
+
+extern "C" {
+	/// start of the program code, defined by cortex-m-rt
+	static __stext: u16;
+	/// end of the program code, defined by cortex-m-rt
+	static __etext: u16;
+}
+
+fn text() -> Range<u32> {
+	unsafe { &__stext as *const u16 as u32..&__etext as *const u16 as u32 }
+}
+
+/// Return true, if the instruction before `address` is a `bl` or `blx`,
+/// so `address` is a real return address and not a random value of the stack.
+fn is_after_call(text: &Range<u32>, address: u32) -> bool {
+	if address < text.start + 4 || address > text.end {
+		return false;
+	}
+	let read = |address: u32| unsafe { core::ptr::read_volatile(address as *const u16) };
+	let last = read(address - 2);
+	let first = read(address - 4);
+	// blx register
+	last & 0xFF87 == 0x4780
+		// bl immediate, 32 bit instruction
+		|| (first & 0xF800 == 0xF000 && last & 0xD000 == 0xD000)
+}
+
+/// Return addresses of the current call stack.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Backtrace {
+	/// address of the call instructions, the innermost first
+	addresses: [u32; MAX_FRAMES],
+	len: usize
+}
+
+impl Backtrace {
+	/// Collect the return addresses of the callers of the current function.
+	#[inline(always)]
+	pub(crate) fn capture() -> Self {
+		let fp: u32;
+		unsafe { core::arch::asm!("mov {}, r7", out(reg) fp, options(nomem, nostack)) };
+		Self::walk(fp)
+	}
+
+	fn walk(mut fp: u32) -> Self {
+		let text = text();
+		let stack = crate::stack::guard().end..crate::stack::stack_start();
+		let mut backtrace = Backtrace::default();
+		while backtrace.len < MAX_FRAMES
+			&& fp % 4 == 0
+			&& stack.contains(&fp)
+			&& stack.contains(&(fp + 4))
+		{
+			let next = unsafe { core::ptr::read_volatile(fp as *const u32) };
+			let lr = unsafe { core::ptr::read_volatile((fp + 4) as *const u32) };
+			// skip values, which are no return address into thumb code,
+			// like the magic value used by exception handlers to return
+			let address = lr & !1;
+			if lr & 1 == 1 && is_after_call(&text, address) {
+				// the call instruction is before the return address
+				backtrace.addresses[backtrace.len] = address - 2;
+				backtrace.len += 1;
+			}
+			// the stack grows downwards, so the frames of the callers are at higher addresses
+			if next <= fp {
+				break;
+			}
+			fp = next;
+		}
+		backtrace
+	}
+
+	pub(crate) fn addresses(&self) -> &[u32] {
+		&self.addresses[..self.len]
+	}
+}
+
+/// Format like `backtrace:` followed by lines of hex addresses,
+/// which can be resolved by `tools/symbolicate.sh`.
+impl Display for Backtrace {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "backtrace:")?;
+		for (i, address) in self.addresses().iter().enumerate() {
+			if i % PER_LINE == 0 {
+				writeln!(f)?;
+			} else {
+				write!(f, " ")?;
+			}
+			// the flash has a size of 512KiB, so 5 digits are enough
+			write!(f, "{address:05x}")?;
+		}
+		writeln!(f)
+	}
+}
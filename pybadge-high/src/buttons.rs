@@ -123,12 +123,19 @@ impl Buttons {
 		self.button_pressed(Button::Left)
 	}
 
+	/// Iterator over all currently pressed buttons.
+	pub fn pressed(&self) -> impl Iterator<Item = Button> + '_ {
+		(0..8)
+			.map(|i| Button::try_from(1 << i).unwrap())
+			.filter(|button| self.button_pressed(*button))
+	}
+
 	/// Iterator over alle [`Event`]s (Button status changes) occured between the last and penultimate update.
 	///
 	/// This does only include the changes of Buttons!
 	/// For example if a button was pressed at the penultimate update und is still pressed at the last update,
 	/// the iterator does skip the button.
-	pub fn events(&self) -> EventIter {
+	pub fn events(&self) -> EventIter<'_> {
 		EventIter {
 			postion: 0,
 			buttons: self,
@@ -174,5 +181,9 @@ impl Buttons {
 
 		self.last_state = self.current_state;
 		self.current_state = current;
+		#[cfg(feature = "usb_hid")]
+		if self.current_state != self.last_state {
+			crate::usb::report_buttons(self.pressed());
+		}
 	}
 }
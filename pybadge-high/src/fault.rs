@@ -0,0 +1,169 @@
+//! Handler of hard faults and unexpected exceptions, which show the reason at the bluescreen.
+//!
+//! The decoding of the fault status registers does not access the hardware.
+//! See <https://developer.arm.com/documentation/dui0553/latest/> chapter 4.3.10 and following.
+
+#[cfg(feature = "stack-guard")]
+use core::ops::Range;
+use core::{
+	fmt,
+	fmt::{Display, Formatter, Write}
+};
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::{exception, ExceptionFrame};
+
+/// Bits of the configurable fault status register and their description.
+const CFSR_BITS: [(u32, &str); 17] = [
+	// memory management fault
+	(0, "instr. access violation"),
+	(1, "data access violation"),
+	(3, "memory unstacking error"),
+	(4, "mem stacking, overflow?"),
+	(5, "mem fpu lazy state error"),
+	// bus fault
+	(8, "instruction bus error"),
+	(9, "precise data bus error"),
+	(10, "imprecise data bus error"),
+	(11, "bus unstacking error"),
+	(12, "bus stacking, overflow?"),
+	(13, "bus fpu lazy state error"),
+	// usage fault
+	(16, "undefined instruction"),
+	(17, "invalid state"),
+	(18, "invalid pc load"),
+	(19, "no coprocessor"),
+	(24, "unaligned access"),
+	(25, "divide by zero")
+];
+/// stacking for an exception entry has failed
+#[cfg(feature = "stack-guard")]
+const MSTKERR: u32 = 1 << 4;
+/// memory management fault address register is valid
+const MMARVALID: u32 = 1 << 7;
+/// bus fault address register is valid
+const BFARVALID: u32 = 1 << 15;
+
+/// Bits of the hard fault status register and their description.
+const HFSR_BITS: [(u32, &str); 3] = [
+	(1, "vector table read error"),
+	(30, "escalated fault"),
+	(31, "debug event")
+];
+
+/// Return true, if the fault was caused by an access to the `guard` region below the stack.
+#[cfg(feature = "stack-guard")]
+fn is_stack_overflow(cfsr: u32, mmfar: u32, guard: Range<u32>) -> bool {
+	cfsr & MSTKERR != 0 || (cfsr & MMARVALID != 0 && guard.contains(&mmfar))
+}
+
+/// Registers, which describe a hard fault.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct FaultReport {
+	/// program counter, address of the failed instruction
+	pub(crate) pc: u32,
+	/// link register, return address of the current function
+	pub(crate) lr: u32,
+	pub(crate) xpsr: u32,
+	/// configurable fault status register
+	pub(crate) cfsr: u32,
+	/// hard fault status register
+	pub(crate) hfsr: u32,
+	/// memory management fault address register
+	pub(crate) mmfar: u32,
+	/// bus fault address register
+	pub(crate) bfar: u32
+}
+
+impl FaultReport {
+	/// Read the fault status registers.
+	fn read(frame: &ExceptionFrame) -> Self {
+		let scb = unsafe { &*SCB::PTR };
+		FaultReport {
+			pc: frame.pc(),
+			lr: frame.lr(),
+			xpsr: frame.xpsr(),
+			cfsr: scb.cfsr.read(),
+			hfsr: scb.hfsr.read(),
+			mmfar: scb.mmfar.read(),
+			bfar: scb.bfar.read()
+		}
+	}
+
+	/// Human readable descriptions of the set fault status bits.
+	pub(crate) fn reasons(&self) -> impl Iterator<Item = &'static str> + '_ {
+		let cfsr = CFSR_BITS
+			.iter()
+			.filter(|(bit, _)| self.cfsr & 1 << bit != 0);
+		let hfsr = HFSR_BITS
+			.iter()
+			.filter(|(bit, _)| self.hfsr & 1 << bit != 0);
+		cfsr.chain(hfsr).map(|(_, reason)| *reason)
+	}
+}
+
+impl Display for FaultReport {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		writeln!(f, "hard fault")?;
+		writeln!(f, "PC   {:#010x}", self.pc)?;
+		writeln!(f, "LR   {:#010x}", self.lr)?;
+		writeln!(f, "xPSR {:#010x}", self.xpsr)?;
+		writeln!(f, "CFSR {:#010x}", self.cfsr)?;
+		writeln!(f, "HFSR {:#010x}", self.hfsr)?;
+		if self.cfsr & MMARVALID != 0 {
+			writeln!(f, "MMFAR {:#010x}", self.mmfar)?;
+		}
+		if self.cfsr & BFARVALID != 0 {
+			writeln!(f, "BFAR {:#010x}", self.bfar)?;
+		}
+		for reason in self.reasons() {
+			writeln!(f, "{reason}")?;
+		}
+		Ok(())
+	}
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+	// The stack pointer might be inside the guard region, which is not protected by the MPU
+	// at the hard fault handler. So large local variables must not be created,
+	// before the stack pointer is checked.
+	#[cfg(feature = "stack-guard")]
+	{
+		let scb = &*SCB::PTR;
+		if is_stack_overflow(scb.cfsr.read(), scb.mmfar.read(), crate::stack::guard()) {
+			crate::stack::overflow();
+		}
+		if crate::stack::remaining() < crate::stack::FAULT_STACK {
+			// the return addresses are lost, if the stack is discarded
+			crate::stack::reset(frame as *const ExceptionFrame as u32, report_discarded);
+		}
+	}
+	report(frame, true)
+}
+
+#[cfg(feature = "stack-guard")]
+extern "C" fn report_discarded(frame: u32) -> ! {
+	report(unsafe { &*(frame as *const ExceptionFrame) }, false)
+}
+
+#[inline(never)]
+#[cfg_attr(not(feature = "backtrace"), allow(unused_variables))]
+fn report(frame: &ExceptionFrame, with_backtrace: bool) -> ! {
+	#[cfg(feature = "backtrace")]
+	let backtrace = crate::backtrace::Backtrace::capture();
+	let fault = FaultReport::read(frame);
+	let mut report = heapless::String::<512>::new();
+	write!(report, "{fault}").ok();
+	#[cfg(feature = "backtrace")]
+	if with_backtrace {
+		write!(report, "{backtrace}").ok();
+	}
+	crate::fatal_error(&report)
+}
+
+#[exception]
+unsafe fn DefaultHandler(irqn: i16) {
+	let mut report = heapless::String::<64>::new();
+	write!(report, "unhandled exception\n\nIRQn {irqn}").ok();
+	crate::fatal_error(&report)
+}
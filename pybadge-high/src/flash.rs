@@ -2,25 +2,105 @@
 
 use crate::{prelude::*, Delay};
 use edgebadge::{hal, pac, pins};
+use embedded_storage::nor_flash::{
+	check_erase, check_read, check_write, ErrorType, MultiwriteNorFlash, NorFlash,
+	NorFlashError, NorFlashErrorKind, ReadNorFlash
+};
 use hal::qspi::{self, Command};
 use pac::{MCLK, QSPI};
 
+mod ram;
+pub use ram::RamFlash;
+
+/// Size of the flash in bytes.
+pub const CAPACITY: u32 = 2 * 1024 * 1024;
+/// Size of a page in bytes. A page is the biggest unit, witch can be written at once.
+pub const PAGE_SIZE: u32 = 256;
+/// Size of a sector in bytes. A sector is the smallest unit, witch can be erased.
+pub const SECTOR_SIZE: u32 = 4 * 1024;
+/// Size of a small block in bytes.
+pub const BLOCK_32K_SIZE: u32 = 32 * 1024;
+/// Size of a block in bytes.
+pub const BLOCK_64K_SIZE: u32 = 64 * 1024;
+
+/// JEDEC ID (manufacturer, memory type, capacity) of the GD25Q16C.
+pub const JEDEC_ID: [u8; 3] = [0xC8, 0x40, 0x15];
+
+// Maximum duration of the operations in milliseconds, with some margin to the datasheet.
+const PROGRAM_TIMEOUT_MS: u32 = 10;
+const WRITE_STATUS_TIMEOUT_MS: u32 = 100;
+const SECTOR_ERASE_TIMEOUT_MS: u32 = 1_000;
+const BLOCK_ERASE_TIMEOUT_MS: u32 = 4_000;
+const CHIP_ERASE_TIMEOUT_MS: u32 = 140_000;
+
+/// Errors, which can occur while accessing the flash.
+#[derive(Debug)]
+pub enum Error {
+	/// The qspi peripheral has rejected the command.
+	Qspi(qspi::Error),
+	/// The chip has returned an unexpected JEDEC ID.
+	/// So it is not a GD25Q16C or it does not respond at all.
+	WrongChip([u8; 3]),
+	/// The chip is still busy after the maximum duration of the operation.
+	Timeout,
+	/// The access goes beyond the end of the flash.
+	OutOfBounds,
+	/// The address is not aligned to the size of the erased region.
+	NotAligned,
+	/// The power was cut while writing.
+	/// Is only returned by [`RamFlash`] after calling [`RamFlash::cut_power_after()`].
+	PowerCut
+}
+
+impl From<qspi::Error> for Error {
+	fn from(error: qspi::Error) -> Self {
+		Error::Qspi(error)
+	}
+}
+
+impl From<NorFlashErrorKind> for Error {
+	fn from(kind: NorFlashErrorKind) -> Self {
+		match kind {
+			NorFlashErrorKind::NotAligned => Error::NotAligned,
+			_ => Error::OutOfBounds
+		}
+	}
+}
+
+impl NorFlashError for Error {
+	fn kind(&self) -> NorFlashErrorKind {
+		match self {
+			Error::NotAligned => NorFlashErrorKind::NotAligned,
+			Error::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+			_ => NorFlashErrorKind::Other
+		}
+	}
+}
+
 /// GD25Q16C 2M bytes flash storage.
 ///
-/// Existing out of 8k pages, witch 265Bytes each.
+/// Existing out of 8k pages, witch 256 bytes each.
 /// Page `i` starts at adress `i << 8` and ends with `(i << 8) + 255`.
 ///
+/// Bits can only be changed from `1` to `0` by writing.
+/// To set them back to `1` the flash must be erased.
+/// The smallest region, which can be erased is a sector of 4K bytes.
+///
+/// The flash does implement the [`NorFlash`] trait of [embedded-storage](https://crates.io/crates/embedded-storage),
+/// so it can be used with any crate build on top of it.
+///
 /// [DataSheet](https://cdn-shop.adafruit.com/product-files/4763/4763_GD25Q16CTIGR.pdf)
 pub struct Flash {
-	flash: edgebadge::qspi::Qspi<edgebadge::qspi::OneShot>
+	flash: qspi::Qspi<qspi::OneShot>
 }
+
 impl Flash {
 	pub(crate) fn init(
 		flash_pins: pins::QSPIFlash,
 		mlk: &mut MCLK,
 		qspi: QSPI,
 		delay: &mut Delay
-	) -> Self {
+	) -> Result<Self, Error> {
 		let mut flash = qspi::Qspi::new(
 			mlk,
 			qspi,
@@ -34,10 +114,20 @@ impl Flash {
 
 		// Startup delay. Can't find documented but Adafruit use 5ms
 		delay.delay_ms(15u8);
-		// Reset. It is recommended to check the BUSY(WIP?) bit and the SUS before reset
-		wait_ready(&mut flash);
-		flash.run_command(Command::EnableReset).unwrap();
-		flash.run_command(Command::Reset).unwrap();
+
+		// make sure we talk to the expected chip, before waiting for it
+		// and writing the status register
+		let mut jedec_id = [0_u8; 3];
+		flash.read_command(Command::ReadId, &mut jedec_id)?;
+		if jedec_id != JEDEC_ID {
+			return Err(Error::WrongChip(jedec_id));
+		}
+
+		// Reset. It is recommended to check the BUSY(WIP?) bit and the SUS before reset.
+		// A program or erase, which was interrupted by a reset of the microcontroller, may still run.
+		wait_ready(&mut flash, CHIP_ERASE_TIMEOUT_MS)?;
+		flash.run_command(Command::EnableReset)?;
+		flash.run_command(Command::Reset)?;
 		// tRST to reset. During this period, no command will be accepted
 		delay.delay_ms(15u8);
 
@@ -46,26 +136,36 @@ impl Flash {
 		flash.set_clk_divider(2);
 
 		// Enable Quad SPI mode. Requires write enable. Check WIP.
-		flash.run_command(Command::WriteEnable).unwrap();
-		flash
-			.write_command(Command::WriteStatus, &[0, 0x02])
-			.unwrap(); //set QE bit
-		wait_ready(&mut flash);
+		flash.run_command(Command::WriteEnable)?;
+		flash.write_command(Command::WriteStatus, &[0, 0x02])?; //set QE bit
+		wait_ready(&mut flash, WRITE_STATUS_TIMEOUT_MS)?;
+
+		Ok(Self { flash })
+	}
 
-		Self { flash }
+	/// Returns the JEDEC ID (manufacturer, memory type, capacity) of the chip.
+	pub fn jedec_id(&mut self) -> Result<[u8; 3], Error> {
+		let mut jedec_id = [0_u8; 3];
+		self.flash.read_command(Command::ReadId, &mut jedec_id)?;
+		Ok(jedec_id)
 	}
 
 	/// Read data from flash to `read_buf`.
 	/// The first byte addressed (`addr`)  can be at any location.
 	/// The address is automatically incremented to the next higher address after each byte of data is shifted out until `read_buf` is full.
-	pub fn read(&mut self, addr: u32, read_buf: &mut [u8]) {
+	///
+	/// # Errors
+	///
+	/// * [`OutOfBounds`](Error::OutOfBounds) - `read_buf` goes beyond the end of the flash.
+	pub fn read(&mut self, addr: u32, read_buf: &mut [u8]) -> Result<(), Error> {
+		check_bounds(addr, read_buf.len(), CAPACITY)?;
+		wait_ready(&mut self.flash, PROGRAM_TIMEOUT_MS)?;
 		// datasheet claims 6BH needs a single dummy byte, but doesnt work then
 		// adafruit uses 8, and the underlying implementation uses 8 atm as well
 		self.flash.read_memory(addr, read_buf);
+		Ok(())
 	}
 
-	// The following unwraps do only unwrap an result, witch check if I had use the right command.
-
 	/// Write bytes from `write_buf` to a page inside the flash.
 	///
 	/// If the 8 least significant address bits (`addr`) are not all zero, all transmitted data
@@ -77,31 +177,253 @@ impl Flash {
 	/// bytes are sent to device, they are correctly programmed at the
 	/// requested addresses without having any effects on the other bytes of
 	/// the same page.
-	pub fn write_page(&mut self, addr: u32, write_buf: &[u8]) {
-		self.flash.run_command(Command::WriteEnable).unwrap();
+	///
+	/// # Errors
+	///
+	/// * [`OutOfBounds`](Error::OutOfBounds) - `addr` is not inside the flash.
+	pub fn write_page(&mut self, addr: u32, write_buf: &[u8]) -> Result<(), Error> {
+		check_bounds(addr, 1, CAPACITY)?;
+		wait_ready(&mut self.flash, PROGRAM_TIMEOUT_MS)?;
+		self.flash.run_command(Command::WriteEnable)?;
 		self.flash.write_memory(addr, write_buf);
-		wait_ready(&mut self.flash);
+		wait_ready(&mut self.flash, PROGRAM_TIMEOUT_MS)
+	}
+
+	/// Erase the 4K bytes sector starting at `addr`.
+	///
+	/// # Errors
+	///
+	/// * [`NotAligned`](Error::NotAligned) - `addr` is not a multiple of [`SECTOR_SIZE`].
+	/// * [`OutOfBounds`](Error::OutOfBounds) - `addr` is not inside the flash.
+	pub fn erase_sector(&mut self, addr: u32) -> Result<(), Error> {
+		check_aligned(addr, SECTOR_SIZE, CAPACITY)?;
+		self.erase(Command::EraseSector, addr, SECTOR_ERASE_TIMEOUT_MS)
+	}
+
+	/// Erase the 32K bytes block starting at `addr`.
+	///
+	/// # Errors
+	///
+	/// * [`NotAligned`](Error::NotAligned) - `addr` is not a multiple of [`BLOCK_32K_SIZE`].
+	/// * [`OutOfBounds`](Error::OutOfBounds) - `addr` is not inside the flash.
+	pub fn erase_block_32k(&mut self, addr: u32) -> Result<(), Error> {
+		check_aligned(addr, BLOCK_32K_SIZE, CAPACITY)?;
+		// the hal does not know the 32K block erase command (52H),
+		// so erase the block sector by sector.
+		for sector in (addr..addr + BLOCK_32K_SIZE).step_by(SECTOR_SIZE as usize) {
+			self.erase(Command::EraseSector, sector, SECTOR_ERASE_TIMEOUT_MS)?;
+		}
+		Ok(())
+	}
+
+	/// Erase the 64K bytes block starting at `addr`.
+	///
+	/// # Errors
+	///
+	/// * [`NotAligned`](Error::NotAligned) - `addr` is not a multiple of [`BLOCK_64K_SIZE`].
+	/// * [`OutOfBounds`](Error::OutOfBounds) - `addr` is not inside the flash.
+	pub fn erase_block_64k(&mut self, addr: u32) -> Result<(), Error> {
+		check_aligned(addr, BLOCK_64K_SIZE, CAPACITY)?;
+		self.erase(Command::EraseBlock, addr, BLOCK_ERASE_TIMEOUT_MS)
 	}
 
 	///Erase the whole chip. Can take up to 140 seconds!
-	pub fn erase_chip(&mut self) {
-		// Chip Erase. Requires write enable. Check WIP.
-		self.flash.run_command(Command::WriteEnable).unwrap();
-		self.flash.erase_command(Command::EraseChip, 0x0).unwrap();
+	pub fn erase_chip(&mut self) -> Result<(), Error> {
 		// Worst case up to 140 seconds!
-		wait_ready(&mut self.flash);
+		self.erase(Command::EraseChip, 0x0, CHIP_ERASE_TIMEOUT_MS)
+	}
+
+	fn erase(&mut self, cmd: Command, addr: u32, timeout_ms: u32) -> Result<(), Error> {
+		// Erase requires write enable. Check WIP.
+		wait_ready(&mut self.flash, PROGRAM_TIMEOUT_MS)?;
+		self.flash.run_command(Command::WriteEnable)?;
+		self.flash.erase_command(cmd, addr)?;
+		wait_ready(&mut self.flash, timeout_ms)
 	}
 }
 
+impl ErrorType for Flash {
+	type Error = Error;
+}
+
+impl ReadNorFlash for Flash {
+	const READ_SIZE: usize = 1;
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+		check_read(self, offset, bytes.len())?;
+		Flash::read(self, offset, bytes)
+	}
+
+	fn capacity(&self) -> usize {
+		CAPACITY as usize
+	}
+}
+
+impl NorFlash for Flash {
+	const WRITE_SIZE: usize = 1;
+	const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+	fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+		check_erase(self, from, to)?;
+		let mut addr = from;
+		while addr < to {
+			// prefer the faster block erase, if the whole block should be erased
+			if addr % BLOCK_64K_SIZE == 0 && to - addr >= BLOCK_64K_SIZE {
+				self.erase_block_64k(addr)?;
+				addr += BLOCK_64K_SIZE;
+			} else {
+				self.erase_sector(addr)?;
+				addr += SECTOR_SIZE;
+			}
+		}
+		Ok(())
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+		check_write(self, offset, bytes.len())?;
+		for (addr, chunk) in PageChunks::new(offset, bytes) {
+			self.write_page(addr, chunk)?;
+		}
+		Ok(())
+	}
+}
+
+/// Writing only clears bits, so a already written region can be written again.
+impl MultiwriteNorFlash for Flash {}
+
+/// Iterator, which split a write at the page borders.
+///
+/// [`Flash::write_page()`] does wrap around at the end of a page.
+/// So data must be split in chunks, which does not cross a page border,
+/// before it can be written.
+pub(crate) struct PageChunks<'a> {
+	addr: u32,
+	bytes: &'a [u8]
+}
+
+impl<'a> PageChunks<'a> {
+	pub(crate) fn new(addr: u32, bytes: &'a [u8]) -> Self {
+		Self { addr, bytes }
+	}
+}
+
+impl<'a> Iterator for PageChunks<'a> {
+	type Item = (u32, &'a [u8]);
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.bytes.is_empty() {
+			return None;
+		}
+		let page_left = (PAGE_SIZE - self.addr % PAGE_SIZE) as usize;
+		let (chunk, rest) = self.bytes.split_at(page_left.min(self.bytes.len()));
+		let addr = self.addr;
+		self.addr += chunk.len() as u32;
+		self.bytes = rest;
+		Some((addr, chunk))
+	}
+}
+
+/// Check if `len` bytes starting at `addr` are inside a flash of `capacity` bytes.
+pub(crate) fn check_bounds(addr: u32, len: usize, capacity: u32) -> Result<(), Error> {
+	match addr.checked_add(len as u32) {
+		Some(end) if end <= capacity => Ok(()),
+		_ => Err(Error::OutOfBounds)
+	}
+}
+
+/// Check if `addr` is aligned to `size` and the region is inside a flash of `capacity` bytes.
+pub(crate) fn check_aligned(addr: u32, size: u32, capacity: u32) -> Result<(), Error> {
+	if addr % size != 0 {
+		return Err(Error::NotAligned);
+	}
+	check_bounds(addr, size as usize, capacity)
+}
+
 /// Wait for the write-in-progress and suspended write/erase.
-fn wait_ready(flash: &mut qspi::Qspi<qspi::OneShot>) {
-	while flash_status(flash, Command::ReadStatus) & 0x01 != 0 {}
-	while flash_status(flash, Command::ReadStatus2) & 0x80 != 0 {}
+///
+/// Returns [`Timeout`](Error::Timeout), if the chip is still busy after at least `timeout_ms`.
+fn wait_ready(
+	flash: &mut qspi::Qspi<qspi::OneShot>,
+	timeout_ms: u32
+) -> Result<(), Error> {
+	// poll every 10us, 120mhz = 1200 cycles
+	let mut polls = timeout_ms.saturating_mul(100);
+	while flash_status(flash, Command::ReadStatus)? & 0x01 != 0
+		|| flash_status(flash, Command::ReadStatus2)? & 0x80 != 0
+	{
+		if polls == 0 {
+			return Err(Error::Timeout);
+		}
+		polls -= 1;
+		cortex_m::asm::delay(1200);
+	}
+	Ok(())
 }
 
 /// Returns the contents of the status register indicated by cmd.
-fn flash_status(flash: &mut qspi::Qspi<qspi::OneShot>, cmd: Command) -> u8 {
+fn flash_status(
+	flash: &mut qspi::Qspi<qspi::OneShot>,
+	cmd: Command
+) -> Result<u8, Error> {
 	let mut out = [0u8; 1];
-	flash.read_command(cmd, &mut out).ok().unwrap();
-	out[0]
+	flash.read_command(cmd, &mut out)?;
+	Ok(out[0])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn chunks(addr: u32, len: usize) -> Vec<(u32, usize)> {
+		let bytes = vec![0; len];
+		PageChunks::new(addr, &bytes)
+			.map(|(addr, chunk)| (addr, chunk.len()))
+			.collect()
+	}
+
+	#[test]
+	fn page_chunks() {
+		assert_eq!(chunks(0, 0), []);
+		assert_eq!(chunks(10, 20), [(10, 20)]);
+		assert_eq!(chunks(0, 256), [(0, 256)]);
+		assert_eq!(chunks(250, 6), [(250, 6)]);
+		assert_eq!(chunks(250, 7), [(250, 6), (256, 1)]);
+		assert_eq!(chunks(200, 600), [
+			(200, 56),
+			(256, 256),
+			(512, 256),
+			(768, 32)
+		]);
+		assert_eq!(chunks(256, 512), [(256, 256), (512, 256)]);
+	}
+
+	#[test]
+	fn page_chunks_keep_bytes() {
+		let bytes: Vec<u8> = (0..1000_u32).map(|i| i as u8).collect();
+		let mut joined = Vec::new();
+		for (addr, chunk) in PageChunks::new(100, &bytes) {
+			assert_eq!(addr, 100 + joined.len() as u32);
+			assert!(addr % PAGE_SIZE + chunk.len() as u32 <= PAGE_SIZE);
+			joined.extend_from_slice(chunk);
+		}
+		assert_eq!(joined, bytes);
+	}
+
+	#[test]
+	fn bounds() {
+		assert!(check_bounds(0, 16, 16).is_ok());
+		assert!(matches!(check_bounds(1, 16, 16), Err(Error::OutOfBounds)));
+		assert!(matches!(
+			check_bounds(u32::MAX, 2, 16),
+			Err(Error::OutOfBounds)
+		));
+		assert!(check_aligned(SECTOR_SIZE, SECTOR_SIZE, 2 * SECTOR_SIZE).is_ok());
+		assert!(matches!(
+			check_aligned(1, SECTOR_SIZE, 2 * SECTOR_SIZE),
+			Err(Error::NotAligned)
+		));
+		assert!(matches!(
+			check_aligned(2 * SECTOR_SIZE, SECTOR_SIZE, 2 * SECTOR_SIZE),
+			Err(Error::OutOfBounds)
+		));
+	}
 }
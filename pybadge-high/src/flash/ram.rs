@@ -0,0 +1,246 @@
+use super::{check_aligned, check_bounds, Error, PageChunks, PAGE_SIZE, SECTOR_SIZE};
+use embedded_storage::nor_flash::{
+	check_erase, check_read, check_write, ErrorType, MultiwriteNorFlash, NorFlash,
+	ReadNorFlash
+};
+
+/// Flash storage emulated inside the RAM.
+///
+/// Behave like the [`Flash`](super::Flash):
+/// Erased bytes are `0xFF`, writing can only change bits from `1` to `0`
+/// and [`write_page()`](Self::write_page) does wrap around at the end of a page.
+/// This allows testing code, which use the flash, without real hardware
+/// and without wearing out the flash.
+///
+/// A power cut can be simulated with [`cut_power_after()`](Self::cut_power_after),
+/// to check if data stay consistent, when the power is lost in the middle of a write.
+///
+/// `N` is the size in bytes and should be a multiple of [`SECTOR_SIZE`].
+pub struct RamFlash<const N: usize> {
+	data: [u8; N],
+	/// number of bytes, which can still be written, before the power is cut
+	power_left: Option<usize>
+}
+
+impl<const N: usize> RamFlash<N> {
+	/// Create a new erased flash.
+	pub const fn new() -> Self {
+		Self {
+			data: [0xFF; N],
+			power_left: None
+		}
+	}
+
+	/// Create a flash with the given content.
+	pub const fn from_bytes(data: [u8; N]) -> Self {
+		Self {
+			data,
+			power_left: None
+		}
+	}
+
+	/// Returns the whole content of the flash.
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.data
+	}
+
+	/// Simulate a power cut after `bytes` further bytes were written.
+	///
+	/// The byte, at which the power is cut, and all following writes and erases are dropped
+	/// and return [`PowerCut`](Error::PowerCut), until [`restore_power()`](Self::restore_power) is called.
+	/// The data written before the cut stay at the flash, like at real hardware.
+	pub fn cut_power_after(&mut self, bytes: usize) {
+		self.power_left = Some(bytes);
+	}
+
+	/// Undo [`cut_power_after()`](Self::cut_power_after).
+	pub fn restore_power(&mut self) {
+		self.power_left = None;
+	}
+
+	/// Consume power for writing one byte. Return false, if the power is cut.
+	fn use_power(&mut self) -> bool {
+		match &mut self.power_left {
+			Some(0) => false,
+			Some(left) => {
+				*left -= 1;
+				true
+			},
+			None => true
+		}
+	}
+
+	/// Read data from flash to `read_buf`.
+	/// See [`Flash::read()`](super::Flash::read).
+	pub fn read(&mut self, addr: u32, read_buf: &mut [u8]) -> Result<(), Error> {
+		check_bounds(addr, read_buf.len(), N as u32)?;
+		let addr = addr as usize;
+		read_buf.copy_from_slice(&self.data[addr..addr + read_buf.len()]);
+		Ok(())
+	}
+
+	/// Write bytes from `write_buf` to a page inside the flash.
+	/// See [`Flash::write_page()`](super::Flash::write_page).
+	pub fn write_page(&mut self, addr: u32, write_buf: &[u8]) -> Result<(), Error> {
+		check_bounds(addr, 1, N as u32)?;
+		// only the last 256 bytes are programmed,
+		// at the position where they end up after wrapping around
+		let skipped = write_buf.len().saturating_sub(PAGE_SIZE as usize);
+		let write_buf = &write_buf[skipped..];
+		let start = addr + skipped as u32;
+		let page = addr - addr % PAGE_SIZE;
+		for (i, byte) in write_buf.iter().enumerate() {
+			if !self.use_power() {
+				return Err(Error::PowerCut);
+			}
+			let addr = page + (start + i as u32) % PAGE_SIZE;
+			self.data[addr as usize] &= byte;
+		}
+		Ok(())
+	}
+
+	/// Erase the 4K bytes sector starting at `addr`.
+	/// See [`Flash::erase_sector()`](super::Flash::erase_sector).
+	pub fn erase_sector(&mut self, addr: u32) -> Result<(), Error> {
+		check_aligned(addr, SECTOR_SIZE, N as u32)?;
+		if self.power_left == Some(0) {
+			return Err(Error::PowerCut);
+		}
+		let addr = addr as usize;
+		self.data[addr..addr + SECTOR_SIZE as usize].fill(0xFF);
+		Ok(())
+	}
+
+	///Erase the whole chip.
+	pub fn erase_chip(&mut self) -> Result<(), Error> {
+		if self.power_left == Some(0) {
+			return Err(Error::PowerCut);
+		}
+		self.data.fill(0xFF);
+		Ok(())
+	}
+}
+
+impl<const N: usize> Default for RamFlash<N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<const N: usize> ErrorType for RamFlash<N> {
+	type Error = Error;
+}
+
+impl<const N: usize> ReadNorFlash for RamFlash<N> {
+	const READ_SIZE: usize = 1;
+
+	fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+		check_read(self, offset, bytes.len())?;
+		RamFlash::read(self, offset, bytes)
+	}
+
+	fn capacity(&self) -> usize {
+		N
+	}
+}
+
+impl<const N: usize> NorFlash for RamFlash<N> {
+	const WRITE_SIZE: usize = 1;
+	const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+	fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+		check_erase(self, from, to)?;
+		for sector in (from..to).step_by(SECTOR_SIZE as usize) {
+			self.erase_sector(sector)?;
+		}
+		Ok(())
+	}
+
+	fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+		check_write(self, offset, bytes.len())?;
+		for (addr, chunk) in PageChunks::new(offset, bytes) {
+			self.write_page(addr, chunk)?;
+		}
+		Ok(())
+	}
+}
+
+impl<const N: usize> MultiwriteNorFlash for RamFlash<N> {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SECTOR_SIZE_USIZE: usize = SECTOR_SIZE as usize;
+
+	#[test]
+	fn write_page_wraps_around() {
+		let mut flash = RamFlash::<SECTOR_SIZE_USIZE>::new();
+		flash.write_page(250, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+		assert_eq!(&flash.as_bytes()[250..256], &[1, 2, 3, 4, 5, 6]);
+		assert_eq!(&flash.as_bytes()[0..2], &[7, 8]);
+		assert!(flash.as_bytes()[2..250].iter().all(|byte| *byte == 0xFF));
+		assert!(flash.as_bytes()[256..].iter().all(|byte| *byte == 0xFF));
+	}
+
+	#[test]
+	fn write_page_keeps_last_256_bytes() {
+		let data: Vec<u8> = (0..300_u32).map(|i| i as u8).collect();
+		for addr in [0, 10, 255, 256 + 44] {
+			let mut flash = RamFlash::<SECTOR_SIZE_USIZE>::new();
+			flash.write_page(addr, &data).unwrap();
+			let page = addr - addr % PAGE_SIZE;
+			for (i, byte) in data.iter().enumerate().skip(300 - 256) {
+				let position = page + (addr + i as u32) % PAGE_SIZE;
+				assert_eq!(
+					flash.as_bytes()[position as usize],
+					*byte,
+					"addr {addr} byte {i}"
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn write_only_clears_bits() {
+		let mut flash = RamFlash::<SECTOR_SIZE_USIZE>::new();
+		flash.write_page(0, &[0b1100]).unwrap();
+		flash.write_page(0, &[0b1010]).unwrap();
+		assert_eq!(flash.as_bytes()[0], 0b1000);
+		flash.erase_sector(0).unwrap();
+		assert_eq!(flash.as_bytes()[0], 0xFF);
+		assert!(matches!(flash.erase_sector(1), Err(Error::NotAligned)));
+		assert!(matches!(
+			flash.erase_sector(SECTOR_SIZE),
+			Err(Error::OutOfBounds)
+		));
+		assert!(matches!(
+			flash.write_page(SECTOR_SIZE, &[0]),
+			Err(Error::OutOfBounds)
+		));
+	}
+
+	#[test]
+	fn write_across_pages() {
+		let mut flash = RamFlash::<{ 2 * SECTOR_SIZE_USIZE }>::new();
+		let data: Vec<u8> = (0..600_u32).map(|i| i as u8).collect();
+		NorFlash::write(&mut flash, 200, &data).unwrap();
+		let mut read = vec![0; data.len()];
+		ReadNorFlash::read(&mut flash, 200, &mut read).unwrap();
+		assert_eq!(read, data);
+		assert!(flash.as_bytes()[..200].iter().all(|byte| *byte == 0xFF));
+		assert!(flash.as_bytes()[800..].iter().all(|byte| *byte == 0xFF));
+	}
+
+	#[test]
+	fn power_cut() {
+		let mut flash = RamFlash::<SECTOR_SIZE_USIZE>::new();
+		flash.cut_power_after(3);
+		assert!(matches!(flash.write_page(0, &[0; 5]), Err(Error::PowerCut)));
+		assert_eq!(&flash.as_bytes()[..5], &[0, 0, 0, 0xFF, 0xFF]);
+		assert!(matches!(flash.erase_sector(0), Err(Error::PowerCut)));
+		flash.restore_power();
+		flash.erase_sector(0).unwrap();
+		assert_eq!(flash.as_bytes()[0], 0xFF);
+	}
+}
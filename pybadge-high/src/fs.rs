@@ -0,0 +1,1324 @@
+//! FAT filesystem, compatible with the `CIRCUITPY` drive of CircuitPython.
+//!
+//! The pybadge is shipped with CircuitPython, which formats the external flash as FAT volume.
+//! So files copied to the drive before, like sprites or levels, can be read with this module.
+//! ```
+//! use pybadge_high::fs::{FileSystem, FlashDisk};
+//!
+//! let mut fs = FileSystem::mount(FlashDisk::new(pybadge.flash.unwrap())).unwrap();
+//! let mut buf = [0_u8; 64];
+//! let len = fs.read_file("/level1.txt", &mut buf).unwrap();
+//! fs.write_file("/score.txt", b"42").unwrap();
+//! ```
+//!
+//! Only FAT12 and FAT16 are supported, which are used by volumes up to 32M bytes.
+//! Long file names are not supported.
+//! Files with a long name can still be accessed by their short 8.3 name,
+//! which Windows shows with `dir /x`.
+
+use core::cmp::min;
+
+mod disk;
+pub use disk::{BlockDevice, FlashDisk, MemoryDisk, BLOCK_SIZE};
+
+const ENTRY_SIZE: usize = 32;
+const ENTRIES_PER_BLOCK: u32 = (BLOCK_SIZE / ENTRY_SIZE) as u32;
+
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// name\[0\] of a deleted entry
+const DELETED: u8 = 0xE5;
+/// flags at NTRes, used by Windows to show 8.3 names in lower case
+const LOWER_CASE_BASE: u8 = 0x08;
+const LOWER_CASE_EXT: u8 = 0x10;
+
+/// 1980-01-01 as FAT date, because the time is unknown
+const DEFAULT_DATE: u16 = (1 << 5) | 1;
+
+/// Errors, which can occur while accessing the [`FileSystem`].
+#[derive(Debug)]
+pub enum Error<E> {
+	/// Error of the underlying [`BlockDevice`].
+	Disk(E),
+	/// The device does not contain a FAT volume.
+	NotFormatted,
+	/// The volume uses FAT32 or a not supported sector size.
+	Unsupported,
+	/// The file or directory does not exist.
+	NotFound,
+	/// The file or directory does already exist.
+	AlreadyExists,
+	/// The name is not a valid 8.3 name.
+	InvalidName,
+	/// A directory was given, where a file was expected.
+	IsDirectory,
+	/// A file was given, where a directory was expected.
+	NotADirectory,
+	/// The directory can not be removed, because it is not empty.
+	DirectoryNotEmpty,
+	/// The root directory has no free entry left.
+	DirectoryFull,
+	/// The file is read-only.
+	ReadOnly,
+	/// There is no free space left at the volume.
+	DiskFull,
+	/// The filesystem is damaged.
+	Corrupted
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FatType {
+	Fat12,
+	Fat16
+}
+
+/// Start of a directory.
+#[derive(Clone, Copy)]
+enum DirStart {
+	Root,
+	Cluster(u32)
+}
+
+/// Position inside a directory.
+#[derive(Clone, Copy)]
+struct DirCursor {
+	start: DirStart,
+	cluster: u32,
+	index: u32
+}
+
+impl DirCursor {
+	fn new(start: DirStart) -> Self {
+		Self {
+			start,
+			cluster: match start {
+				DirStart::Root => 0,
+				DirStart::Cluster(cluster) => cluster
+			},
+			index: 0
+		}
+	}
+}
+
+/// Directory entry read from disk.
+#[derive(Clone, Copy)]
+struct RawEntry {
+	name: [u8; 11],
+	attr: u8,
+	nt_res: u8,
+	cluster: u32,
+	size: u32,
+	/// position of the entry at disk
+	block: u32,
+	offset: usize
+}
+
+impl RawEntry {
+	fn parse(bytes: &[u8], block: u32, offset: usize) -> Self {
+		let bytes = &bytes[offset..offset + ENTRY_SIZE];
+		Self {
+			name: bytes[0..11].try_into().unwrap(),
+			attr: bytes[11],
+			nt_res: bytes[12],
+			cluster: u16::from_le_bytes([bytes[26], bytes[27]]) as u32
+				| (u16::from_le_bytes([bytes[20], bytes[21]]) as u32) << 16,
+			size: u32::from_le_bytes(bytes[28..32].try_into().unwrap()),
+			block,
+			offset
+		}
+	}
+
+	fn is_dir(&self) -> bool {
+		self.attr & ATTR_DIRECTORY != 0
+	}
+
+	fn is_dot(&self) -> bool {
+		self.name[0] == b'.'
+	}
+
+	fn dir_start(&self) -> DirStart {
+		match self.cluster {
+			// ".." of a directory inside the root directory
+			0 => DirStart::Root,
+			cluster => DirStart::Cluster(cluster)
+		}
+	}
+}
+
+/// File or directory inside a directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirEntry {
+	name: heapless::String<12>,
+	is_dir: bool,
+	read_only: bool,
+	size: u32
+}
+
+impl DirEntry {
+	/// 8.3 name of the entry.
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn is_dir(&self) -> bool {
+		self.is_dir
+	}
+
+	pub fn is_read_only(&self) -> bool {
+		self.read_only
+	}
+
+	/// Size of the file in bytes. Is always 0 for directories.
+	pub fn size(&self) -> u32 {
+		self.size
+	}
+}
+
+/// Open directory. Created by [`FileSystem::read_dir()`].
+pub struct Dir {
+	cursor: DirCursor
+}
+
+/// Open file. Created by [`FileSystem::open()`] or [`FileSystem::create()`].
+///
+/// The file must be accessed with the [`FileSystem`] it was opened with
+/// and should be closed by [`FileSystem::close()`] after writing.
+pub struct File {
+	/// position of the directory entry at disk
+	entry_block: u32,
+	entry_offset: usize,
+	first_cluster: u32,
+	size: u32,
+	pos: u32,
+	read_only: bool,
+	/// cluster containing the `cluster_index`th cluster of the file, or 0 if unknown
+	cluster: u32,
+	cluster_index: u32
+}
+
+impl File {
+	/// Size of the file in bytes.
+	pub fn size(&self) -> u32 {
+		self.size
+	}
+
+	/// Current read/write position.
+	pub fn position(&self) -> u32 {
+		self.pos
+	}
+
+	/// Set the read/write position.
+	/// The position is limited to the size of the file.
+	pub fn seek(&mut self, pos: u32) {
+		self.pos = min(pos, self.size);
+	}
+}
+
+/// Layout of a FAT volume.
+#[derive(Clone, Copy)]
+struct Volume {
+	fat_type: FatType,
+	blocks_per_cluster: u32,
+	fat_start: u32,
+	fat_size: u32,
+	fat_count: u32,
+	root_start: u32,
+	root_entries: u32,
+	data_start: u32,
+	cluster_count: u32
+}
+
+impl Volume {
+	/// Read the layout of the FAT volume from the boot sector of `disk`.
+	fn read<D: BlockDevice>(disk: &mut D) -> Result<Self, Error<D::Error>> {
+		let mut buf = [0_u8; BLOCK_SIZE];
+		disk.read_block(0, &mut buf).map_err(Error::Disk)?;
+		let mut volume_start = 0;
+		if !is_boot_sector(&buf) {
+			// check for a partition table
+			let partition = &buf[446..462];
+			if buf[510..512] != [0x55, 0xAA] || partition[4] == 0 {
+				return Err(Error::NotFormatted);
+			}
+			volume_start = u32::from_le_bytes(partition[8..12].try_into().unwrap());
+			if volume_start >= disk.block_count() {
+				return Err(Error::NotFormatted);
+			}
+			disk.read_block(volume_start, &mut buf)
+				.map_err(Error::Disk)?;
+			if !is_boot_sector(&buf) {
+				return Err(Error::NotFormatted);
+			}
+		}
+
+		let bytes_per_sector = u16::from_le_bytes([buf[11], buf[12]]) as usize;
+		let blocks_per_cluster = buf[13] as u32;
+		let reserved = u16::from_le_bytes([buf[14], buf[15]]) as u32;
+		let fat_count = buf[16] as u32;
+		let root_entries = u16::from_le_bytes([buf[17], buf[18]]) as u32;
+		let total = match u16::from_le_bytes([buf[19], buf[20]]) {
+			0 => u32::from_le_bytes(buf[32..36].try_into().unwrap()),
+			total => total as u32
+		};
+		// FAT32 does store the size of the FAT at a different field
+		let fat_size = u16::from_le_bytes([buf[22], buf[23]]) as u32;
+		if bytes_per_sector != BLOCK_SIZE || fat_size == 0 {
+			return Err(Error::Unsupported);
+		}
+		if blocks_per_cluster == 0 || fat_count == 0 {
+			return Err(Error::NotFormatted);
+		}
+
+		let root_blocks = root_entries.div_ceil(ENTRIES_PER_BLOCK);
+		let meta_blocks = reserved + fat_count * fat_size + root_blocks;
+		let cluster_count = total.checked_sub(meta_blocks).ok_or(Error::NotFormatted)?
+			/ blocks_per_cluster;
+		let fat_type = match cluster_count {
+			0..=4084 => FatType::Fat12,
+			4085..=65524 => FatType::Fat16,
+			_ => return Err(Error::Unsupported)
+		};
+		let fat_start = volume_start + reserved;
+		let root_start = fat_start + fat_count * fat_size;
+		Ok(Self {
+			fat_type,
+			blocks_per_cluster,
+			fat_start,
+			fat_size,
+			fat_count,
+			root_start,
+			root_entries,
+			data_start: root_start + root_blocks,
+			cluster_count
+		})
+	}
+}
+
+/// FAT12/FAT16 filesystem.
+///
+/// See the [module documentation](self) for more details.
+pub struct FileSystem<D: BlockDevice> {
+	disk: D,
+	volume: Volume,
+	/// cluster to start the search for a free cluster
+	next_free: u32
+}
+
+impl<D: BlockDevice> FileSystem<D> {
+	/// Mount the FAT volume of the `disk`.
+	///
+	/// The volume can start at the first block (like CircuitPython does)
+	/// or be the first partition of a MBR partition table.
+	///
+	/// # Errors
+	///
+	/// * [`NotFormatted`](Error::NotFormatted) - there is no FAT volume on the disk.
+	///   A new volume can be created with [`format()`](Self::format).
+	/// * [`Unsupported`](Error::Unsupported) - the volume use FAT32.
+	pub fn mount(mut disk: D) -> Result<Self, Error<D::Error>> {
+		let volume = Volume::read(&mut disk)?;
+		Ok(Self {
+			disk,
+			volume,
+			next_free: 2
+		})
+	}
+
+	/// Mount the FAT volume of the `disk`, or create a new one with [`format()`](Self::format),
+	/// if the disk is not formatted yet.
+	pub fn mount_or_format(mut disk: D, label: &str) -> Result<Self, Error<D::Error>> {
+		match Volume::read(&mut disk) {
+			Ok(volume) => Ok(Self {
+				disk,
+				volume,
+				next_free: 2
+			}),
+			Err(Error::NotFormatted) => Self::format(disk, label),
+			Err(error) => Err(error)
+		}
+	}
+
+	/// Create a new empty FAT12 volume at the `disk`, like CircuitPython does, and mount it.
+	///
+	/// ⚠️ All data at the disk is lost.
+	///
+	/// # Errors
+	///
+	/// * [`Unsupported`](Error::Unsupported) - the disk is too large for FAT12 (more than 32M bytes).
+	pub fn format(mut disk: D, label: &str) -> Result<Self, Error<D::Error>> {
+		let total = disk.block_count();
+		let reserved = 1;
+		let fat_count = 1;
+		let root_entries = 512;
+		let root_blocks = root_entries / ENTRIES_PER_BLOCK;
+
+		// find the smallest cluster size, which keeps the cluster count inside the FAT12 limit
+		let mut blocks_per_cluster = 1;
+		let (fat_size, cluster_count) = loop {
+			let mut fat_size = 1;
+			let cluster_count = loop {
+				let cluster_count = total
+					.checked_sub(reserved + fat_count * fat_size + root_blocks)
+					.ok_or(Error::Unsupported)?
+					/ blocks_per_cluster;
+				// 1.5 bytes per cluster + 2 reserved entries
+				let needed = ((cluster_count + 2) * 3)
+					.div_ceil(2)
+					.div_ceil(BLOCK_SIZE as u32);
+				if needed <= fat_size {
+					break cluster_count;
+				}
+				fat_size = needed;
+			};
+			if cluster_count < 4085 {
+				break (fat_size, cluster_count);
+			}
+			if blocks_per_cluster >= 128 {
+				return Err(Error::Unsupported);
+			}
+			blocks_per_cluster *= 2;
+		};
+		if cluster_count == 0 {
+			return Err(Error::Unsupported);
+		}
+
+		// boot sector
+		let mut buf = [0_u8; BLOCK_SIZE];
+		buf[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+		buf[3..11].copy_from_slice(b"MSDOS5.0");
+		buf[11..13].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+		buf[13] = blocks_per_cluster as u8;
+		buf[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+		buf[16] = fat_count as u8;
+		buf[17..19].copy_from_slice(&(root_entries as u16).to_le_bytes());
+		if total < 0x10000 {
+			buf[19..21].copy_from_slice(&(total as u16).to_le_bytes());
+		} else {
+			buf[32..36].copy_from_slice(&total.to_le_bytes());
+		}
+		buf[21] = 0xF8; // media: fixed disk
+		buf[22..24].copy_from_slice(&(fat_size as u16).to_le_bytes());
+		buf[24..26].copy_from_slice(&1_u16.to_le_bytes()); // sectors per track
+		buf[26..28].copy_from_slice(&1_u16.to_le_bytes()); // number of heads
+		buf[36] = 0x80; // drive number
+		buf[38] = 0x29; // extended boot signature
+		buf[39..43].copy_from_slice(&0x1234_5678_u32.to_le_bytes()); // volume id
+		buf[43..54].copy_from_slice(&volume_label(label));
+		buf[54..62].copy_from_slice(b"FAT12   ");
+		buf[510..512].copy_from_slice(&[0x55, 0xAA]);
+		disk.write_block(0, &buf).map_err(Error::Disk)?;
+
+		// FAT and root directory
+		let empty = [0_u8; BLOCK_SIZE];
+		for block in reserved..reserved + fat_count * fat_size + root_blocks {
+			disk.write_block(block, &empty).map_err(Error::Disk)?;
+		}
+		// the first two entries are reserved: media type and end of chain
+		let mut buf = [0_u8; BLOCK_SIZE];
+		buf[0..3].copy_from_slice(&[0xF8, 0xFF, 0xFF]);
+		for fat in 0..fat_count {
+			disk.write_block(reserved + fat * fat_size, &buf)
+				.map_err(Error::Disk)?;
+		}
+		// volume label
+		let mut buf = [0_u8; BLOCK_SIZE];
+		buf[0..11].copy_from_slice(&volume_label(label));
+		buf[11] = ATTR_VOLUME_ID;
+		disk.write_block(reserved + fat_count * fat_size, &buf)
+			.map_err(Error::Disk)?;
+		disk.flush().map_err(Error::Disk)?;
+
+		Self::mount(disk)
+	}
+
+	/// Write cached data and release the underlying disk.
+	pub fn release(mut self) -> Result<D, Error<D::Error>> {
+		self.disk.flush().map_err(Error::Disk)?;
+		Ok(self.disk)
+	}
+
+	/// Returns the free space of the volume in bytes.
+	pub fn free_space(&mut self) -> Result<u32, Error<D::Error>> {
+		let mut free = 0;
+		for cluster in 2..self.volume.cluster_count + 2 {
+			if self.fat_entry(cluster)? == 0 {
+				free += 1;
+			}
+		}
+		Ok(free * self.cluster_bytes())
+	}
+
+	/// Open the directory at `path` for listing its content with [`next_entry()`](Self::next_entry).
+	pub fn read_dir(&mut self, path: &str) -> Result<Dir, Error<D::Error>> {
+		let start = self.lookup_dir(path)?;
+		Ok(Dir {
+			cursor: DirCursor::new(start)
+		})
+	}
+
+	/// Returns the next entry of the directory, or `None` if all entries were returned.
+	///
+	/// The `.` and `..` entries are skipped.
+	pub fn next_entry(
+		&mut self,
+		dir: &mut Dir
+	) -> Result<Option<DirEntry>, Error<D::Error>> {
+		let mut buf = [0_u8; BLOCK_SIZE];
+		while let Some((block, offset)) = self.next_slot(&mut dir.cursor)? {
+			self.read_block(block, &mut buf)?;
+			let entry = RawEntry::parse(&buf, block, offset);
+			match entry.name[0] {
+				0 => return Ok(None),
+				DELETED => continue,
+				_ => {}
+			}
+			if entry.attr & ATTR_VOLUME_ID != 0 || entry.is_dot() {
+				continue;
+			}
+			return Ok(Some(DirEntry {
+				name: display_name(&entry.name, entry.nt_res),
+				is_dir: entry.is_dir(),
+				read_only: entry.attr & ATTR_READ_ONLY != 0,
+				size: if entry.is_dir() { 0 } else { entry.size }
+			}));
+		}
+		Ok(None)
+	}
+
+	/// Returns true, if a file or directory exists at `path`.
+	pub fn exists(&mut self, path: &str) -> Result<bool, Error<D::Error>> {
+		match self.lookup(path) {
+			Ok(_) => Ok(true),
+			Err(Error::NotFound) => Ok(false),
+			Err(error) => Err(error)
+		}
+	}
+
+	/// Open the file at `path` for reading and writing.
+	pub fn open(&mut self, path: &str) -> Result<File, Error<D::Error>> {
+		let entry = self.lookup(path)?.ok_or(Error::IsDirectory)?;
+		if entry.is_dir() {
+			return Err(Error::IsDirectory);
+		}
+		Ok(File {
+			entry_block: entry.block,
+			entry_offset: entry.offset,
+			first_cluster: entry.cluster,
+			size: entry.size,
+			pos: 0,
+			read_only: entry.attr & ATTR_READ_ONLY != 0,
+			cluster: 0,
+			cluster_index: 0
+		})
+	}
+
+	/// Create a new empty file at `path` and open it.
+	/// If the file does already exist, its content is removed.
+	pub fn create(&mut self, path: &str) -> Result<File, Error<D::Error>> {
+		match self.lookup(path) {
+			Ok(_) => {
+				let mut file = self.open(path)?;
+				if file.read_only {
+					return Err(Error::ReadOnly);
+				}
+				self.free_chain(file.first_cluster)?;
+				file.first_cluster = 0;
+				file.size = 0;
+				self.update_entry(&file)?;
+				self.disk.flush().map_err(Error::Disk)?;
+				Ok(file)
+			},
+			Err(Error::NotFound) => {
+				let entry = self.create_entry(path, ATTR_ARCHIVE, 0)?;
+				self.disk.flush().map_err(Error::Disk)?;
+				Ok(File {
+					entry_block: entry.block,
+					entry_offset: entry.offset,
+					first_cluster: 0,
+					size: 0,
+					pos: 0,
+					read_only: false,
+					cluster: 0,
+					cluster_index: 0
+				})
+			},
+			Err(error) => Err(error)
+		}
+	}
+
+	/// Create a new directory at `path`.
+	pub fn create_dir(&mut self, path: &str) -> Result<(), Error<D::Error>> {
+		match self.lookup(path) {
+			Ok(_) => return Err(Error::AlreadyExists),
+			Err(Error::NotFound) => {},
+			Err(error) => return Err(error)
+		}
+		let (parent, _) = split_path(path);
+		let parent_cluster = match self.lookup_dir(parent)? {
+			DirStart::Root => 0,
+			DirStart::Cluster(cluster) => cluster
+		};
+		let cluster = self.alloc_cluster(None)?;
+		self.zero_cluster(cluster)?;
+		let mut buf = [0_u8; BLOCK_SIZE];
+		write_entry(
+			&mut buf[0..ENTRY_SIZE],
+			b".          ",
+			ATTR_DIRECTORY,
+			0,
+			cluster
+		);
+		write_entry(
+			&mut buf[ENTRY_SIZE..2 * ENTRY_SIZE],
+			b"..         ",
+			ATTR_DIRECTORY,
+			0,
+			parent_cluster
+		);
+		self.write_block(self.cluster_block(cluster), &buf)?;
+		if let Err(error) = self.create_entry(path, ATTR_DIRECTORY, cluster) {
+			self.free_chain(cluster)?;
+			return Err(error);
+		}
+		self.disk.flush().map_err(Error::Disk)
+	}
+
+	/// Remove the file or the empty directory at `path`.
+	pub fn remove(&mut self, path: &str) -> Result<(), Error<D::Error>> {
+		let (parent, _) = split_path(path);
+		let parent = self.lookup_dir(parent)?;
+		let name = path_name(path)?;
+		let (entry, mut cursor) = self.find(parent, &name)?.ok_or(Error::NotFound)?;
+		if entry.attr & ATTR_READ_ONLY != 0 {
+			return Err(Error::ReadOnly);
+		}
+		if entry.is_dir() {
+			let mut dir = Dir {
+				cursor: DirCursor::new(entry.dir_start())
+			};
+			if self.next_entry(&mut dir)?.is_some() {
+				return Err(Error::DirectoryNotEmpty);
+			}
+		}
+		// mark the entry and the long name entries before as deleted
+		let mut buf = [0_u8; BLOCK_SIZE];
+		while let Some((block, offset)) = self.next_slot(&mut cursor)? {
+			self.read_block(block, &mut buf)?;
+			buf[offset] = DELETED;
+			self.write_block(block, &buf)?;
+			if (block, offset) == (entry.block, entry.offset) {
+				break;
+			}
+		}
+		self.free_chain(entry.cluster)?;
+		self.disk.flush().map_err(Error::Disk)
+	}
+
+	/// Read bytes from the current position of `file` to `buf`
+	/// and return the number of read bytes.
+	/// Returns 0 at the end of the file.
+	pub fn read(
+		&mut self,
+		file: &mut File,
+		buf: &mut [u8]
+	) -> Result<usize, Error<D::Error>> {
+		let len = min(buf.len(), (file.size - file.pos) as usize);
+		let mut done = 0;
+		let mut block_buf = [0_u8; BLOCK_SIZE];
+		while done < len {
+			// the cluster chain is shorter than the file size
+			let cluster = self.file_cluster(file, false)?.ok_or(Error::Corrupted)?;
+			let offset_in_cluster = file.pos % self.cluster_bytes();
+			let block =
+				self.cluster_block(cluster) + offset_in_cluster / BLOCK_SIZE as u32;
+			let offset = (file.pos % BLOCK_SIZE as u32) as usize;
+			let count = min(len - done, BLOCK_SIZE - offset);
+			self.read_block(block, &mut block_buf)?;
+			buf[done..done + count].copy_from_slice(&block_buf[offset..offset + count]);
+			done += count;
+			file.pos += count as u32;
+		}
+		Ok(done)
+	}
+
+	/// Write `data` at the current position of `file`.
+	/// The file grows, if data is written beyond its end.
+	///
+	/// The data may be cached by the disk, until the file is [closed](Self::close)
+	/// or the filesystem is [flushed](Self::flush).
+	/// So many small writes do not erase the same sector of the flash again and again.
+	pub fn write(&mut self, file: &mut File, data: &[u8]) -> Result<(), Error<D::Error>> {
+		if file.read_only {
+			return Err(Error::ReadOnly);
+		}
+		let result = self.write_data(file, data);
+		// store the new size, even if not all data could be written
+		self.update_entry(file)?;
+		result
+	}
+
+	/// Close the `file` and write its cached data to the disk.
+	pub fn close(&mut self, _file: File) -> Result<(), Error<D::Error>> {
+		self.flush()
+	}
+
+	/// Read the file at `path` to `buf` and return the number of read bytes.
+	///
+	/// If the file is larger than `buf`, only the beginning of the file is read.
+	pub fn read_file(
+		&mut self,
+		path: &str,
+		buf: &mut [u8]
+	) -> Result<usize, Error<D::Error>> {
+		let mut file = self.open(path)?;
+		self.read(&mut file, buf)
+	}
+
+	/// Create the file at `path` with the content of `data`.
+	/// If the file does already exist, it is overwritten.
+	pub fn write_file(&mut self, path: &str, data: &[u8]) -> Result<(), Error<D::Error>> {
+		let mut file = self.create(path)?;
+		self.write(&mut file, data)?;
+		self.close(file)
+	}
+
+	/// Write all cached data to the disk.
+	pub fn flush(&mut self) -> Result<(), Error<D::Error>> {
+		self.disk.flush().map_err(Error::Disk)
+	}
+
+	fn read_block(
+		&mut self,
+		block: u32,
+		buf: &mut [u8; BLOCK_SIZE]
+	) -> Result<(), Error<D::Error>> {
+		self.disk.read_block(block, buf).map_err(Error::Disk)
+	}
+
+	fn write_block(
+		&mut self,
+		block: u32,
+		buf: &[u8; BLOCK_SIZE]
+	) -> Result<(), Error<D::Error>> {
+		self.disk.write_block(block, buf).map_err(Error::Disk)
+	}
+
+	fn cluster_bytes(&self) -> u32 {
+		self.volume.blocks_per_cluster * BLOCK_SIZE as u32
+	}
+
+	fn cluster_block(&self, cluster: u32) -> u32 {
+		self.volume.data_start + (cluster - 2) * self.volume.blocks_per_cluster
+	}
+
+	fn zero_cluster(&mut self, cluster: u32) -> Result<(), Error<D::Error>> {
+		let start = self.cluster_block(cluster);
+		for block in start..start + self.volume.blocks_per_cluster {
+			self.write_block(block, &[0; BLOCK_SIZE])?;
+		}
+		Ok(())
+	}
+
+	/// Check if `cluster` is a valid data cluster (not free and not the end of a chain).
+	fn is_data_cluster(&self, cluster: u32) -> bool {
+		(2..self.volume.cluster_count + 2).contains(&cluster)
+	}
+
+	/// Returns the FAT entry of `cluster`.
+	fn fat_entry(&mut self, cluster: u32) -> Result<u32, Error<D::Error>> {
+		let (offset, shift, mask) = self.fat_position(cluster);
+		let mut buf = [0_u8; BLOCK_SIZE];
+		let block = self.volume.fat_start + offset / BLOCK_SIZE as u32;
+		let offset = offset as usize % BLOCK_SIZE;
+		self.read_block(block, &mut buf)?;
+		let low = buf[offset];
+		// a FAT12 entry can be split between two blocks
+		let high = if offset + 1 < BLOCK_SIZE {
+			buf[offset + 1]
+		} else {
+			self.read_block(block + 1, &mut buf)?;
+			buf[0]
+		};
+		Ok((u16::from_le_bytes([low, high]) as u32 >> shift) & mask)
+	}
+
+	/// Set the FAT entry of `cluster` at all FATs.
+	fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), Error<D::Error>> {
+		let (offset, shift, mask) = self.fat_position(cluster);
+		let mut buf = [0_u8; BLOCK_SIZE];
+		for fat in 0..self.volume.fat_count {
+			let block = self.volume.fat_start
+				+ fat * self.volume.fat_size
+				+ offset / BLOCK_SIZE as u32;
+			let offset = offset as usize % BLOCK_SIZE;
+			self.read_block(block, &mut buf)?;
+			let split = offset + 1 >= BLOCK_SIZE;
+			let high = if split {
+				let mut next = [0_u8; BLOCK_SIZE];
+				self.read_block(block + 1, &mut next)?;
+				next[0]
+			} else {
+				buf[offset + 1]
+			};
+			let old = u16::from_le_bytes([buf[offset], high]) as u32;
+			let new = (old & !(mask << shift)) | ((value & mask) << shift);
+			let [low, high] = (new as u16).to_le_bytes();
+			buf[offset] = low;
+			if split {
+				self.write_block(block, &buf)?;
+				self.read_block(block + 1, &mut buf)?;
+				buf[0] = high;
+				self.write_block(block + 1, &buf)?;
+			} else {
+				buf[offset + 1] = high;
+				self.write_block(block, &buf)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Returns byte offset inside the FAT, shift and mask of the entry of `cluster`.
+	fn fat_position(&self, cluster: u32) -> (u32, u32, u32) {
+		match self.volume.fat_type {
+			FatType::Fat12 => (cluster + cluster / 2, (cluster & 1) * 4, 0xFFF),
+			FatType::Fat16 => (cluster * 2, 0, 0xFFFF)
+		}
+	}
+
+	fn end_of_chain(&self) -> u32 {
+		match self.volume.fat_type {
+			FatType::Fat12 => 0xFFF,
+			FatType::Fat16 => 0xFFFF
+		}
+	}
+
+	/// Allocate a free cluster and append it to the chain ending with `prev`.
+	fn alloc_cluster(&mut self, prev: Option<u32>) -> Result<u32, Error<D::Error>> {
+		for i in 0..self.volume.cluster_count {
+			let cluster = (self.next_free - 2 + i) % self.volume.cluster_count + 2;
+			if self.fat_entry(cluster)? == 0 {
+				self.set_fat_entry(cluster, self.end_of_chain())?;
+				if let Some(prev) = prev {
+					self.set_fat_entry(prev, cluster)?;
+				}
+				self.next_free = cluster;
+				return Ok(cluster);
+			}
+		}
+		Err(Error::DiskFull)
+	}
+
+	/// Free all clusters of the chain starting with `cluster`.
+	fn free_chain(&mut self, mut cluster: u32) -> Result<(), Error<D::Error>> {
+		while self.is_data_cluster(cluster) {
+			let next = self.fat_entry(cluster)?;
+			self.set_fat_entry(cluster, 0)?;
+			cluster = next;
+		}
+		Ok(())
+	}
+
+	/// Returns the cluster containing the current position of `file`.
+	/// If `allocate` is true, missing clusters are allocated.
+	fn file_cluster(
+		&mut self,
+		file: &mut File,
+		allocate: bool
+	) -> Result<Option<u32>, Error<D::Error>> {
+		let index = file.pos / self.cluster_bytes();
+		if file.first_cluster == 0 {
+			if !allocate {
+				return Ok(None);
+			}
+			file.first_cluster = self.alloc_cluster(None)?;
+		}
+		if file.cluster == 0 || file.cluster_index > index {
+			file.cluster = file.first_cluster;
+			file.cluster_index = 0;
+		}
+		while file.cluster_index < index {
+			let next = self.fat_entry(file.cluster)?;
+			file.cluster = if self.is_data_cluster(next) {
+				next
+			} else if allocate {
+				self.alloc_cluster(Some(file.cluster))?
+			} else {
+				return Ok(None);
+			};
+			file.cluster_index += 1;
+		}
+		Ok(Some(file.cluster))
+	}
+
+	fn write_data(
+		&mut self,
+		file: &mut File,
+		data: &[u8]
+	) -> Result<(), Error<D::Error>> {
+		let mut done = 0;
+		let mut block_buf = [0_u8; BLOCK_SIZE];
+		while done < data.len() {
+			let cluster = self.file_cluster(file, true)?.ok_or(Error::DiskFull)?;
+			let offset_in_cluster = file.pos % self.cluster_bytes();
+			let block =
+				self.cluster_block(cluster) + offset_in_cluster / BLOCK_SIZE as u32;
+			let offset = (file.pos % BLOCK_SIZE as u32) as usize;
+			let count = min(data.len() - done, BLOCK_SIZE - offset);
+			if count < BLOCK_SIZE {
+				self.read_block(block, &mut block_buf)?;
+			}
+			block_buf[offset..offset + count].copy_from_slice(&data[done..done + count]);
+			self.write_block(block, &block_buf)?;
+			done += count;
+			file.pos += count as u32;
+			file.size = file.size.max(file.pos);
+		}
+		Ok(())
+	}
+
+	/// Store size and first cluster of `file` at its directory entry.
+	fn update_entry(&mut self, file: &File) -> Result<(), Error<D::Error>> {
+		let mut buf = [0_u8; BLOCK_SIZE];
+		self.read_block(file.entry_block, &mut buf)?;
+		let entry = &mut buf[file.entry_offset..file.entry_offset + ENTRY_SIZE];
+		entry[20..22].copy_from_slice(&((file.first_cluster >> 16) as u16).to_le_bytes());
+		entry[26..28].copy_from_slice(&(file.first_cluster as u16).to_le_bytes());
+		entry[28..32].copy_from_slice(&file.size.to_le_bytes());
+		self.write_block(file.entry_block, &buf)
+	}
+
+	/// Returns block and offset of the next entry of the directory
+	/// or `None` at the end of the directory.
+	fn next_slot(
+		&mut self,
+		cursor: &mut DirCursor
+	) -> Result<Option<(u32, usize)>, Error<D::Error>> {
+		let index = cursor.index;
+		let block = match cursor.start {
+			DirStart::Root => {
+				if index >= self.volume.root_entries {
+					return Ok(None);
+				}
+				self.volume.root_start + index / ENTRIES_PER_BLOCK
+			},
+			DirStart::Cluster(_) => {
+				let entries_per_cluster =
+					ENTRIES_PER_BLOCK * self.volume.blocks_per_cluster;
+				if index > 0 && index % entries_per_cluster == 0 {
+					let next = self.fat_entry(cursor.cluster)?;
+					if !self.is_data_cluster(next) {
+						return Ok(None);
+					}
+					cursor.cluster = next;
+				}
+				self.cluster_block(cursor.cluster)
+					+ (index % entries_per_cluster) / ENTRIES_PER_BLOCK
+			}
+		};
+		cursor.index += 1;
+		Ok(Some((
+			block,
+			(index % ENTRIES_PER_BLOCK) as usize * ENTRY_SIZE
+		)))
+	}
+
+	/// Search the entry with `name` inside the directory.
+	///
+	/// Returns the entry and a cursor pointing to the entry,
+	/// or to its long name entries, if it has some.
+	fn find(
+		&mut self,
+		dir: DirStart,
+		name: &[u8; 11]
+	) -> Result<Option<(RawEntry, DirCursor)>, Error<D::Error>> {
+		let mut cursor = DirCursor::new(dir);
+		let mut buf = [0_u8; BLOCK_SIZE];
+		let mut long_name = None;
+		loop {
+			let before = cursor;
+			let Some((block, offset)) = self.next_slot(&mut cursor)? else {
+				return Ok(None);
+			};
+			self.read_block(block, &mut buf)?;
+			let entry = RawEntry::parse(&buf, block, offset);
+			match entry.name[0] {
+				0 => return Ok(None),
+				DELETED => {
+					long_name = None;
+					continue;
+				},
+				_ => {}
+			}
+			if entry.attr & ATTR_LONG_NAME == ATTR_LONG_NAME {
+				long_name.get_or_insert(before);
+				continue;
+			}
+			if entry.attr & ATTR_VOLUME_ID == 0 && entry.name == *name {
+				return Ok(Some((entry, long_name.unwrap_or(before))));
+			}
+			long_name = None;
+		}
+	}
+
+	/// Returns the entry at `path`, or `None` for the root directory.
+	fn lookup(&mut self, path: &str) -> Result<Option<RawEntry>, Error<D::Error>> {
+		let mut dir = DirStart::Root;
+		let mut result: Option<RawEntry> = None;
+		for component in path.split('/').filter(|component| !component.is_empty()) {
+			if let Some(entry) = result {
+				if !entry.is_dir() {
+					return Err(Error::NotADirectory);
+				}
+				dir = entry.dir_start();
+			}
+			if component == "." {
+				continue;
+			}
+			let name = short_name(component).ok_or(Error::InvalidName)?;
+			match (dir, component) {
+				// the root directory has no ".." entry
+				(DirStart::Root, "..") => result = None,
+				_ => result = Some(self.find(dir, &name)?.ok_or(Error::NotFound)?.0)
+			}
+		}
+		Ok(result)
+	}
+
+	/// Returns the start of the directory at `path`.
+	fn lookup_dir(&mut self, path: &str) -> Result<DirStart, Error<D::Error>> {
+		match self.lookup(path)? {
+			None => Ok(DirStart::Root),
+			Some(entry) if entry.is_dir() => Ok(entry.dir_start()),
+			Some(_) => Err(Error::NotADirectory)
+		}
+	}
+
+	/// Create a new directory entry at `path`.
+	fn create_entry(
+		&mut self,
+		path: &str,
+		attr: u8,
+		cluster: u32
+	) -> Result<RawEntry, Error<D::Error>> {
+		let (parent, _) = split_path(path);
+		let parent = self.lookup_dir(parent)?;
+		let name = path_name(path)?;
+		let nt_res = lower_case_flags(split_path(path).1);
+
+		// search a free entry
+		let mut cursor = DirCursor::new(parent);
+		let mut buf = [0_u8; BLOCK_SIZE];
+		let (block, offset) = loop {
+			match self.next_slot(&mut cursor)? {
+				Some((block, offset)) => {
+					self.read_block(block, &mut buf)?;
+					if buf[offset] == 0 || buf[offset] == DELETED {
+						break (block, offset);
+					}
+				},
+				None => match parent {
+					DirStart::Root => return Err(Error::DirectoryFull),
+					DirStart::Cluster(_) => {
+						// extend the directory by a new cluster
+						let new = self.alloc_cluster(Some(cursor.cluster))?;
+						self.zero_cluster(new)?;
+						self.read_block(self.cluster_block(new), &mut buf)?;
+						break (self.cluster_block(new), 0);
+					}
+				}
+			}
+		};
+		write_entry(
+			&mut buf[offset..offset + ENTRY_SIZE],
+			&name,
+			attr,
+			nt_res,
+			cluster
+		);
+		self.write_block(block, &buf)?;
+		Ok(RawEntry::parse(&buf, block, offset))
+	}
+}
+
+fn is_boot_sector(buf: &[u8; BLOCK_SIZE]) -> bool {
+	(buf[0] == 0xEB || buf[0] == 0xE9)
+		&& buf[510..512] == [0x55, 0xAA]
+		&& u16::from_le_bytes([buf[11], buf[12]]) != 0
+}
+
+fn write_entry(entry: &mut [u8], name: &[u8; 11], attr: u8, nt_res: u8, cluster: u32) {
+	entry.fill(0);
+	entry[0..11].copy_from_slice(name);
+	entry[11] = attr;
+	entry[12] = nt_res;
+	// creation, access and modification date
+	for date in [16, 18, 24] {
+		entry[date..date + 2].copy_from_slice(&DEFAULT_DATE.to_le_bytes());
+	}
+	entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+	entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+}
+
+/// Split `path` into the path of the parent directory and the name.
+fn split_path(path: &str) -> (&str, &str) {
+	let path = path.trim_end_matches('/');
+	match path.rfind('/') {
+		Some(pos) => (&path[..pos], &path[pos + 1..]),
+		None => ("", path)
+	}
+}
+
+/// Returns the 8.3 name of the last component of `path`.
+fn path_name<E>(path: &str) -> Result<[u8; 11], Error<E>> {
+	let (_, name) = split_path(path);
+	if name == "." || name == ".." {
+		return Err(Error::InvalidName);
+	}
+	short_name(name).ok_or(Error::InvalidName)
+}
+
+/// Convert `name` to a 8.3 name, like it is stored at the directory entry.
+fn short_name(name: &str) -> Option<[u8; 11]> {
+	let mut short = [b' '; 11];
+	match name {
+		"." => short[0] = b'.',
+		".." => short[..2].copy_from_slice(b".."),
+		_ => {
+			let (base, ext) = match name.rfind('.') {
+				Some(pos) => (&name[..pos], &name[pos + 1..]),
+				None => (name, "")
+			};
+			if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+				return None;
+			}
+			let (short_base, short_ext) = short.split_at_mut(8);
+			for (short, char) in short_base
+				.iter_mut()
+				.zip(base.bytes())
+				.chain(short_ext.iter_mut().zip(ext.bytes()))
+			{
+				if !(char.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&char))
+				{
+					return None;
+				}
+				*short = char.to_ascii_uppercase();
+			}
+		}
+	}
+	Some(short)
+}
+
+/// NTRes flags to show `name` in lower case, if base or extension are lower case.
+fn lower_case_flags(name: &str) -> u8 {
+	let (base, ext) = match name.rfind('.') {
+		Some(pos) => (&name[..pos], &name[pos + 1..]),
+		None => (name, "")
+	};
+	let is_lower = |part: &str| {
+		part.bytes().any(|char| char.is_ascii_lowercase())
+			&& !part.bytes().any(|char| char.is_ascii_uppercase())
+	};
+	let mut flags = 0;
+	if is_lower(base) {
+		flags |= LOWER_CASE_BASE;
+	}
+	if is_lower(ext) {
+		flags |= LOWER_CASE_EXT;
+	}
+	flags
+}
+
+/// Convert a 8.3 name from a directory entry to a readable name, like `CODE.PY`.
+fn display_name(name: &[u8; 11], nt_res: u8) -> heapless::String<12> {
+	let mut display = heapless::String::new();
+	let convert = |char: u8, lower: bool| {
+		let char = if lower {
+			char.to_ascii_lowercase()
+		} else {
+			char
+		};
+		// 0x05 is used for names starting with 0xE5
+		char::from(if char == 0x05 { 0xE5 } else { char })
+	};
+	for char in name[..8].iter().take_while(|char| **char != b' ') {
+		display
+			.push(convert(*char, nt_res & LOWER_CASE_BASE != 0))
+			.ok();
+	}
+	if name[8] != b' ' {
+		display.push('.').ok();
+		for char in name[8..].iter().take_while(|char| **char != b' ') {
+			display
+				.push(convert(*char, nt_res & LOWER_CASE_EXT != 0))
+				.ok();
+		}
+	}
+	display
+}
+
+/// Label of the volume, padded with spaces.
+fn volume_label(label: &str) -> [u8; 11] {
+	let mut padded = [b' '; 11];
+	for (padded, char) in padded.iter_mut().zip(label.bytes()) {
+		*padded = char.to_ascii_uppercase();
+	}
+	padded
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// 1M bytes FAT12 volume with the label `CIRCUITPY`, formatted by the `fatfs` crate.
+	///
+	/// Only the boot sector, the FATs and the start of the root directory are stored,
+	/// the rest of the volume is zero.
+	fn fat12_image() -> Vec<u8> {
+		let mut image = include_bytes!("fs/fat12.img").to_vec();
+		image.resize(1024 * 1024, 0);
+		image
+	}
+
+	fn list<D: BlockDevice>(fs: &mut FileSystem<D>, path: &str) -> Vec<String> {
+		let mut dir = fs.read_dir(path).unwrap();
+		let mut names = Vec::new();
+		while let Some(entry) = fs.next_entry(&mut dir).unwrap() {
+			names.push(entry.name().to_owned());
+		}
+		names.sort();
+		names
+	}
+
+	/// [`MemoryDisk`], which counts the calls of [`flush()`](BlockDevice::flush).
+	struct CountingDisk<'a> {
+		disk: MemoryDisk<'a>,
+		flushes: usize
+	}
+
+	impl BlockDevice for CountingDisk<'_> {
+		type Error = core::convert::Infallible;
+
+		fn read_block(
+			&mut self,
+			index: u32,
+			buf: &mut [u8; BLOCK_SIZE]
+		) -> Result<(), Self::Error> {
+			self.disk.read_block(index, buf)
+		}
+
+		fn write_block(
+			&mut self,
+			index: u32,
+			buf: &[u8; BLOCK_SIZE]
+		) -> Result<(), Self::Error> {
+			self.disk.write_block(index, buf)
+		}
+
+		fn block_count(&self) -> u32 {
+			self.disk.block_count()
+		}
+
+		fn flush(&mut self) -> Result<(), Self::Error> {
+			self.flushes += 1;
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn existing_volume() {
+		let mut image = fat12_image();
+		let mut fs = FileSystem::mount(MemoryDisk::new(&mut image)).unwrap();
+		// the volume label is not listed
+		assert!(list(&mut fs, "/").is_empty());
+		let free = fs.free_space().unwrap();
+
+		fs.write_file("/code.py", b"print('hello')").unwrap();
+		fs.create_dir("/lib").unwrap();
+		let data: Vec<u8> = (0..5000_u32).map(|i| (i * 7) as u8).collect();
+		fs.write_file("/lib/data.bin", &data).unwrap();
+		// enough files to extend the directory by further clusters
+		for i in 0..40 {
+			fs.write_file(&format!("/lib/f{i}.txt"), i.to_string().as_bytes())
+				.unwrap();
+		}
+		assert_eq!(list(&mut fs, "/"), ["code.py", "lib"]);
+		assert_eq!(list(&mut fs, "/lib").len(), 41);
+
+		// append
+		let mut file = fs.open("/lib/data.bin").unwrap();
+		file.seek(file.size());
+		fs.write(&mut file, &data).unwrap();
+		fs.close(file).unwrap();
+
+		let mut fs = FileSystem::mount(fs.release().unwrap()).unwrap();
+		let mut buf = [0_u8; 64];
+		let len = fs.read_file("/CODE.PY", &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"print('hello')");
+		let len = fs.read_file("/lib/../lib/./f39.txt", &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"39");
+		let mut file = fs.open("/lib/data.bin").unwrap();
+		assert_eq!(file.size(), 10000);
+		let mut read = vec![0_u8; 12000];
+		assert_eq!(fs.read(&mut file, &mut read).unwrap(), 10000);
+		assert_eq!(&read[..5000], data);
+		assert_eq!(&read[5000..10000], data);
+
+		assert!(matches!(fs.remove("/lib"), Err(Error::DirectoryNotEmpty)));
+		for name in list(&mut fs, "/lib") {
+			fs.remove(&format!("/lib/{name}")).unwrap();
+		}
+		fs.remove("/lib").unwrap();
+		fs.remove("/code.py").unwrap();
+		assert!(!fs.exists("/code.py").unwrap());
+		assert!(list(&mut fs, "/").is_empty());
+		assert_eq!(fs.free_space().unwrap(), free);
+	}
+
+	#[test]
+	fn format_fresh_volume() {
+		let mut image = vec![0xFF_u8; 1024 * 1024];
+		assert!(matches!(
+			FileSystem::mount(MemoryDisk::new(&mut image)),
+			Err(Error::NotFormatted)
+		));
+		let mut fs =
+			FileSystem::mount_or_format(MemoryDisk::new(&mut image), "CIRCUITPY")
+				.unwrap();
+		assert!(list(&mut fs, "/").is_empty());
+		fs.write_file("/score.txt", b"42").unwrap();
+		drop(fs);
+		assert_eq!(&image[43..54], b"CIRCUITPY  ");
+		assert_eq!(&image[54..62], b"FAT12   ");
+
+		// the existing volume is kept
+		let mut fs =
+			FileSystem::mount_or_format(MemoryDisk::new(&mut image), "OTHER").unwrap();
+		let mut buf = [0_u8; 8];
+		let len = fs.read_file("/score.txt", &mut buf).unwrap();
+		assert_eq!(&buf[..len], b"42");
+	}
+
+	#[test]
+	fn write_is_flushed_at_close() {
+		let mut image = vec![0_u8; 1024 * 1024];
+		FileSystem::format(MemoryDisk::new(&mut image), "CIRCUITPY").unwrap();
+		let disk = CountingDisk {
+			disk: MemoryDisk::new(&mut image),
+			flushes: 0
+		};
+		let mut fs = FileSystem::mount(disk).unwrap();
+		let mut file = fs.create("/log.txt").unwrap();
+		let flushes = fs.disk.flushes;
+		for _ in 0..100 {
+			fs.write(&mut file, b"line\n").unwrap();
+		}
+		assert_eq!(fs.disk.flushes, flushes);
+		fs.close(file).unwrap();
+		assert_eq!(fs.disk.flushes, flushes + 1);
+		assert_eq!(fs.open("/log.txt").unwrap().size(), 500);
+	}
+}
@@ -0,0 +1,190 @@
+use core::{convert::Infallible, fmt::Debug};
+use embedded_storage::nor_flash::NorFlash;
+
+/// Size of a block (sector of the filesystem) in bytes.
+pub const BLOCK_SIZE: usize = 512;
+/// Size of the erase cache of the [`FlashDisk`].
+const CACHE_SIZE: usize = 4096;
+
+/// Storage, which is accessed in blocks of [`BLOCK_SIZE`] bytes.
+pub trait BlockDevice {
+	type Error: Debug;
+
+	/// Read the block with `index` to `buf`.
+	fn read_block(
+		&mut self,
+		index: u32,
+		buf: &mut [u8; BLOCK_SIZE]
+	) -> Result<(), Self::Error>;
+
+	/// Write `buf` to the block with `index`.
+	///
+	/// The data may be cached until [`flush()`](BlockDevice::flush) is called.
+	fn write_block(
+		&mut self,
+		index: u32,
+		buf: &[u8; BLOCK_SIZE]
+	) -> Result<(), Self::Error>;
+
+	/// Returns the number of blocks of the device.
+	fn block_count(&self) -> u32;
+
+	/// Write all cached data to the device.
+	fn flush(&mut self) -> Result<(), Self::Error> {
+		Ok(())
+	}
+}
+
+impl<T: BlockDevice + ?Sized> BlockDevice for &mut T {
+	type Error = T::Error;
+
+	fn read_block(
+		&mut self,
+		index: u32,
+		buf: &mut [u8; BLOCK_SIZE]
+	) -> Result<(), Self::Error> {
+		T::read_block(self, index, buf)
+	}
+
+	fn write_block(
+		&mut self,
+		index: u32,
+		buf: &[u8; BLOCK_SIZE]
+	) -> Result<(), Self::Error> {
+		T::write_block(self, index, buf)
+	}
+
+	fn block_count(&self) -> u32 {
+		T::block_count(self)
+	}
+
+	fn flush(&mut self) -> Result<(), Self::Error> {
+		T::flush(self)
+	}
+}
+
+/// [`BlockDevice`] on top of a [`NorFlash`], like the [`Flash`](crate::Flash).
+///
+/// A flash can only be erased in sectors of 4K bytes.
+/// So the disk keeps one sector in a cache and writes it back,
+/// when a block of another sector is written or [`flush()`](BlockDevice::flush) is called.
+/// This is the same way CircuitPython does access the flash.
+///
+/// [`NorFlash::ERASE_SIZE`] must be a divisor of 4096.
+pub struct FlashDisk<S: NorFlash> {
+	storage: S,
+	cache: [u8; CACHE_SIZE],
+	/// address of the cached sector
+	cached: Option<u32>,
+	dirty: bool
+}
+
+impl<S: NorFlash> FlashDisk<S> {
+	pub fn new(storage: S) -> Self {
+		debug_assert!(CACHE_SIZE % S::ERASE_SIZE == 0);
+		Self {
+			storage,
+			cache: [0; CACHE_SIZE],
+			cached: None,
+			dirty: false
+		}
+	}
+
+	/// Write cached data and release the underlying storage.
+	pub fn release(mut self) -> Result<S, S::Error> {
+		self.flush()?;
+		Ok(self.storage)
+	}
+}
+
+impl<S: NorFlash> BlockDevice for FlashDisk<S> {
+	type Error = S::Error;
+
+	fn read_block(
+		&mut self,
+		index: u32,
+		buf: &mut [u8; BLOCK_SIZE]
+	) -> Result<(), Self::Error> {
+		let addr = index * BLOCK_SIZE as u32;
+		let sector = addr - addr % CACHE_SIZE as u32;
+		if self.cached == Some(sector) {
+			let offset = (addr - sector) as usize;
+			buf.copy_from_slice(&self.cache[offset..offset + BLOCK_SIZE]);
+			return Ok(());
+		}
+		self.storage.read(addr, buf)
+	}
+
+	fn write_block(
+		&mut self,
+		index: u32,
+		buf: &[u8; BLOCK_SIZE]
+	) -> Result<(), Self::Error> {
+		let addr = index * BLOCK_SIZE as u32;
+		let sector = addr - addr % CACHE_SIZE as u32;
+		if self.cached != Some(sector) {
+			self.flush()?;
+			self.storage.read(sector, &mut self.cache)?;
+			self.cached = Some(sector);
+		}
+		let offset = (addr - sector) as usize;
+		let block = &mut self.cache[offset..offset + BLOCK_SIZE];
+		if block != buf {
+			block.copy_from_slice(buf);
+			self.dirty = true;
+		}
+		Ok(())
+	}
+
+	fn block_count(&self) -> u32 {
+		(self.storage.capacity() / BLOCK_SIZE) as u32
+	}
+
+	fn flush(&mut self) -> Result<(), Self::Error> {
+		if let (Some(sector), true) = (self.cached, self.dirty) {
+			self.storage.erase(sector, sector + CACHE_SIZE as u32)?;
+			self.storage.write(sector, &self.cache)?;
+			self.dirty = false;
+		}
+		Ok(())
+	}
+}
+
+/// [`BlockDevice`] inside a byte slice, like an image file loaded to memory.
+pub struct MemoryDisk<'a> {
+	data: &'a mut [u8]
+}
+
+impl<'a> MemoryDisk<'a> {
+	pub fn new(data: &'a mut [u8]) -> Self {
+		Self { data }
+	}
+}
+
+impl BlockDevice for MemoryDisk<'_> {
+	type Error = Infallible;
+
+	fn read_block(
+		&mut self,
+		index: u32,
+		buf: &mut [u8; BLOCK_SIZE]
+	) -> Result<(), Self::Error> {
+		let start = index as usize * BLOCK_SIZE;
+		buf.copy_from_slice(&self.data[start..start + BLOCK_SIZE]);
+		Ok(())
+	}
+
+	fn write_block(
+		&mut self,
+		index: u32,
+		buf: &[u8; BLOCK_SIZE]
+	) -> Result<(), Self::Error> {
+		let start = index as usize * BLOCK_SIZE;
+		self.data[start..start + BLOCK_SIZE].copy_from_slice(buf);
+		Ok(())
+	}
+
+	fn block_count(&self) -> u32 {
+		(self.data.len() / BLOCK_SIZE) as u32
+	}
+}
@@ -0,0 +1,293 @@
+//! Game loop with a fixed timestep for the game logic.
+//!
+//! The game logic is updated at a fixed rate, independent of how long drawing does take.
+//! So the game runs at the same speed, even if the frame rate drops.
+//! ```
+//! use pybadge_high::{game_loop::GameLoop, PyBadge};
+//!
+//! struct Game {
+//! 	pybadge: PyBadge,
+//! 	x: f32
+//! }
+//!
+//! let mut game = Game {
+//! 	pybadge: PyBadge::take().unwrap(),
+//! 	x: 0.0
+//! };
+//! GameLoop::new(50).max_fps(30).run(
+//! 	&mut game,
+//! 	|game, dt| {
+//! 		game.pybadge.buttons.update();
+//! 		if game.pybadge.buttons.right_pressed() {
+//! 			game.x += 40.0 * dt.as_secs_f32();
+//! 		}
+//! 	},
+//! 	|game, stats| {
+//! 		// draw the game and show stats.fps
+//! 	}
+//! )
+//! ```
+
+use crate::time::{Duration, Instant};
+
+/// Length of the window, over which the frames per second are measured.
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Timing of the frames.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FrameStats {
+	/// Frames per second, measured over the last second.
+	pub fps: u32,
+	/// Time between the start of the last two frames.
+	pub frame_time: Duration,
+	/// Longest frame time of the last second.
+	pub max_frame_time: Duration,
+	/// Number of updates, which were skipped since start,
+	/// because a frame has taken too long to catch up.
+	pub skipped_updates: u32,
+	/// Number of frames since start.
+	pub frames: u64
+}
+
+/// Call the update function at a fixed rate and the draw function as fast as possible.
+///
+/// If a frame takes longer than one update step, the update function is called multiple times
+/// at the next frame, to catch up.
+/// The number of updates per frame is limited, so a slow frame does not cause more slow frames.
+/// Afterwards the updates, which could not be caught up, are skipped and the game slows down.
+pub struct GameLoop<C = fn() -> Instant> {
+	clock: C,
+	step: Duration,
+	max_updates: u32,
+	min_frame_time: Option<Duration>,
+	/// start of the last frame
+	last: Option<Instant>,
+	/// time, which was not processed by the update function yet
+	lag: Duration,
+	stats: FrameStats,
+	window_start: Option<Instant>,
+	window_frames: u32,
+	window_max: Duration
+}
+
+#[cfg(feature = "time")]
+impl GameLoop {
+	/// Call the update function `updates_per_second` times per second.
+	pub fn new(updates_per_second: u32) -> Self {
+		Self::with_clock(updates_per_second, Instant::now)
+	}
+}
+
+impl<C: FnMut() -> Instant> GameLoop<C> {
+	/// Use `clock` instead of [`Instant::now()`] as time source,
+	/// for example to test the game without hardware.
+	pub fn with_clock(updates_per_second: u32, clock: C) -> Self {
+		GameLoop {
+			clock,
+			step: Duration::from_micros(1_000_000 / updates_per_second.max(1) as u64),
+			max_updates: 5,
+			min_frame_time: None,
+			last: None,
+			lag: Duration::ZERO,
+			stats: FrameStats::default(),
+			window_start: None,
+			window_frames: 0,
+			window_max: Duration::ZERO
+		}
+	}
+
+	/// Limit the frames per second, by waiting at the end of each frame.
+	///
+	/// This saves power and gives an even frame rate, if drawing is sometimes slow.
+	pub fn max_fps(mut self, fps: u32) -> Self {
+		self.min_frame_time = Some(Duration::from_micros(1_000_000 / fps.max(1) as u64));
+		self
+	}
+
+	/// Maximum number of updates per frame to catch up. The default is 5.
+	pub fn max_updates_per_frame(mut self, updates: u32) -> Self {
+		self.max_updates = updates.max(1);
+		self
+	}
+
+	/// Fixed time between two updates.
+	pub fn step(&self) -> Duration {
+		self.step
+	}
+
+	pub fn stats(&self) -> &FrameStats {
+		&self.stats
+	}
+
+	/// Run a single frame.
+	///
+	/// `update` is called with the fixed time step as often as needed to catch up
+	/// and `draw` is called once afterwards.
+	/// The first frame does only draw, because no time has passed yet.
+	pub fn frame<S>(
+		&mut self,
+		state: &mut S,
+		mut update: impl FnMut(&mut S, Duration),
+		mut draw: impl FnMut(&mut S, &FrameStats)
+	) {
+		let start = (self.clock)();
+		if let Some(last) = self.last {
+			self.lag += start.duration_since(last);
+			self.stats.frame_time = start.duration_since(last);
+		}
+		self.last = Some(start);
+		self.measure(start);
+
+		let mut updates = 0;
+		while self.lag >= self.step {
+			if updates == self.max_updates {
+				let skipped = self.lag.as_micros() / self.step.as_micros();
+				self.stats.skipped_updates =
+					self.stats.skipped_updates.saturating_add(skipped as u32);
+				self.lag -= self.step * skipped as u32;
+				break;
+			}
+			update(state, self.step);
+			self.lag -= self.step;
+			updates += 1;
+		}
+		draw(state, &self.stats);
+
+		if let Some(min_frame_time) = self.min_frame_time {
+			let end = start + min_frame_time;
+			while (self.clock)() < end {}
+		}
+	}
+
+	/// Run frames forever.
+	pub fn run<S>(
+		mut self,
+		state: &mut S,
+		mut update: impl FnMut(&mut S, Duration),
+		mut draw: impl FnMut(&mut S, &FrameStats)
+	) -> ! {
+		loop {
+			self.frame(state, &mut update, &mut draw);
+		}
+	}
+
+	/// Update the statistics at the start of a frame.
+	fn measure(&mut self, start: Instant) {
+		self.stats.frames += 1;
+		self.window_frames += 1;
+		self.window_max = self.window_max.max(self.stats.frame_time);
+		let window_start = *self.window_start.get_or_insert(start);
+		let window = start.duration_since(window_start);
+		if window >= FPS_WINDOW {
+			// the current frame does belong to the next window
+			let frames = (self.window_frames - 1) as u64;
+			let window = window.as_micros();
+			self.stats.fps = ((frames * 1_000_000 + window / 2) / window) as u32;
+			self.stats.max_frame_time = self.window_max;
+			self.window_start = Some(start);
+			self.window_frames = 1;
+			self.window_max = Duration::ZERO;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+
+	fn at(millis: u64) -> Instant {
+		Instant::default() + Duration::from_millis(millis)
+	}
+
+	#[test]
+	fn fixed_updates_and_catch_up() {
+		let now = Cell::new(0);
+		// 10 ms per update
+		let mut game_loop = GameLoop::with_clock(100, || at(now.get()));
+		let mut updates = 0;
+		let mut frame_at = |millis, game_loop: &mut GameLoop<_>| {
+			now.set(millis);
+			game_loop.frame(
+				&mut updates,
+				|updates, dt| {
+					assert_eq!(dt, Duration::from_millis(10));
+					*updates += 1;
+				},
+				|_, _| {}
+			);
+			updates
+		};
+		assert_eq!(frame_at(0, &mut game_loop), 0);
+		// 5 ms are left for the next frame
+		assert_eq!(frame_at(25, &mut game_loop), 2);
+		assert_eq!(frame_at(30, &mut game_loop), 3);
+		// 20 updates are needed, but only 5 are allowed per frame
+		assert_eq!(frame_at(230, &mut game_loop), 8);
+		assert_eq!(game_loop.stats().skipped_updates, 15);
+		assert_eq!(frame_at(240, &mut game_loop), 9);
+	}
+
+	#[test]
+	fn max_updates_per_frame() {
+		let now = Cell::new(0);
+		let mut game_loop =
+			GameLoop::with_clock(100, || at(now.get())).max_updates_per_frame(2);
+		let mut updates = 0;
+		game_loop.frame(&mut updates, |updates, _| *updates += 1, |_, _| {});
+		now.set(100);
+		game_loop.frame(&mut updates, |updates, _| *updates += 1, |_, _| {});
+		assert_eq!(updates, 2);
+		assert_eq!(game_loop.stats().skipped_updates, 8);
+	}
+
+	#[test]
+	fn max_fps() {
+		// the clock advances by 1 ms at each read, so waiting for the next frame does end
+		let now = Cell::new(0);
+		let clock = || {
+			now.set(now.get() + 1);
+			at(now.get())
+		};
+		let mut game_loop = GameLoop::with_clock(50, clock).max_fps(20);
+		for _ in 0..50 {
+			game_loop.frame(&mut (), |_, _| {}, |_, _| {});
+		}
+		let stats = *game_loop.stats();
+		assert_eq!(stats.frames, 50);
+		assert_eq!(stats.fps, 20);
+		// 50 ms per frame, plus the reads of the clock
+		assert!(stats.frame_time >= Duration::from_millis(50));
+		assert!(stats.frame_time <= Duration::from_millis(52));
+	}
+
+	#[test]
+	fn fps_window() {
+		let now = Cell::new(0);
+		let mut game_loop = GameLoop::with_clock(100, || at(now.get()));
+		let mut frame_at = |millis| {
+			now.set(millis);
+			game_loop.frame(&mut (), |_, _| {}, |_, _| {});
+			*game_loop.stats()
+		};
+		// a frame every 10 ms, with one slow frame of 40 ms
+		for millis in (0..=500).step_by(10).chain((540..1000).step_by(10)) {
+			let stats = frame_at(millis);
+			// the first window is not complete yet
+			assert_eq!(stats.fps, 0);
+		}
+		let stats = frame_at(1000);
+		assert_eq!(stats.fps, 97);
+		assert_eq!(stats.max_frame_time, Duration::from_millis(40));
+		assert_eq!(stats.frame_time, Duration::from_millis(10));
+
+		// the slow frame is forgotten at the next window
+		for millis in (1010..2000).step_by(10) {
+			frame_at(millis);
+		}
+		let stats = frame_at(2000);
+		assert_eq!(stats.fps, 100);
+		assert_eq!(stats.max_frame_time, Duration::from_millis(10));
+		assert_eq!(stats.frames, 198);
+	}
+}
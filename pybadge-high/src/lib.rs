@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![allow(clippy::tabs_in_doc_comments)]
 #![warn(unreachable_pub)]
 #![cfg_attr(all(doc, nightly), feature(doc_auto_cfg))]
@@ -88,7 +88,18 @@
 //! ```
 //! When a program does panic, the red led at the back of the board starts flashing.
 //! If the `bluescreen`(default) feature is enable, the display does show the postion of the error.
-//! When the `beep_panic` feature is enable, the pybadge also beep for 3 seconds.
+//! A hard fault, like an invalid memory access at `unsafe` code, does also show a bluescreen.
+//! With the `stack-guard` feature this includes stack overflows.
+//! When the `beep_panic` feature is enable, the pybadge also beeps 5 times within 1.8 seconds.
+//! When the `panic-usb` feature is enable, the error is also printed over usb serial.
+//! The colors, the beeping and a restart after some time can be configured at runtime by [`PanicBehaviour`].
+//!
+//! If the position points into a library, like an `unwrap()`, the `backtrace` feature helps to find the caller.
+//! It does show the addresses of the calling functions at the bluescreen.
+//! Their functions and source lines can be looked up with the elf file of the program:
+//! ```bash
+//! tools/symbolicate.sh target/thumbv7em-none-eabihf/release/my-app 04a3c 05f12 0021e
+//! ```
 //!
 //! #### Flashing:
 //! To flash you program, put your device in bootloader mode by hitting the reset button twice.
@@ -98,6 +109,10 @@
 //! ```
 //! The display does not work until you have press the reset button of the pybadge after flashing.
 //!
+//! If the running program has enabled the `usb` feature, the pybadge does also enter the bootloader,
+//! when the serial port is opened with 1200 baud and closed again, like Arduino boards.
+//! At linux this can be done by `stty -F /dev/ttyACM0 1200`.
+//!
 //! # Feature-flags
 //! This crate has spilt functionallity in multiple feature flags.
 //! See the [rust book](https://doc.rust-lang.org/cargo/reference/features.html) for more information about features.
@@ -107,7 +122,7 @@
 //! The following features are aviable:
 #![doc = document_features::document_features!()]
 
-#[cfg(feature = "bluescreen")]
+#[cfg(any(feature = "bluescreen", feature = "panic-usb"))]
 use core::fmt::Write;
 pub use cortex_m;
 #[cfg(feature = "neopixel")]
@@ -143,6 +158,8 @@ use ws2812_timer_delay as ws2812;
 
 pub mod time;
 
+pub mod game_loop;
+
 /// There are 8 buttons on the front: A, B, Select, Start and four arranged in a d-pad.
 pub mod buttons;
 use buttons::Buttons;
@@ -161,12 +178,38 @@ pub mod usb;
 #[cfg(feature = "usb")]
 use usb::UsbBuilder;
 
+/// GD25Q16C 2M bytes external flash storage.
 #[cfg(feature = "flash")]
-mod flash;
-#[doc(hidden)] //feature temporary disable
+pub mod flash;
 #[cfg(feature = "flash")]
 pub use flash::Flash;
 
+#[cfg(feature = "settings")]
+pub mod settings;
+
+#[cfg(feature = "fs")]
+pub mod fs;
+
+#[cfg(feature = "rtc")]
+pub mod rtc;
+#[cfg(feature = "rtc")]
+pub use rtc::Rtc;
+
+mod panic_behaviour;
+pub use panic_behaviour::PanicBehaviour;
+mod panic_record;
+pub use panic_record::PanicRecord;
+
+#[cfg(feature = "backtrace")]
+mod backtrace;
+#[cfg(feature = "bluescreen")]
+mod fault;
+
+#[cfg(feature = "stack-guard")]
+mod stack;
+#[cfg(feature = "stack-guard")]
+pub use stack::{stack_high_watermark, stack_size};
+
 #[cfg(feature = "pwm_sound")]
 mod sound;
 #[cfg(feature = "pwm_sound")]
@@ -248,11 +291,13 @@ pub struct PyBadge {
 	pub delay: Delay,
 	#[cfg(feature = "neopixel")]
 	pub neopixel: NeoPixel,
-	#[doc(hidden)] //feature temporary disable
+	/// The external flash, or the error, if it could not be initialized.
 	#[cfg(feature = "flash")]
-	pub flash: Flash,
+	pub flash: Result<Flash, flash::Error>,
 	#[cfg(feature = "pwm_sound")]
 	pub speaker: PwmSound,
+	#[cfg(feature = "rtc")]
+	pub rtc: Rtc,
 	#[cfg(feature = "usb")]
 	pub usb_builder: UsbBuilder
 }
@@ -263,6 +308,9 @@ impl PyBadge {
 	/// otherwise it does return Err.
 	pub fn take() -> Result<PyBadge, ()> {
 		let mut peripherals = Peripherals::take().ok_or(())?;
+		panic_record::load();
+		#[cfg(feature = "stack-guard")]
+		stack::init();
 		#[allow(unused_mut)] //only some feature flags need mut
 		let mut core = CorePeripherals::take().ok_or(())?;
 		let mut clocks = GenericClockController::with_internal_32kosc(
@@ -325,7 +373,7 @@ impl PyBadge {
 			&mut delay
 		);
 
-		//32kHz clock to be used for sound and time at TC4 and TC5
+		//48MHz clock to be used for sound and time at TC4 and TC5
 		//move tc4_tc5
 		#[cfg(any(feature = "pwm_sound", feature = "time"))]
 		let tc4_tc5 = {
@@ -363,6 +411,14 @@ impl PyBadge {
 			}
 		};
 
+		//real-time clock
+		#[cfg(feature = "rtc")]
+		let rtc = Rtc::init(
+			peripherals.RTC,
+			&mut peripherals.MCLK,
+			&mut peripherals.OSC32KCTRL
+		);
+
 		//usb
 		#[cfg(feature = "usb")]
 		let usb_builder = {
@@ -380,7 +436,20 @@ impl PyBadge {
 				pins: pins.usb,
 				peripherals: peripherals.USB,
 				clocks,
-				mclk: peripherals.MCLK
+				mclk: peripherals.MCLK,
+				#[cfg(feature = "usb_mass_storage")]
+				flash: None,
+				serial: true,
+				touch_reset: true,
+				#[cfg(feature = "usb_hid")]
+				keyboard: false,
+				#[cfg(feature = "usb_hid")]
+				gamepad: false,
+				#[cfg(feature = "usb_hid")]
+				mouse: false,
+				#[cfg(feature = "usb_midi")]
+				midi: false,
+				classes: heapless::Vec::new()
 			}
 		};
 
@@ -395,17 +464,101 @@ impl PyBadge {
 			flash,
 			#[cfg(feature = "pwm_sound")]
 			speaker,
+			#[cfg(feature = "rtc")]
+			rtc,
 			#[cfg(feature = "usb")]
 			usb_builder,
 			delay
 		})
 	}
+
+	/// Panic of the last run, if the program has panicked before the last reset.
+	///
+	/// The panic is stored at ram, which is not cleared at reset.
+	/// So it is lost if the power is disconnected.
+	/// It is loaded by [`PyBadge::take()`]; before this `None` is returned.
+	/// ```
+	/// let pybadge = PyBadge::take().unwrap();
+	/// if let Some(panic) = PyBadge::last_panic() {
+	/// 	// prints `previous run crashed at src/main.rs:42:5`
+	/// 	writeln!(usb, "previous run crashed at {panic}").ok();
+	/// }
+	/// ```
+	pub fn last_panic() -> Option<&'static PanicRecord> {
+		panic_record::last_panic()
+	}
+}
+
+/// Last word of the ram, which is checked by the UF2 bootloader after reset.
+const DOUBLE_TAP_ADDRESS: usize = 0x2000_0000 + 192 * 1024 - 4;
+/// Value, which tells the bootloader that the reset button was pressed twice.
+const DOUBLE_TAP_MAGIC: u32 = 0xF016_69EF;
+
+/// Restart into the UF2 bootloader, like pressing the reset button twice.
+///
+/// Afterwards a new program can be flashed.
+pub fn reset_into_bootloader() -> ! {
+	unsafe {
+		core::ptr::write_volatile(DOUBLE_TAP_ADDRESS as *mut u32, DOUBLE_TAP_MAGIC)
+	};
+	cortex_m::peripheral::SCB::sys_reset()
 }
 
 #[inline(never)]
+#[cfg(not(test))]
 #[panic_handler]
 #[allow(unused_variables)] //panic_info is unused if bluescreen feature is disable
 fn panic(panic_info: &core::panic::PanicInfo) -> ! {
+	//capture the backtrace, before other functions overwrite the stack
+	#[cfg(feature = "backtrace")]
+	let backtrace = backtrace::Backtrace::capture();
+
+	//store the panic first, in case something does fail later
+	if let Some(location) = panic_info.location() {
+		#[allow(unused_mut)] //only needed with message
+		let mut record = PanicRecord::new(location.file(), location.line(), location.column());
+		#[cfg(all(feature = "bluescreen-message-nightly", nightly))]
+		if let Some(message) = panic_info.message() {
+			record.set_message(format_args!("{message}"));
+		}
+		panic_record::store(record);
+	}
+
+	#[cfg(any(feature = "bluescreen", feature = "panic-usb"))]
+	{
+		let mut report = heapless::String::<1024>::new();
+		writeln!(report, "program panicked at\n").ok();
+		if let Some(location) = panic_info.location() {
+			writeln!(
+				report,
+				"{}:{}:{}",
+				location.file(),
+				location.line(),
+				location.column()
+			)
+			.ok();
+		}
+		#[cfg(all(feature = "bluescreen-message-nightly", nightly))]
+		if let Some(message) = panic_info.message() {
+			writeln!(report, "{message}").ok();
+		}
+		#[cfg(feature = "backtrace")]
+		write!(report, "\n{backtrace}").ok();
+		fatal_error(&report)
+	}
+	#[cfg(not(any(feature = "bluescreen", feature = "panic-usb")))]
+	fatal_error("")
+}
+
+/// Blink the led and show `report` at the bluescreen, after the program has failed.
+#[inline(never)]
+#[allow(unused_variables)] //report is unused if bluescreen feature is disable
+fn fatal_error(report: &str) -> ! {
+	let behaviour = panic_behaviour::enter();
+	if let Some(hook) = behaviour.hook {
+		hook();
+	}
+
 	//simple turn red led on
 	let mut peripherals = unsafe { crate::pac::Peripherals::steal() };
 	let mut pins = Pins::new(peripherals.PORT).split();
@@ -423,9 +576,18 @@ fn panic(panic_info: &core::panic::PanicInfo) -> ! {
 	);
 
 	let mut delay = hal::delay::Delay::new(core.SYST, &mut clocks);
+	#[cfg(feature = "panic-usb")]
+	{
+		// the clock of the usb was reset by the clock controller
+		let gclk1 = clocks.gclk1();
+		clocks.usb(&gclk1);
+	}
 	let mut speaker_enable = pins.speaker.enable.into_push_pull_output(&mut pins.port);
 	let mut speaker = pins.speaker.speaker.into_push_pull_output(&mut pins.port);
 
+	#[cfg(feature = "panic-usb")]
+	let mut usb_report = usb::PanicReport::new(report);
+
 	#[cfg(feature = "bluescreen")]
 	{
 		let dislpay = pins
@@ -441,28 +603,11 @@ fn panic(panic_info: &core::panic::PanicInfo) -> ! {
 			.ok()
 			.map(|(display, _backlight)| display);
 		if let Some(mut display) = dislpay {
-			display.clear(Color::BLUE).unwrap();
-			let mut output = heapless::String::<1024>::new();
-			writeln!(output, "program panicked at\n").ok();
-			if let Some(location) = panic_info.location() {
-				writeln!(
-					output,
-					"{}:{}:{}",
-					location.file(),
-					location.line(),
-					location.column()
-				)
-				.ok();
-			}
-			#[cfg(all(feature = "bluescreen-message-nightly", nightly))]
-			if let Some(message) = panic_info.message() {
-				writeln!(output, "{message}").ok();
-			}
+			display.clear(behaviour.background).unwrap();
 			//insert newline every x char (no auto line wrap)
-			let old_output = output;
 			let mut output = heapless::String::<1024>::new();
 			let mut i: usize = 0;
-			for char in old_output.chars() {
+			for char in report.chars() {
 				i += 1;
 				if char == '\n' {
 					i = 0;
@@ -473,26 +618,47 @@ fn panic(panic_info: &core::panic::PanicInfo) -> ! {
 				}
 				write!(output, "{char}").ok();
 			}
-			let style = MonoTextStyle::new(&FONT_6X10, Color::WHITE);
+			let style = MonoTextStyle::new(&FONT_6X10, behaviour.text_color);
 			Text::new(&output, Point::new(5, 20), style)
 				.draw(&mut display)
 				.ok();
+			if let Some(draw) = behaviour.draw {
+				draw(&mut display);
+			}
 		}
 	}
 
-	let mut i = 0_u8;
+	let mut step = 0_u32;
+	#[cfg(feature = "panic-usb")]
+	let mut cycle = 0_u8;
 	loop {
 		led.toggle();
-		//stop sound after 3 seconds (it is annoying)
-		if i <= 8 && cfg!(feature = "beep_panic") {
-			speaker_enable.toggle();
-			i += 1
+		if let Some(reset_after) = behaviour.reset_after {
+			if step.saturating_mul(panic_behaviour::STEP_MS) >= reset_after.0 {
+				cortex_m::peripheral::SCB::sys_reset();
+			}
+		}
+		//print the report again every second
+		#[cfg(feature = "panic-usb")]
+		if let Some(usb_report) = usb_report.as_mut() {
+			cycle = (cycle + 1) % 5;
+			if cycle == 0 {
+				usb_report.repeat();
+			}
+		}
+		if step < 32 && behaviour.beep_pattern & 1 << step != 0 {
+			speaker_enable.set_high().ok();
 		} else {
 			speaker_enable.set_low().ok();
 		}
+		step = step.saturating_add(1);
 		for _ in 0..100 {
 			delay.delay_ms(2_u8);
 			speaker.toggle();
+			#[cfg(feature = "panic-usb")]
+			if let Some(usb_report) = usb_report.as_mut() {
+				usb_report.poll();
+			}
 		}
 	}
 }
@@ -0,0 +1,141 @@
+//! Runtime configuration of the panic handler.
+
+use crate::time::Milliseconds;
+#[cfg(feature = "bluescreen")]
+use crate::{Color, Display};
+use core::{
+	cell::Cell,
+	sync::atomic::{AtomicBool, Ordering}
+};
+use cortex_m::interrupt::Mutex;
+#[cfg(feature = "bluescreen")]
+use embedded_graphics::pixelcolor::RgbColor;
+
+/// Length of a step of the panic loop in milliseconds.
+pub(crate) const STEP_MS: u32 = 200;
+
+static PANIC_BEHAVIOUR: Mutex<Cell<PanicBehaviour>> =
+	Mutex::new(Cell::new(PanicBehaviour::new()));
+/// the panic handler was already entered, to prevent a loop if the hook does panic
+static ENTERED: AtomicBool = AtomicBool::new(false);
+
+/// What should be done after the program has panicked or a hard fault has occurred.
+///
+/// The default does depend on the enabled features and stays at the bluescreen forever.
+/// ```
+/// use pybadge_high::{time::Milliseconds, PanicBehaviour};
+///
+/// // reboot a game kiosk after 5 seconds
+/// PanicBehaviour::new()
+/// 	.reset_after(Milliseconds(5000))
+/// 	.hook(|| {
+/// 		// turn off the neopixels, save the game, ...
+/// 	})
+/// 	.set();
+/// ```
+#[derive(Clone, Copy)]
+pub struct PanicBehaviour {
+	pub(crate) reset_after: Option<Milliseconds>,
+	pub(crate) beep_pattern: u32,
+	pub(crate) hook: Option<fn()>,
+	#[cfg(feature = "bluescreen")]
+	pub(crate) background: Color,
+	#[cfg(feature = "bluescreen")]
+	pub(crate) text_color: Color,
+	#[cfg(feature = "bluescreen")]
+	pub(crate) draw: Option<fn(&mut Display)>
+}
+
+impl Default for PanicBehaviour {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl PanicBehaviour {
+	/// Default behaviour, which was chosen by the feature flags.
+	pub const fn new() -> Self {
+		PanicBehaviour {
+			reset_after: None,
+			// beep 5 times, if the feature is enabled
+			beep_pattern: if cfg!(feature = "beep_panic") {
+				0x155
+			} else {
+				0
+			},
+			hook: None,
+			#[cfg(feature = "bluescreen")]
+			background: Color::BLUE,
+			#[cfg(feature = "bluescreen")]
+			text_color: Color::WHITE,
+			#[cfg(feature = "bluescreen")]
+			draw: None
+		}
+	}
+
+	/// Restart the microcontroller after `time`.
+	///
+	/// The panic can be read after restarting by [`PyBadge::last_panic()`](crate::PyBadge::last_panic()).
+	pub fn reset_after(mut self, time: Milliseconds) -> Self {
+		self.reset_after = Some(time);
+		self
+	}
+
+	/// Stay at the bluescreen forever. This is the default.
+	pub fn stay(mut self) -> Self {
+		self.reset_after = None;
+		self
+	}
+
+	/// Beep pattern of the speaker.
+	///
+	/// Bit n does enable the speaker at the n-th step of 200 milliseconds.
+	/// So `0b101` beeps two times.
+	/// The default is `0x155` if the `beep_panic` feature is enabled, otherwise `0`.
+	pub fn beep(mut self, pattern: u32) -> Self {
+		self.beep_pattern = pattern;
+		self
+	}
+
+	/// Call `hook` before anything else is done by the panic handler.
+	///
+	/// Can be used for last cleanups, like turning off the neopixels or saving the state.
+	/// Peripherals may be in an undefined state, because of the failure.
+	/// If the hook does panic itself, it is not called again.
+	pub fn hook(mut self, hook: fn()) -> Self {
+		self.hook = Some(hook);
+		self
+	}
+
+	/// Colors of the bluescreen.
+	#[cfg(feature = "bluescreen")]
+	pub fn colors(mut self, background: Color, text: Color) -> Self {
+		self.background = background;
+		self.text_color = text;
+		self
+	}
+
+	/// Call `draw` after the error was drawn at the bluescreen,
+	/// to draw something own, like a logo.
+	#[cfg(feature = "bluescreen")]
+	pub fn draw(mut self, draw: fn(&mut Display)) -> Self {
+		self.draw = Some(draw);
+		self
+	}
+
+	/// Use this behaviour for the next panic.
+	pub fn set(self) {
+		cortex_m::interrupt::free(|cs| PANIC_BEHAVIOUR.borrow(cs).set(self))
+	}
+}
+
+/// Current behaviour.
+///
+/// The hook is removed, if the panic handler was already entered before.
+pub(crate) fn enter() -> PanicBehaviour {
+	let mut behaviour = cortex_m::interrupt::free(|cs| PANIC_BEHAVIOUR.borrow(cs).get());
+	if ENTERED.swap(true, Ordering::Relaxed) {
+		behaviour.hook = None;
+	}
+	behaviour
+}
@@ -0,0 +1,259 @@
+//! Panic information, which survives a reset of the microcontroller.
+//!
+//! The panic handler stores the record at a ram section, which is not initialized at startup.
+//! The formatting does not access the hardware.
+
+use core::{
+	fmt,
+	fmt::{Display, Formatter, Write},
+	mem::MaybeUninit,
+	ptr::{addr_of, addr_of_mut}
+};
+
+/// marks a valid record, the ram content is random after power on
+const MAGIC: u32 = 0x5041_4E43;
+const FILE_SIZE: usize = 64;
+const MESSAGE_SIZE: usize = 128;
+
+#[link_section = ".uninit.PANIC_RECORD"]
+static mut PANIC_RECORD: MaybeUninit<PanicRecord> = MaybeUninit::uninit();
+/// Record of the last run, which was loaded by [`load()`].
+static mut LAST_PANIC: Option<PanicRecord> = None;
+
+/// Location and message of a panic.
+///
+/// See [`PyBadge::last_panic()`](crate::PyBadge::last_panic()).
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub struct PanicRecord {
+	magic: u32,
+	checksum: u32,
+	line: u32,
+	column: u32,
+	file_len: u32,
+	message_len: u32,
+	file: [u8; FILE_SIZE],
+	message: [u8; MESSAGE_SIZE]
+}
+
+impl PanicRecord {
+	/// Create a record without message.
+	///
+	/// If `file` is too long, only its end is stored.
+	pub fn new(file: &str, line: u32, column: u32) -> Self {
+		let mut record = PanicRecord {
+			magic: MAGIC,
+			checksum: 0,
+			line,
+			column,
+			file_len: 0,
+			message_len: 0,
+			file: [0; FILE_SIZE],
+			message: [0; MESSAGE_SIZE]
+		};
+		let mut start = file.len().saturating_sub(FILE_SIZE);
+		while !file.is_char_boundary(start) {
+			start += 1;
+		}
+		let file = &file.as_bytes()[start..];
+		record.file[..file.len()].copy_from_slice(file);
+		record.file_len = file.len() as u32;
+		record.checksum = record.calc_checksum();
+		record
+	}
+
+	/// Path of the source file, which has panicked.
+	pub fn file(&self) -> &str {
+		let len = (self.file_len as usize).min(FILE_SIZE);
+		core::str::from_utf8(&self.file[..len]).unwrap_or_default()
+	}
+
+	pub fn line(&self) -> u32 {
+		self.line
+	}
+
+	pub fn column(&self) -> u32 {
+		self.column
+	}
+
+	/// Panic message, which may be truncated.
+	///
+	/// The message is only available if the `bluescreen-message-nightly` feature is enabled.
+	pub fn message(&self) -> Option<&str> {
+		let len = (self.message_len as usize).min(MESSAGE_SIZE);
+		if len == 0 {
+			return None;
+		}
+		core::str::from_utf8(&self.message[..len]).ok()
+	}
+
+	/// Set the message, which is truncated if it is too long.
+	pub fn set_message(&mut self, message: fmt::Arguments<'_>) {
+		self.message_len = 0;
+		// a full buffer is not an error, the rest of the message is dropped
+		write!(MessageWriter(self), "{message}").ok();
+		self.checksum = self.calc_checksum();
+	}
+
+	fn calc_checksum(&self) -> u32 {
+		// FNV-1a
+		let mut hash: u32 = 0x811C_9DC5;
+		let numbers = [
+			self.magic,
+			self.line,
+			self.column,
+			self.file_len,
+			self.message_len
+		];
+		let bytes = numbers
+			.iter()
+			.flat_map(|number| number.to_le_bytes())
+			.chain(self.file.iter().copied())
+			.chain(self.message.iter().copied());
+		for byte in bytes {
+			hash ^= byte as u32;
+			hash = hash.wrapping_mul(0x0100_0193);
+		}
+		hash
+	}
+
+	/// Return true, if the record was not changed since it was created.
+	pub fn is_valid(&self) -> bool {
+		self.magic == MAGIC
+			&& self.file_len as usize <= FILE_SIZE
+			&& self.message_len as usize <= MESSAGE_SIZE
+			&& self.checksum == self.calc_checksum()
+	}
+}
+
+/// Format like `src/main.rs:42:5: message`.
+impl Display for PanicRecord {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}:{}:{}", self.file(), self.line, self.column)?;
+		if let Some(message) = self.message() {
+			write!(f, ": {message}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Append to the message of a record, until it is full.
+struct MessageWriter<'a>(&'a mut PanicRecord);
+
+impl Write for MessageWriter<'_> {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		let record = &mut *self.0;
+		let len = record.message_len as usize;
+		let mut count = s.len().min(MESSAGE_SIZE - len);
+		// do not split a character
+		while !s.is_char_boundary(count) {
+			count -= 1;
+		}
+		record.message[len..len + count].copy_from_slice(&s.as_bytes()[..count]);
+		record.message_len += count as u32;
+		if count < s.len() {
+			return Err(fmt::Error);
+		}
+		Ok(())
+	}
+}
+
+/// Pointer to the record at the uninitialized ram.
+fn record_ptr() -> *mut PanicRecord {
+	addr_of_mut!(PANIC_RECORD).cast()
+}
+
+/// Store the record, so it can be loaded after the next reset.
+pub(crate) fn store(record: PanicRecord) {
+	unsafe { core::ptr::write_volatile(record_ptr(), record) };
+}
+
+/// Move the record of the last run out of the uninitialized ram.
+///
+/// Afterwards the ram is cleared, so a following reset without panic is not reported.
+pub(crate) fn load() {
+	let record = unsafe { core::ptr::read_volatile(record_ptr()) };
+	if record.is_valid() {
+		unsafe { *addr_of_mut!(LAST_PANIC) = Some(record) };
+	}
+	unsafe { core::ptr::write_volatile(addr_of_mut!((*record_ptr()).magic), 0) };
+}
+
+pub(crate) fn last_panic() -> Option<&'static PanicRecord> {
+	unsafe { (*addr_of!(LAST_PANIC)).as_ref() }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn display() {
+		let mut record = PanicRecord::new("src/main.rs", 42, 5);
+		assert_eq!(record.message(), None);
+		assert_eq!(record.to_string(), "src/main.rs:42:5");
+		record.set_message(format_args!("index {} out of range", 7));
+		assert_eq!(record.message(), Some("index 7 out of range"));
+		assert_eq!(record.to_string(), "src/main.rs:42:5: index 7 out of range");
+		record.set_message(format_args!(""));
+		assert_eq!(record.to_string(), "src/main.rs:42:5");
+	}
+
+	#[test]
+	fn long_message() {
+		let mut record = PanicRecord::new("src/main.rs", 1, 1);
+		let message = "x".repeat(200);
+		record.set_message(format_args!("{message}"));
+		assert_eq!(record.message(), Some(&message[..MESSAGE_SIZE]));
+		assert!(record.is_valid());
+		// a multi byte character is not split
+		record.set_message(format_args!("x{}", "ä".repeat(100)));
+		let message = record.message().unwrap();
+		assert_eq!(message.len(), MESSAGE_SIZE - 1);
+		assert!(message.ends_with('ä'));
+		// the message is replaced, not appended
+		record.set_message(format_args!("short"));
+		assert_eq!(record.message(), Some("short"));
+	}
+
+	#[test]
+	fn long_file() {
+		let file = format!("/home/{}/src/main.rs", "ö".repeat(40));
+		let record = PanicRecord::new(&file, 1, 1);
+		assert!(record.file().len() <= FILE_SIZE);
+		assert!(file.ends_with(record.file()));
+		assert!(record.file().ends_with("ö/src/main.rs"));
+		assert!(record.is_valid());
+	}
+
+	#[test]
+	fn checksum() {
+		let mut record = PanicRecord::new("src/main.rs", 42, 5);
+		record.set_message(format_args!("boom"));
+		assert!(record.is_valid());
+
+		let mut changed = record.clone();
+		changed.line = 43;
+		assert!(!changed.is_valid());
+		let mut changed = record.clone();
+		changed.message[0] ^= 1;
+		assert!(!changed.is_valid());
+		let mut changed = record.clone();
+		changed.message[MESSAGE_SIZE - 1] = 1;
+		assert!(!changed.is_valid());
+		let mut changed = record.clone();
+		changed.checksum ^= 1;
+		assert!(!changed.is_valid());
+		let mut changed = record.clone();
+		changed.magic = 0;
+		changed.checksum = changed.calc_checksum();
+		assert!(!changed.is_valid());
+		let mut changed = record.clone();
+		changed.file_len = FILE_SIZE as u32 + 1;
+		changed.checksum = changed.calc_checksum();
+		assert!(!changed.is_valid());
+
+		let zeroed: PanicRecord = unsafe { MaybeUninit::zeroed().assume_init() };
+		assert!(!zeroed.is_valid());
+	}
+}
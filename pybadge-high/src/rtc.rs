@@ -0,0 +1,171 @@
+//! Real-time clock with calendar date and time.
+//!
+//! The rtc counts the seconds since 2000-01-01 00:00:00 in a 32 bit counter.
+//! It keeps running during a reset, but not if the power is disconnected.
+//! The rtc is clocked by the internal ultra low power oscillator,
+//! so it can drift some seconds per hour and should be synchronized regularly, for example over usb.
+//! ```
+//! let mut rtc = pybadge.rtc;
+//! if !rtc.is_set() {
+//! 	rtc.set_datetime(DateTime::new(2024, 5, 1, 12, 30, 0).unwrap());
+//! }
+//! rtc.set_alarm(DateTime::new(2024, 5, 1, 13, 0, 0).unwrap(), || {
+//! 	// time for lunch
+//! });
+//! ```
+
+use core::cell::Cell;
+use cortex_m::{interrupt::Mutex, peripheral::NVIC};
+use edgebadge::{pac, pac::RTC};
+use pac::{interrupt, MCLK, OSC32KCTRL};
+
+mod datetime;
+pub use datetime::{days_in_month, is_leap_year, DateTime, Weekday};
+
+/// Stored at a general purpose register of the rtc, after the time was set.
+/// The register is only cleared if the power is lost.
+const MAGIC: u32 = 0x5254_4353;
+
+type Callback = Option<fn()>;
+
+/// Callback of the alarm
+static ALARM: Mutex<Cell<Callback>> = Mutex::new(Cell::new(None));
+
+/// Real-time clock, which counts the seconds since 2000-01-01 00:00:00.
+pub struct Rtc {
+	rtc: RTC
+}
+
+impl Rtc {
+	/// Start the rtc, if it is not already running since a previous reset.
+	pub(crate) fn init(rtc: RTC, mclk: &mut MCLK, osc32kctrl: &mut OSC32KCTRL) -> Self {
+		mclk.apbamask.modify(|_, w| w.rtc_().set_bit());
+		let mode0 = rtc.mode0();
+		let ctrla = mode0.ctrla.read();
+		let running = ctrla.enable().bit_is_set()
+			&& ctrla.mode().is_count32()
+			&& ctrla.prescaler().is_div1024();
+		if !running {
+			//1.024 kHz from the internal oscillator, divided by 1024 gives 1 Hz
+			osc32kctrl.rtcctrl.write(|w| w.rtcsel().ulp1k());
+			mode0.ctrla.write(|w| w.swrst().set_bit());
+			while mode0.syncbusy.read().swrst().bit_is_set() {}
+			mode0.ctrla.write(|w| {
+				w.mode().count32();
+				w.prescaler().div1024();
+				w.countsync().set_bit();
+				w.enable().set_bit()
+			});
+			while mode0.syncbusy.read().enable().bit_is_set() {}
+		}
+		//an alarm of the previous run is not valid anymore
+		mode0.intenclr.write(|w| w.cmp0().set_bit());
+		unsafe {
+			NVIC::unmask(interrupt::RTC);
+		}
+		Rtc { rtc }
+	}
+
+	/// Seconds since 2000-01-01 00:00:00.
+	pub fn timestamp(&self) -> u32 {
+		let mode0 = self.rtc.mode0();
+		while mode0.syncbusy.read().count().bit_is_set() {}
+		mode0.count.read().bits()
+	}
+
+	/// Current date and time.
+	///
+	/// If the time was not set, it starts at 2000-01-01 00:00:00.
+	pub fn now(&self) -> DateTime {
+		DateTime::from_timestamp(self.timestamp())
+	}
+
+	/// Set the current date and time. The rtc does keep it, until the power is disconnected.
+	pub fn set_datetime(&mut self, datetime: DateTime) {
+		let mode0 = self.rtc.mode0();
+		mode0
+			.count
+			.write(|w| unsafe { w.bits(datetime.timestamp()) });
+		while mode0.syncbusy.read().count().bit_is_set() {}
+		mode0.gp[0].write(|w| unsafe { w.bits(MAGIC) });
+	}
+
+	/// Return true, if the time was set since the power was connected.
+	pub fn is_set(&self) -> bool {
+		self.rtc.mode0().gp[0].read().bits() == MAGIC
+	}
+
+	/// Call `callback` at `datetime`, from the rtc interrupt.
+	///
+	/// Only one alarm can be active, an previous alarm is replaced.
+	/// Returns false and does not set the alarm, if `datetime` has already passed.
+	pub fn set_alarm(&mut self, datetime: DateTime, callback: fn()) -> bool {
+		let timestamp = datetime.timestamp();
+		self.cancel_alarm();
+		if timestamp <= self.timestamp() {
+			return false;
+		}
+		let mode0 = self.rtc.mode0();
+		cortex_m::interrupt::free(|cs| ALARM.borrow(cs).set(Some(callback)));
+		mode0.comp[0].write(|w| unsafe { w.bits(timestamp) });
+		while mode0.syncbusy.read().comp0().bit_is_set() {}
+		mode0.intflag.write(|w| w.cmp0().set_bit());
+		mode0.intenset.write(|w| w.cmp0().set_bit());
+		// the time might have been reached, while the compare register was synchronized
+		if self.timestamp() >= timestamp && mode0.intflag.read().cmp0().bit_is_clear() {
+			self.cancel_alarm();
+			return false;
+		}
+		true
+	}
+
+	/// Time of the active alarm.
+	pub fn alarm(&self) -> Option<DateTime> {
+		let mode0 = self.rtc.mode0();
+		if mode0.intenset.read().cmp0().bit_is_clear() {
+			return None;
+		}
+		Some(DateTime::from_timestamp(mode0.comp[0].read().bits()))
+	}
+
+	pub fn cancel_alarm(&mut self) {
+		self.rtc.mode0().intenclr.write(|w| w.cmp0().set_bit());
+		cortex_m::interrupt::free(|cs| ALARM.borrow(cs).set(None));
+	}
+
+	/// Set the time, if the host has sent a line with the current time over usb serial.
+	///
+	/// The line can contain a unix timestamp with `T` prefix, like `T1714566600`
+	/// or a date time like `2024-05-01 12:30:00`.
+	/// At linux the time can be sent by `date +T%s > /dev/ttyACM0`.
+	/// Other lines are kept, so they can still be read by [`Usb::read_line()`](crate::usb::Usb::read_line()).
+	/// Returns true, if the time was set.
+	#[cfg(feature = "usb")]
+	pub fn sync_usb(&mut self, usb: &mut crate::usb::Usb) -> bool {
+		let mut buf = [0_u8; 32];
+		let mut received = None;
+		usb.read_line_if(&mut buf, |line| {
+			received = datetime::parse_sync(line);
+			received.is_some()
+		});
+		match received {
+			Some(datetime) => {
+				self.set_datetime(datetime);
+				true
+			},
+			None => false
+		}
+	}
+}
+
+#[interrupt]
+fn RTC() {
+	let mode0 = unsafe { RTC::ptr().as_ref().unwrap().mode0() };
+	//clear intfalg, oterwise interrup is fired again at the next cycle
+	mode0.intflag.write(|w| w.cmp0().set_bit());
+	mode0.intenclr.write(|w| w.cmp0().set_bit());
+	let alarm = cortex_m::interrupt::free(|cs| ALARM.borrow(cs).take());
+	if let Some(alarm) = alarm {
+		alarm();
+	}
+}
@@ -0,0 +1,373 @@
+//! Calendar date and time, counted in seconds since 2000-01-01 00:00:00.
+//!
+//! The conversion does not access the hardware.
+
+use crate::time::Duration;
+use core::{
+	fmt,
+	fmt::{Display, Formatter},
+	ops::Add,
+	str::FromStr
+};
+
+/// first year, which can be represented
+const EPOCH_YEAR: u16 = 2000;
+/// last year, which fits partly into the 32 bit counter of the rtc
+const MAX_YEAR: u16 = 2136;
+/// seconds between 1970-01-01 and 2000-01-01
+const UNIX_OFFSET: u64 = 946_684_800;
+const SECS_PER_DAY: u32 = 24 * 60 * 60;
+
+pub fn is_leap_year(year: u16) -> bool {
+	year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Number of days of `month` (1 to 12) at `year`.
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+	match month {
+		2 if is_leap_year(year) => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31
+	}
+}
+
+fn days_in_year(year: u16) -> u32 {
+	if is_leap_year(year) {
+		366
+	} else {
+		365
+	}
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Weekday {
+	Monday,
+	Tuesday,
+	Wednesday,
+	Thursday,
+	Friday,
+	Saturday,
+	Sunday
+}
+
+impl Weekday {
+	/// Monday is 0 and sunday 6.
+	fn from_index(index: u32) -> Self {
+		match index % 7 {
+			0 => Weekday::Monday,
+			1 => Weekday::Tuesday,
+			2 => Weekday::Wednesday,
+			3 => Weekday::Thursday,
+			4 => Weekday::Friday,
+			5 => Weekday::Saturday,
+			_ => Weekday::Sunday
+		}
+	}
+}
+
+/// Date and time from the year 2000 up to 2136-02-07, without timezone.
+///
+/// The fields are ordered, so the comparison is chronological.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct DateTime {
+	year: u16,
+	month: u8,
+	day: u8,
+	hour: u8,
+	minute: u8,
+	second: u8
+}
+
+impl DateTime {
+	/// Returns `None`, if the date does not exist or is outside of the supported range.
+	pub fn new(
+		year: u16,
+		month: u8,
+		day: u8,
+		hour: u8,
+		minute: u8,
+		second: u8
+	) -> Option<Self> {
+		if !(EPOCH_YEAR..=MAX_YEAR).contains(&year)
+			|| !(1..=12).contains(&month)
+			|| day == 0
+			|| day > days_in_month(year, month)
+			|| hour > 23
+			|| minute > 59
+			|| second > 59
+		{
+			return None;
+		}
+		let datetime = DateTime {
+			year,
+			month,
+			day,
+			hour,
+			minute,
+			second
+		};
+		// the last year is not complete
+		let timestamp = datetime.days_since_epoch() as u64 * SECS_PER_DAY as u64
+			+ hour as u64 * 3600
+			+ minute as u64 * 60
+			+ second as u64;
+		if timestamp > u32::MAX as u64 {
+			return None;
+		}
+		Some(datetime)
+	}
+
+	/// Create from the seconds since 2000-01-01 00:00:00.
+	pub fn from_timestamp(timestamp: u32) -> Self {
+		let mut days = timestamp / SECS_PER_DAY;
+		let secs = timestamp % SECS_PER_DAY;
+		let mut year = EPOCH_YEAR;
+		while days >= days_in_year(year) {
+			days -= days_in_year(year);
+			year += 1;
+		}
+		let mut month = 1;
+		while days >= days_in_month(year, month) as u32 {
+			days -= days_in_month(year, month) as u32;
+			month += 1;
+		}
+		DateTime {
+			year,
+			month,
+			day: days as u8 + 1,
+			hour: (secs / 3600) as u8,
+			minute: (secs / 60 % 60) as u8,
+			second: (secs % 60) as u8
+		}
+	}
+
+	/// Seconds since 2000-01-01 00:00:00.
+	pub fn timestamp(&self) -> u32 {
+		self.days_since_epoch() * SECS_PER_DAY
+			+ self.hour as u32 * 3600
+			+ self.minute as u32 * 60
+			+ self.second as u32
+	}
+
+	/// Create from the seconds since 1970-01-01 00:00:00, like it is used by most computers.
+	///
+	/// Returns `None`, if the time is outside of the supported range.
+	pub fn from_unix(unix: u64) -> Option<Self> {
+		let timestamp = unix.checked_sub(UNIX_OFFSET)?;
+		Some(Self::from_timestamp(timestamp.try_into().ok()?))
+	}
+
+	/// Seconds since 1970-01-01 00:00:00.
+	pub fn unix(&self) -> u64 {
+		self.timestamp() as u64 + UNIX_OFFSET
+	}
+
+	fn days_since_epoch(&self) -> u32 {
+		let mut days: u32 = (EPOCH_YEAR..self.year).map(days_in_year).sum();
+		for month in 1..self.month {
+			days += days_in_month(self.year, month) as u32;
+		}
+		days + self.day as u32 - 1
+	}
+
+	pub fn year(&self) -> u16 {
+		self.year
+	}
+
+	/// Month from 1 to 12.
+	pub fn month(&self) -> u8 {
+		self.month
+	}
+
+	/// Day of the month, starting at 1.
+	pub fn day(&self) -> u8 {
+		self.day
+	}
+
+	pub fn hour(&self) -> u8 {
+		self.hour
+	}
+
+	pub fn minute(&self) -> u8 {
+		self.minute
+	}
+
+	pub fn second(&self) -> u8 {
+		self.second
+	}
+
+	pub fn weekday(&self) -> Weekday {
+		// 2000-01-01 was a saturday
+		Weekday::from_index(self.days_since_epoch() + 5)
+	}
+
+	/// Add `duration`, ignoring fractions of seconds.
+	///
+	/// Returns `None`, if the result is after 2136-02-07.
+	pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+		let timestamp = self.timestamp() as u64 + duration.as_secs();
+		Some(Self::from_timestamp(timestamp.try_into().ok()?))
+	}
+}
+
+/// Panics, if the result is after 2136-02-07.
+impl Add<Duration> for DateTime {
+	type Output = DateTime;
+
+	fn add(self, rhs: Duration) -> DateTime {
+		self.checked_add(rhs)
+			.expect("overflow when adding duration to date time")
+	}
+}
+
+/// Format like `2024-05-01 12:30:00`.
+impl Display for DateTime {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+			self.year, self.month, self.day, self.hour, self.minute, self.second
+		)
+	}
+}
+
+/// Parse `2024-05-01 12:30:00`, also `T` can be used as separator between date and time, like at ISO 8601.
+impl FromStr for DateTime {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, ()> {
+		let (date, time) = s.trim().split_once([' ', 'T']).ok_or(())?;
+		let mut date = date.split('-');
+		let mut time = time.split(':');
+		let next = |parts: &mut core::str::Split<'_, char>| -> Result<u16, ()> {
+			parts.next().ok_or(())?.parse().map_err(|_| ())
+		};
+		let year = next(&mut date)?;
+		let month = next(&mut date)?;
+		let day = next(&mut date)?;
+		let hour = next(&mut time)?;
+		let minute = next(&mut time)?;
+		let second = next(&mut time)?;
+		if date.next().is_some() || time.next().is_some() {
+			return Err(());
+		}
+		let byte = |value: u16| u8::try_from(value).map_err(|_| ());
+		DateTime::new(
+			year,
+			byte(month)?,
+			byte(day)?,
+			byte(hour)?,
+			byte(minute)?,
+			byte(second)?
+		)
+		.ok_or(())
+	}
+}
+
+/// Parse a line sent by the host to set the clock.
+///
+/// Either a unix timestamp with `T` prefix, like `T1714566600`,
+/// which can be created by `date +T%s`,
+/// or a date time like `2024-05-01 12:30:00`.
+#[cfg(feature = "usb")]
+pub(crate) fn parse_sync(line: &str) -> Option<DateTime> {
+	let line = line.trim();
+	if let Some(unix) = line.strip_prefix('T') {
+		return DateTime::from_unix(unix.parse().ok()?);
+	}
+	line.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn leap_years() {
+		assert!(is_leap_year(2000));
+		assert!(is_leap_year(2024));
+		assert!(!is_leap_year(2100));
+		assert!(!is_leap_year(2023));
+		assert_eq!(days_in_month(2024, 2), 29);
+		assert_eq!(days_in_month(2100, 2), 28);
+		assert_eq!(days_in_month(2023, 4), 30);
+	}
+
+	#[test]
+	fn timestamp_roundtrip() {
+		let mut timestamp = 0_u32;
+		let mut previous: Option<DateTime> = None;
+		// odd step, to hit every day at a different time
+		while let Some(next) = timestamp.checked_add(86_399) {
+			let datetime = DateTime::from_timestamp(timestamp);
+			assert_eq!(datetime.timestamp(), timestamp, "{datetime}");
+			assert_eq!(
+				DateTime::new(
+					datetime.year(),
+					datetime.month(),
+					datetime.day(),
+					datetime.hour(),
+					datetime.minute(),
+					datetime.second()
+				),
+				Some(datetime)
+			);
+			if let Some(previous) = previous {
+				assert!(previous < datetime);
+			}
+			previous = Some(datetime);
+			timestamp = next;
+		}
+		assert_eq!(
+			DateTime::from_timestamp(u32::MAX).to_string(),
+			"2136-02-07 06:28:15"
+		);
+	}
+
+	#[test]
+	fn known_dates() {
+		let datetime = DateTime::new(2024, 5, 1, 12, 30, 0).unwrap();
+		assert_eq!(datetime.unix(), 1_714_566_600);
+		assert_eq!(DateTime::from_unix(1_714_566_600), Some(datetime));
+		assert_eq!(DateTime::from_unix(0), None);
+		assert_eq!(datetime.weekday(), Weekday::Wednesday);
+		let weekday = |year, month, day| {
+			DateTime::new(year, month, day, 0, 0, 0).unwrap().weekday()
+		};
+		assert_eq!(weekday(2000, 1, 1), Weekday::Saturday);
+		assert_eq!(weekday(2000, 2, 29), Weekday::Tuesday);
+		assert_eq!(weekday(2024, 2, 29), Weekday::Thursday);
+		assert_eq!(weekday(2100, 3, 1), Weekday::Monday);
+		assert_eq!(DateTime::new(2023, 2, 29, 0, 0, 0), None);
+		assert_eq!(DateTime::new(2100, 2, 29, 0, 0, 0), None);
+		assert_eq!(DateTime::new(1999, 12, 31, 0, 0, 0), None);
+		assert_eq!(DateTime::new(2024, 13, 1, 0, 0, 0), None);
+		assert_eq!(DateTime::new(2024, 1, 1, 24, 0, 0), None);
+		assert!(DateTime::new(2136, 2, 7, 6, 28, 15).is_some());
+		assert_eq!(DateTime::new(2136, 2, 7, 6, 28, 16), None);
+		let datetime = DateTime::new(2024, 2, 28, 23, 0, 0).unwrap()
+			+ Duration::from_secs(3600 * 25);
+		assert_eq!(datetime.to_string(), "2024-03-01 00:00:00");
+	}
+
+	#[test]
+	fn parse() {
+		let datetime = DateTime::new(2024, 5, 1, 12, 30, 0).unwrap();
+		assert_eq!("2024-05-01 12:30:00".parse(), Ok(datetime));
+		assert_eq!("2024-05-01T12:30:00\r\n".parse(), Ok(datetime));
+		assert!("2024-05-01".parse::<DateTime>().is_err());
+		assert!("2024-05-01 12:30:00:00".parse::<DateTime>().is_err());
+		assert!("2024-02-30 12:30:00".parse::<DateTime>().is_err());
+	}
+
+	#[cfg(feature = "usb")]
+	#[test]
+	fn parse_sync_line() {
+		let datetime = DateTime::new(2024, 5, 1, 12, 30, 0).unwrap();
+		assert_eq!(parse_sync("T1714566600\n"), Some(datetime));
+		assert_eq!(parse_sync("2024-05-01 12:30:00"), Some(datetime));
+		assert_eq!(parse_sync("hello"), None);
+		assert_eq!(parse_sync("T12"), None);
+	}
+}
@@ -0,0 +1,688 @@
+//! Persistent typed key/value store, like high scores, brightness or user names.
+//!
+//! The [`Settings`] can be build on top of the [`Flash`](crate::Flash),
+//! or any other [`NorFlash`] implementation, like [`RamFlash`](crate::flash::RamFlash).
+//! ```
+//! use pybadge_high::settings::{Key, Settings};
+//!
+//! const HIGH_SCORE: Key<u32> = Key::new(1);
+//!
+//! let mut settings =
+//! 	Settings::new(pybadge.flash.unwrap(), 0x1F_0000..0x20_0000).unwrap();
+//! let high_score = settings.get(HIGH_SCORE).unwrap().unwrap_or(0);
+//! settings.set(HIGH_SCORE, &(high_score + 1)).unwrap();
+//! ```
+//!
+//! # Storage format
+//! The settings use a region of the flash, which must contain at least two erase sectors.
+//! Only one of the sectors is active at once.
+//! New values are appended to the active sector, so writing does not need an erase.
+//! If the active sector is full, the current values are copied to the next sector
+//! and the old one becomes free.
+//! So all sectors of the region are erased in turns (wear levelling).
+//!
+//! Each record has a checksum.
+//! If the power is lost, while writing a record, the broken record is ignored
+//! and the previous value is kept.
+//! The header of a new sector is written after all values were copied,
+//! so a power loss while moving to the next sector does also keep the old sector active.
+//!
+//! ⚠️ CircuitPython uses the whole flash for its `CIRCUITPY` drive.
+//! Using the same region for settings destroys the drive.
+
+use core::{marker::PhantomData, ops::Range};
+use embedded_storage::nor_flash::NorFlash;
+
+/// Maximum size of a single value in bytes.
+pub const MAX_VALUE_SIZE: usize = 128;
+/// Maximum supported [`NorFlash::WRITE_SIZE`].
+const MAX_WRITE_SIZE: usize = 16;
+
+/// "PBST"
+const MAGIC: u32 = 0x5453_4250;
+/// magic (4 bytes), sequence number (4 bytes), schema version (2 bytes), crc (2 bytes)
+const SECTOR_HEADER_SIZE: usize = 12;
+/// key (2 bytes), kind (1 byte), length (1 byte), crc (2 bytes)
+const RECORD_HEADER_SIZE: usize = 6;
+const RECORD_BUF_SIZE: usize = RECORD_HEADER_SIZE + MAX_VALUE_SIZE + MAX_WRITE_SIZE;
+
+const KIND_VALUE: u8 = 0x01;
+const KIND_REMOVED: u8 = 0x00;
+
+/// Compare sequence numbers, which may have wrapped around.
+///
+/// The sequence numbers of all sectors are close to each other,
+/// so a small difference means newer.
+fn is_newer(sequence: u32, than: u32) -> bool {
+	(sequence.wrapping_sub(than) as i32) > 0
+}
+
+/// Errors, which can occur while accessing the [`Settings`].
+#[derive(Debug)]
+pub enum Error<E> {
+	/// Error of the underlying storage.
+	Storage(E),
+	/// The region is not aligned to the erase size, contains less than two sectors
+	/// or the storage is not supported.
+	InvalidRegion,
+	/// The value needs more than [`MAX_VALUE_SIZE`] bytes.
+	TooLarge,
+	/// There is no space left, even after old values were removed.
+	Full,
+	/// The stored value can not be converted to the requested type.
+	WrongType
+}
+
+/// Type, which can be stored at the [`Settings`].
+///
+/// Is implemented for the primitive number types, [`bool`], byte arrays and [`heapless::String`].
+pub trait Value: Sized {
+	/// Write the value to `buf` and return the number of used bytes.
+	/// Return `None`, if `buf` is to small.
+	fn encode(&self, buf: &mut [u8]) -> Option<usize>;
+
+	/// Create the value from the bytes created by [`encode()`](Value::encode).
+	/// Return `None`, if the bytes are invalid.
+	fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_value_num {
+	($($type:ty),*) => {
+		$(
+			impl Value for $type {
+				fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+					let bytes = self.to_le_bytes();
+					buf.get_mut(..bytes.len())?.copy_from_slice(&bytes);
+					Some(bytes.len())
+				}
+
+				fn decode(bytes: &[u8]) -> Option<Self> {
+					Some(Self::from_le_bytes(bytes.try_into().ok()?))
+				}
+			}
+		)*
+	};
+}
+
+impl_value_num!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Value for bool {
+	fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+		(*self as u8).encode(buf)
+	}
+
+	fn decode(bytes: &[u8]) -> Option<Self> {
+		match u8::decode(bytes)? {
+			0 => Some(false),
+			1 => Some(true),
+			_ => None
+		}
+	}
+}
+
+impl<const N: usize> Value for [u8; N] {
+	fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+		buf.get_mut(..N)?.copy_from_slice(self);
+		Some(N)
+	}
+
+	fn decode(bytes: &[u8]) -> Option<Self> {
+		bytes.try_into().ok()
+	}
+}
+
+impl<const N: usize> Value for heapless::String<N> {
+	fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+		buf.get_mut(..self.len())?.copy_from_slice(self.as_bytes());
+		Some(self.len())
+	}
+
+	fn decode(bytes: &[u8]) -> Option<Self> {
+		let mut string = heapless::String::new();
+		string.push_str(core::str::from_utf8(bytes).ok()?).ok()?;
+		Some(string)
+	}
+}
+
+/// Typed key of a setting.
+///
+/// Each setting needs its own id.
+/// The id `0xFFFF` is reserved.
+/// ```
+/// const BRIGHTNESS: Key<u8> = Key::new(1);
+/// const USER_NAME: Key<heapless::String<16>> = Key::new(2);
+/// ```
+pub struct Key<T> {
+	id: u16,
+	value: PhantomData<fn() -> T>
+}
+
+impl<T> Key<T> {
+	pub const fn new(id: u16) -> Self {
+		assert!(id != 0xFFFF, "key id 0xFFFF is reserved");
+		Self {
+			id,
+			value: PhantomData
+		}
+	}
+
+	pub const fn id(&self) -> u16 {
+		self.id
+	}
+}
+
+impl<T> Clone for Key<T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<T> Copy for Key<T> {}
+
+/// Header of a record read from storage.
+struct Record {
+	key: u16,
+	kind: u8,
+	len: usize,
+	/// checksum does match
+	valid: bool,
+	/// bytes used by the record inclusive padding
+	size: u32
+}
+
+/// Persistent key/value store.
+///
+/// See the [module documentation](self) for more details.
+pub struct Settings<S: NorFlash> {
+	storage: S,
+	start: u32,
+	sector_size: u32,
+	sector_count: u32,
+	/// index of the active sector
+	active: u32,
+	/// sequence number of the active sector
+	sequence: u32,
+	schema_version: u16,
+	/// offset of the first free byte inside the active sector
+	write_pos: u32
+}
+
+impl<S: NorFlash> Settings<S> {
+	/// Load the settings from the `region` of the `storage`.
+	///
+	/// If the region does not contain any settings yet, it is formatted
+	/// and the [schema version](Self::schema_version) is set to 0.
+	///
+	/// The region must be aligned to [`NorFlash::ERASE_SIZE`] and contain at least two sectors.
+	pub fn new(storage: S, region: Range<u32>) -> Result<Self, Error<S::Error>> {
+		let sector_size = S::ERASE_SIZE as u32;
+		if S::READ_SIZE != 1
+			|| S::WRITE_SIZE > MAX_WRITE_SIZE
+			|| region.start % sector_size != 0
+			|| region.end % sector_size != 0
+			|| region.end as usize > storage.capacity()
+			|| region.end <= region.start
+			|| (region.end - region.start) / sector_size < 2
+		{
+			return Err(Error::InvalidRegion);
+		}
+		let mut settings = Self {
+			storage,
+			start: region.start,
+			sector_size,
+			sector_count: (region.end - region.start) / sector_size,
+			active: 0,
+			sequence: 0,
+			schema_version: 0,
+			write_pos: 0
+		};
+
+		// use the sector with the newest valid header
+		let mut newest = None;
+		for sector in 0..settings.sector_count {
+			if let Some((sequence, schema_version)) =
+				settings.read_sector_header(sector)?
+			{
+				let is_newer = match newest {
+					Some((_, newest_sequence, _)) => is_newer(sequence, newest_sequence),
+					None => true
+				};
+				if is_newer {
+					newest = Some((sector, sequence, schema_version));
+				}
+			}
+		}
+		match newest {
+			Some((sector, sequence, schema_version)) => {
+				settings.active = sector;
+				settings.sequence = sequence;
+				settings.schema_version = schema_version;
+				let mut pos = settings.first_record();
+				while let Some(record) = settings.read_record(sector, pos, &mut [])? {
+					pos += record.size;
+				}
+				settings.write_pos = pos;
+			},
+			None => {
+				settings
+					.storage
+					.erase(region.start, region.start + sector_size)
+					.map_err(Error::Storage)?;
+				settings.write_sector_header(0, 0, 0)?;
+				settings.write_pos = settings.first_record();
+			}
+		}
+		Ok(settings)
+	}
+
+	/// Release the underlying storage.
+	pub fn release(self) -> S {
+		self.storage
+	}
+
+	/// Returns the schema version of the stored settings.
+	///
+	/// The version can be used to migrate old settings after a firmware upgrade.
+	/// ```
+	/// if settings.schema_version() < 2 {
+	/// 	let volume = settings.get(OLD_VOLUME)?.unwrap_or(0);
+	/// 	settings.set(VOLUME, &(volume as u16 * 256))?;
+	/// 	settings.remove(OLD_VOLUME)?;
+	/// 	settings.set_schema_version(2)?;
+	/// }
+	/// ```
+	pub fn schema_version(&self) -> u16 {
+		self.schema_version
+	}
+
+	/// Store a new schema version.
+	///
+	/// This does move the settings to the next sector and need a sector erase.
+	pub fn set_schema_version(
+		&mut self,
+		schema_version: u16
+	) -> Result<(), Error<S::Error>> {
+		self.compact(schema_version)
+	}
+
+	/// Returns the value of `key`, or `None` if the value was never set or has been removed.
+	///
+	/// # Errors
+	///
+	/// * [`WrongType`](Error::WrongType) - the stored value can not be converted to `T`.
+	pub fn get<T: Value>(&mut self, key: Key<T>) -> Result<Option<T>, Error<S::Error>> {
+		let mut buf = [0_u8; MAX_VALUE_SIZE];
+		match self.get_raw(key.id, &mut buf)? {
+			Some(len) => T::decode(&buf[..len]).map(Some).ok_or(Error::WrongType),
+			None => Ok(None)
+		}
+	}
+
+	/// Returns true, if `key` has a value.
+	pub fn contains<T>(&mut self, key: Key<T>) -> Result<bool, Error<S::Error>> {
+		Ok(self.get_raw(key.id, &mut [0_u8; MAX_VALUE_SIZE])?.is_some())
+	}
+
+	/// Store `value` for `key`.
+	///
+	/// Nothing is written, if the same value is already stored.
+	///
+	/// # Errors
+	///
+	/// * [`TooLarge`](Error::TooLarge) - the value needs more than [`MAX_VALUE_SIZE`] bytes.
+	/// * [`Full`](Error::Full) - there is no space left for the value.
+	pub fn set<T: Value>(
+		&mut self,
+		key: Key<T>,
+		value: &T
+	) -> Result<(), Error<S::Error>> {
+		let mut buf = [0_u8; MAX_VALUE_SIZE];
+		let len = value.encode(&mut buf).ok_or(Error::TooLarge)?;
+		let mut old = [0_u8; MAX_VALUE_SIZE];
+		if self.get_raw(key.id, &mut old)? == Some(len) && old[..len] == buf[..len] {
+			return Ok(());
+		}
+		self.append(key.id, KIND_VALUE, &buf[..len])
+	}
+
+	/// Remove the value of `key`.
+	pub fn remove<T>(&mut self, key: Key<T>) -> Result<(), Error<S::Error>> {
+		if self.get_raw(key.id, &mut [0_u8; MAX_VALUE_SIZE])?.is_none() {
+			return Ok(());
+		}
+		self.append(key.id, KIND_REMOVED, &[])
+	}
+
+	/// Copy the newest value of `key` to `buf` and return its length.
+	fn get_raw(
+		&mut self,
+		key: u16,
+		buf: &mut [u8; MAX_VALUE_SIZE]
+	) -> Result<Option<usize>, Error<S::Error>> {
+		let mut value = None;
+		let mut data = [0_u8; MAX_VALUE_SIZE];
+		let mut pos = self.first_record();
+		while let Some(record) = self.read_record(self.active, pos, &mut data)? {
+			if record.valid && record.key == key {
+				value = match record.kind {
+					KIND_VALUE => {
+						buf[..record.len].copy_from_slice(&data[..record.len]);
+						Some(record.len)
+					},
+					_ => None
+				};
+			}
+			pos += record.size;
+		}
+		Ok(value)
+	}
+
+	/// Append a record to the active sector.
+	/// Move to the next sector first, if there is not enough space left.
+	fn append(&mut self, key: u16, kind: u8, data: &[u8]) -> Result<(), Error<S::Error>> {
+		let mut buf = [0xFF_u8; RECORD_BUF_SIZE];
+		buf[0..2].copy_from_slice(&key.to_le_bytes());
+		buf[2] = kind;
+		buf[3] = data.len() as u8;
+		buf[RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + data.len()].copy_from_slice(data);
+		let crc = record_crc(key, kind, data);
+		buf[4..6].copy_from_slice(&crc.to_le_bytes());
+		let size = self.align(RECORD_HEADER_SIZE + data.len());
+
+		if self.write_pos + size > self.sector_size {
+			self.compact(self.schema_version)?;
+			if self.write_pos + size > self.sector_size {
+				return Err(Error::Full);
+			}
+		}
+		let addr = self.sector_addr(self.active) + self.write_pos;
+		// the space is consumed, even if the write fails
+		self.write_pos += size;
+		self.storage
+			.write(addr, &buf[..size as usize])
+			.map_err(Error::Storage)
+	}
+
+	/// Copy the newest values to the next sector and make it the active one.
+	fn compact(&mut self, schema_version: u16) -> Result<(), Error<S::Error>> {
+		let old = self.active;
+		let new = (old + 1) % self.sector_count;
+		let new_addr = self.sector_addr(new);
+		self.storage
+			.erase(new_addr, new_addr + self.sector_size)
+			.map_err(Error::Storage)?;
+
+		let mut write_pos = self.first_record();
+		let mut pos = self.first_record();
+		let mut buf = [0_u8; RECORD_BUF_SIZE];
+		while let Some(record) = self.read_record(old, pos, &mut [])? {
+			if record.valid
+				&& record.kind == KIND_VALUE
+				&& !self.is_overwritten(old, pos + record.size, record.key)?
+			{
+				let record_buf = &mut buf[..record.size as usize];
+				self.storage
+					.read(self.sector_addr(old) + pos, record_buf)
+					.map_err(Error::Storage)?;
+				self.storage
+					.write(new_addr + write_pos, record_buf)
+					.map_err(Error::Storage)?;
+				write_pos += record.size;
+			}
+			pos += record.size;
+		}
+
+		// the header must be written last.
+		// Otherwise the new sector could become active with missing values after a power loss.
+		let sequence = self.sequence.wrapping_add(1);
+		self.write_sector_header(new, sequence, schema_version)?;
+		self.active = new;
+		self.sequence = sequence;
+		self.schema_version = schema_version;
+		self.write_pos = write_pos;
+		Ok(())
+	}
+
+	/// Check if a valid record of `key` exists after `pos`.
+	fn is_overwritten(
+		&mut self,
+		sector: u32,
+		mut pos: u32,
+		key: u16
+	) -> Result<bool, Error<S::Error>> {
+		let mut data = [0_u8; MAX_VALUE_SIZE];
+		while let Some(record) = self.read_record(sector, pos, &mut data)? {
+			if record.valid && record.key == key {
+				return Ok(true);
+			}
+			pos += record.size;
+		}
+		Ok(false)
+	}
+
+	/// Read the record at `pos` of `sector` and its data to `data`.
+	/// If `data` is empty, the data is read into a temporary buffer.
+	///
+	/// Return `None` at the end of the written area.
+	fn read_record(
+		&mut self,
+		sector: u32,
+		pos: u32,
+		data: &mut [u8]
+	) -> Result<Option<Record>, Error<S::Error>> {
+		if pos + RECORD_HEADER_SIZE as u32 > self.sector_size {
+			return Ok(None);
+		}
+		let addr = self.sector_addr(sector) + pos;
+		let mut header = [0_u8; RECORD_HEADER_SIZE];
+		self.storage
+			.read(addr, &mut header)
+			.map_err(Error::Storage)?;
+		if header.iter().all(|byte| *byte == 0xFF) {
+			return Ok(None);
+		}
+		let key = u16::from_le_bytes([header[0], header[1]]);
+		let kind = header[2];
+		let len = header[3] as usize;
+		let size = self.align(RECORD_HEADER_SIZE + len);
+		if len > MAX_VALUE_SIZE || pos + size > self.sector_size {
+			// the header was not written completely.
+			// Because the size is unknown, the rest of the sector can not be used anymore.
+			return Ok(Some(Record {
+				key,
+				kind,
+				len: 0,
+				valid: false,
+				size: self.sector_size - pos
+			}));
+		}
+		let mut tmp = [0_u8; MAX_VALUE_SIZE];
+		let data = if data.is_empty() { &mut tmp[..] } else { data };
+		let data = &mut data[..len];
+		self.storage
+			.read(addr + RECORD_HEADER_SIZE as u32, data)
+			.map_err(Error::Storage)?;
+		let crc = u16::from_le_bytes([header[4], header[5]]);
+		Ok(Some(Record {
+			key,
+			kind,
+			len,
+			valid: crc == record_crc(key, kind, data),
+			size
+		}))
+	}
+
+	/// Returns sequence number and schema version, if the sector has a valid header.
+	fn read_sector_header(
+		&mut self,
+		sector: u32
+	) -> Result<Option<(u32, u16)>, Error<S::Error>> {
+		let mut header = [0_u8; SECTOR_HEADER_SIZE];
+		self.storage
+			.read(self.sector_addr(sector), &mut header)
+			.map_err(Error::Storage)?;
+		let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+		let crc = u16::from_le_bytes([header[10], header[11]]);
+		if magic != MAGIC || crc != crc16(&header[..10], 0xFFFF) {
+			return Ok(None);
+		}
+		let sequence = u32::from_le_bytes(header[4..8].try_into().unwrap());
+		let schema_version = u16::from_le_bytes([header[8], header[9]]);
+		Ok(Some((sequence, schema_version)))
+	}
+
+	fn write_sector_header(
+		&mut self,
+		sector: u32,
+		sequence: u32,
+		schema_version: u16
+	) -> Result<(), Error<S::Error>> {
+		let mut header = [0xFF_u8; SECTOR_HEADER_SIZE + MAX_WRITE_SIZE];
+		header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+		header[4..8].copy_from_slice(&sequence.to_le_bytes());
+		header[8..10].copy_from_slice(&schema_version.to_le_bytes());
+		let crc = crc16(&header[..10], 0xFFFF);
+		header[10..12].copy_from_slice(&crc.to_le_bytes());
+		let size = self.align(SECTOR_HEADER_SIZE) as usize;
+		self.storage
+			.write(self.sector_addr(sector), &header[..size])
+			.map_err(Error::Storage)
+	}
+
+	fn sector_addr(&self, sector: u32) -> u32 {
+		self.start + sector * self.sector_size
+	}
+
+	/// offset of the first record inside a sector
+	fn first_record(&self) -> u32 {
+		self.align(SECTOR_HEADER_SIZE)
+	}
+
+	/// Round `size` up to a multiple of [`NorFlash::WRITE_SIZE`].
+	fn align(&self, size: usize) -> u32 {
+		(size.div_ceil(S::WRITE_SIZE) * S::WRITE_SIZE) as u32
+	}
+}
+
+fn record_crc(key: u16, kind: u8, data: &[u8]) -> u16 {
+	let key = key.to_le_bytes();
+	let crc = crc16(&[key[0], key[1], kind, data.len() as u8], 0xFFFF);
+	crc16(data, crc)
+}
+
+/// CRC-16/CCITT-FALSE
+fn crc16(bytes: &[u8], mut crc: u16) -> u16 {
+	for byte in bytes {
+		crc ^= (*byte as u16) << 8;
+		for _ in 0..8 {
+			if crc & 0x8000 != 0 {
+				crc = (crc << 1) ^ 0x1021;
+			} else {
+				crc <<= 1;
+			}
+		}
+	}
+	crc
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::flash::RamFlash;
+
+	const A: Key<u32> = Key::new(1);
+	const B: Key<heapless::String<16>> = Key::new(2);
+	const REGION: Range<u32> = 4096..16384;
+
+	#[test]
+	fn set_get_remove() {
+		let mut settings = Settings::new(RamFlash::<16384>::new(), REGION).unwrap();
+		assert_eq!(settings.get(A).unwrap(), None);
+		// enough values to move through all sectors multiple times
+		for i in 0..2000 {
+			settings.set(A, &i).unwrap();
+			assert_eq!(settings.get(A).unwrap(), Some(i));
+		}
+		settings.set(B, &"hello".into()).unwrap();
+		settings.set_schema_version(3).unwrap();
+
+		let mut settings = Settings::new(settings.release(), REGION).unwrap();
+		assert_eq!(settings.schema_version(), 3);
+		assert_eq!(settings.get(A).unwrap(), Some(1999));
+		assert_eq!(settings.get(B).unwrap().unwrap().as_str(), "hello");
+		assert!(matches!(
+			settings.get(Key::<u8>::new(2)),
+			Err(Error::WrongType)
+		));
+		settings.remove(A).unwrap();
+		assert_eq!(settings.get(A).unwrap(), None);
+
+		let mut settings = Settings::new(settings.release(), REGION).unwrap();
+		assert_eq!(settings.get(A).unwrap(), None);
+	}
+
+	#[test]
+	fn sequence_wraps_around() {
+		let mut settings = Settings::new(RamFlash::<16384>::new(), REGION).unwrap();
+		settings.sequence = u32::MAX - 1;
+		for i in 0..2000 {
+			settings.set(A, &i).unwrap();
+		}
+		assert!(settings.sequence < 100);
+		let mut settings = Settings::new(settings.release(), REGION).unwrap();
+		assert_eq!(settings.get(A).unwrap(), Some(1999));
+	}
+
+	#[test]
+	fn power_cut() {
+		// fill the active sector, so the next values are moved to the next sector
+		let mut settings = Settings::new(RamFlash::<16384>::new(), REGION).unwrap();
+		settings.set(B, &"unchanged".into()).unwrap();
+		let mut first = 0;
+		while settings.write_pos + 64 < settings.sector_size {
+			settings.set(A, &first).unwrap();
+			first += 1;
+		}
+		let full = settings.release();
+
+		// cut the power at every byte of the following writes, including the compaction
+		for cut in 0..300 {
+			let mut flash =
+				RamFlash::<16384>::from_bytes(full.as_bytes().try_into().unwrap());
+			flash.cut_power_after(cut);
+			let mut settings = Settings::new(flash, REGION).unwrap();
+			let mut last_ok = first - 1;
+			let mut cut_off = false;
+			for i in first..first + 20 {
+				if settings.set(A, &i).is_err() {
+					cut_off = true;
+					break;
+				}
+				last_ok = i;
+			}
+			let mut flash = settings.release();
+			flash.restore_power();
+
+			let mut settings = Settings::new(flash, REGION).unwrap();
+			let value = settings.get(A).unwrap().unwrap();
+			if cut_off {
+				// either the old or the new value
+				assert!(
+					value == last_ok || value == last_ok + 1,
+					"cut at {cut}: {value}"
+				);
+			} else {
+				assert_eq!(value, first + 19);
+				assert_ne!(settings.active, 0);
+			}
+			assert_eq!(
+				settings.get(B).unwrap().unwrap().as_str(),
+				"unchanged",
+				"cut at {cut}"
+			);
+			// the settings stay writable
+			settings.set(A, &5).unwrap();
+			assert_eq!(settings.get(A).unwrap(), Some(5));
+		}
+	}
+}
@@ -0,0 +1,136 @@
+//! Detection of stack overflows and measurement of the stack usage.
+//!
+//! The stack grows downwards from the end of the ram towards the static variables.
+//! A region of the MPU without access rights is placed between them,
+//! so an overflow does cause a hard fault, instead of silently overwriting static variables.
+//!
+//! The guard region is placed at `__sheap`, where `cortex-m-rt` does start the heap.
+//! The ram between the guard and the stack is painted, to measure the stack usage.
+//! So no heap allocator can be used together with this module.
+
+use core::ops::Range;
+use cortex_m::peripheral::MPU;
+
+/// size of the guard region, must be a power of two and at least 32
+const GUARD_SIZE: u32 = 256;
+/// unused stack is filled with this pattern, to measure the high watermark
+const PAINT: u32 = 0xCAFE_F00D;
+/// space kept free below the stack pointer, while painting the stack
+const PAINT_MARGIN: u32 = 64;
+/// Stack, which is needed to show the bluescreen after a hard fault.
+/// If less stack is left, the stack is discarded before.
+pub(crate) const FAULT_STACK: u32 = 4096;
+
+extern "C" {
+	/// end of the static variables, defined by cortex-m-rt
+	static __sheap: u32;
+	/// start of the stack, defined by cortex-m-rt
+	static _stack_start: u32;
+}
+
+/// Highest address of the stack.
+pub(crate) fn stack_start() -> u32 {
+	unsafe { &_stack_start as *const u32 as u32 }
+}
+
+/// Address range of the guard region.
+pub(crate) fn guard() -> Range<u32> {
+	let start = unsafe { &__sheap as *const u32 as u32 };
+	// the region must be aligned to its size
+	let start = (start + GUARD_SIZE - 1) & !(GUARD_SIZE - 1);
+	start..start + GUARD_SIZE
+}
+
+/// Protect the guard region by the MPU and fill the unused stack with a pattern.
+pub(crate) fn init() {
+	let guard = guard();
+	let mpu = unsafe { &*MPU::PTR };
+	unsafe {
+		mpu.rnr.write(0);
+		mpu.rbar.write(guard.start);
+		// execute never, no access, size 2^(SIZE+1), enable
+		let size = GUARD_SIZE.trailing_zeros() - 1;
+		mpu.rasr.write(1 << 28 | size << 1 | 1);
+		// enable the default memory map for everything else,
+		// the MPU is disabled inside the hard fault handler
+		mpu.ctrl.write(1 << 2 | 1);
+	}
+	cortex_m::asm::dsb();
+	cortex_m::asm::isb();
+
+	let end = cortex_m::register::msp::read() - PAINT_MARGIN;
+	let mut address = guard.end;
+	while address < end {
+		unsafe { core::ptr::write_volatile(address as *mut u32, PAINT) };
+		address += 4;
+	}
+}
+
+/// Maximum number of bytes, which were used by the stack since [`PyBadge::take()`](crate::PyBadge::take()).
+///
+/// The stack has a size of the free ram, which is not used by static variables.
+/// If the stack overflows a bluescreen with "stack overflow" is shown.
+/// The value is only valid after [`PyBadge::take()`](crate::PyBadge::take()) was called.
+pub fn stack_high_watermark() -> usize {
+	let mut address = guard().end;
+	let end = stack_start();
+	while address < end
+		&& unsafe { core::ptr::read_volatile(address as *const u32) } == PAINT
+	{
+		address += 4;
+	}
+	(end - address) as usize
+}
+
+/// Maximum size of the stack in bytes.
+pub fn stack_size() -> usize {
+	(stack_start() - guard().end) as usize
+}
+
+/// Bytes between the stack pointer and the guard region.
+pub(crate) fn remaining() -> u32 {
+	cortex_m::register::msp::read().saturating_sub(guard().end)
+}
+
+/// Reset the stack pointer to the start of the stack and call `handler` with `arg`.
+///
+/// # Safety
+/// Must only be called from the hard fault handler, because the current stack is discarded.
+#[cfg(target_arch = "arm")]
+pub(crate) unsafe fn reset(arg: u32, handler: extern "C" fn(u32) -> !) -> ! {
+	core::arch::asm!(
+		"msr msp, {stack}",
+		"bx {handler}",
+		stack = in(reg) stack_start(),
+		handler = in(reg) handler,
+		in("r0") arg,
+		options(noreturn)
+	)
+}
+
+/// The stack can not be reset at the host, where only the tests are running.
+#[cfg(not(target_arch = "arm"))]
+pub(crate) unsafe fn reset(arg: u32, handler: extern "C" fn(u32) -> !) -> ! {
+	handler(arg)
+}
+
+/// Reset the stack pointer and show the bluescreen.
+///
+/// # Safety
+/// Must only be called from the hard fault handler, because the current stack is discarded.
+pub(crate) unsafe fn overflow() -> ! {
+	reset(0, stack_overflow)
+}
+
+extern "C" fn stack_overflow(_: u32) -> ! {
+	use core::fmt::Write;
+
+	let mut report = heapless::String::<64>::new();
+	write!(
+		report,
+		"stack overflow\n\nstack size {} bytes",
+		stack_size()
+	)
+	.ok();
+	crate::fatal_error(&report)
+}
@@ -1,31 +1,396 @@
 //! Time Units and current time
 
+use core::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
+#[cfg(feature = "time")]
+use core::ptr::{addr_of, addr_of_mut};
 #[cfg(feature = "time")]
 use cortex_m::peripheral::NVIC;
 pub use edgebadge::time::*;
 #[cfg(feature = "time")]
-use edgebadge::{pac, pac::TC5 as TC, prelude::*, thumbv7em::timer::TimerCounter};
+use edgebadge::{pac, pac::TC5 as TC, thumbv7em::timer::TimerCounter};
 #[cfg(feature = "time")]
 use pac::interrupt;
 
-//time since start of microntroller
 #[cfg(feature = "time")]
-static mut COUNT: Milliseconds = Milliseconds(0);
+mod clock;
+#[cfg(feature = "time")]
+pub use clock::{Clock, CountDownTimer};
+#[cfg(feature = "time")]
+mod timer;
+#[cfg(feature = "time")]
+pub use timer::{Timer, TimerContext, TimerHandle, MAX_TIMERS};
+#[cfg(feature = "time")]
+mod wheel;
+#[cfg(feature = "time")]
+pub use embedded_time;
+#[cfg(feature = "time")]
+pub use fugit;
+
+/// The counter runs with the 48 MHz of gclk1 divided by 16.
+#[cfg(feature = "time")]
+const TICKS_PER_MICRO: u64 = 3;
+/// Counter ticks between two ticks of the software timers.
+#[cfg(feature = "time")]
+const TICKS_PER_MILLI: u16 = 3000;
+
+///time since start of microntroller
+#[cfg(feature = "time")]
+static mut CLOCK: ExtendedCounter<Tc5Counter> = ExtendedCounter::new(Tc5Counter);
+
+/// A span of time with microsecond resolution.
+///
+/// Unlike [`Milliseconds`] it does not overflow in practice, because it is stored as 64 bit value.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Duration {
+	micros: u64
+}
+
+impl Duration {
+	pub const ZERO: Duration = Duration { micros: 0 };
+
+	pub const fn from_micros(micros: u64) -> Self {
+		Duration { micros }
+	}
+
+	pub const fn from_millis(millis: u64) -> Self {
+		Duration {
+			micros: millis * 1000
+		}
+	}
+
+	pub const fn from_secs(secs: u64) -> Self {
+		Duration {
+			micros: secs * 1_000_000
+		}
+	}
+
+	pub const fn as_micros(&self) -> u64 {
+		self.micros
+	}
+
+	pub const fn as_millis(&self) -> u64 {
+		self.micros / 1000
+	}
+
+	pub const fn as_secs(&self) -> u64 {
+		self.micros / 1_000_000
+	}
+
+	/// Seconds as floating point number, like it is used by physics calculations.
+	pub fn as_secs_f32(&self) -> f32 {
+		self.micros as f32 / 1_000_000.0
+	}
+
+	/// Return `None` instead of panicking, if the result would be negative.
+	pub const fn checked_sub(self, rhs: Duration) -> Option<Duration> {
+		match self.micros.checked_sub(rhs.micros) {
+			Some(micros) => Some(Duration { micros }),
+			None => None
+		}
+	}
+
+	/// Return [`Duration::ZERO`] instead of panicking, if the result would be negative.
+	pub const fn saturating_sub(self, rhs: Duration) -> Duration {
+		Duration {
+			micros: self.micros.saturating_sub(rhs.micros)
+		}
+	}
+}
+
+impl Add for Duration {
+	type Output = Duration;
+
+	fn add(self, rhs: Duration) -> Duration {
+		Duration {
+			micros: self.micros + rhs.micros
+		}
+	}
+}
+
+impl AddAssign for Duration {
+	fn add_assign(&mut self, rhs: Duration) {
+		*self = *self + rhs;
+	}
+}
+
+impl Sub for Duration {
+	type Output = Duration;
+
+	fn sub(self, rhs: Duration) -> Duration {
+		Duration {
+			micros: self.micros - rhs.micros
+		}
+	}
+}
+
+impl SubAssign for Duration {
+	fn sub_assign(&mut self, rhs: Duration) {
+		*self = *self - rhs;
+	}
+}
+
+impl Mul<u32> for Duration {
+	type Output = Duration;
+
+	fn mul(self, rhs: u32) -> Duration {
+		Duration {
+			micros: self.micros * rhs as u64
+		}
+	}
+}
+
+impl Div<u32> for Duration {
+	type Output = Duration;
+
+	fn div(self, rhs: u32) -> Duration {
+		Duration {
+			micros: self.micros / rhs as u64
+		}
+	}
+}
+
+impl From<Milliseconds> for Duration {
+	fn from(time: Milliseconds) -> Self {
+		Duration::from_millis(time.0 as u64)
+	}
+}
+
+impl From<Microseconds> for Duration {
+	fn from(time: Microseconds) -> Self {
+		Duration::from_micros(time.0 as u64)
+	}
+}
+
+impl From<Duration> for core::time::Duration {
+	fn from(time: Duration) -> Self {
+		core::time::Duration::from_micros(time.micros)
+	}
+}
+
+/// A point in time, measured since [`PyBadge::take()`](crate::PyBadge::take) was called.
+///
+/// The clock is monotonic and has a resolution of one microsecond.
+/// ```
+/// use pybadge_high::time::Instant;
+///
+/// let start = Instant::now();
+/// do_something();
+/// let time = start.elapsed();
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Instant {
+	micros: u64
+}
+
+impl Instant {
+	/// Current time.
+	#[cfg(feature = "time")]
+	pub fn now() -> Self {
+		let ticks = cortex_m::interrupt::free(|_cs| unsafe { (*addr_of!(CLOCK)).now() });
+		Instant {
+			micros: ticks / TICKS_PER_MICRO
+		}
+	}
+
+	#[cfg(feature = "time")]
+	pub(crate) const fn from_micros(micros: u64) -> Self {
+		Instant { micros }
+	}
+
+	/// Time since [`PyBadge::take()`](crate::PyBadge::take) was called.
+	pub const fn since_start(&self) -> Duration {
+		Duration::from_micros(self.micros)
+	}
+
+	/// Time since `self`.
+	#[cfg(feature = "time")]
+	pub fn elapsed(&self) -> Duration {
+		Instant::now().duration_since(*self)
+	}
+
+	/// Time between `earlier` and `self`, or [`Duration::ZERO`] if `earlier` is later than `self`.
+	pub fn duration_since(&self, earlier: Instant) -> Duration {
+		Duration::from_micros(self.micros.saturating_sub(earlier.micros))
+	}
+
+	/// Time between `earlier` and `self`, or `None` if `earlier` is later than `self`.
+	pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+		self.micros
+			.checked_sub(earlier.micros)
+			.map(Duration::from_micros)
+	}
+}
+
+impl Add<Duration> for Instant {
+	type Output = Instant;
+
+	fn add(self, rhs: Duration) -> Instant {
+		Instant {
+			micros: self.micros + rhs.micros
+		}
+	}
+}
+
+impl AddAssign<Duration> for Instant {
+	fn add_assign(&mut self, rhs: Duration) {
+		*self = *self + rhs;
+	}
+}
+
+impl Sub<Duration> for Instant {
+	type Output = Instant;
+
+	fn sub(self, rhs: Duration) -> Instant {
+		Instant {
+			micros: self.micros - rhs.micros
+		}
+	}
+}
+
+impl SubAssign<Duration> for Instant {
+	fn sub_assign(&mut self, rhs: Duration) {
+		*self = *self - rhs;
+	}
+}
+
+impl Sub for Instant {
+	type Output = Duration;
+
+	fn sub(self, rhs: Instant) -> Duration {
+		self.duration_since(rhs)
+	}
+}
+
+/// A 16 bit hardware counter, which counts up and wraps around.
+#[cfg(any(feature = "time", test))]
+pub(crate) trait Counter {
+	fn count(&self) -> u16;
+	/// Return true, if the counter has wrapped around, since the flag was cleared.
+	fn overflowed(&self) -> bool;
+	fn clear_overflow(&mut self);
+}
+
+/// Extend a 16 bit counter to 64 bit, by counting its overflows.
+#[cfg(any(feature = "time", test))]
+pub(crate) struct ExtendedCounter<C> {
+	counter: C,
+	overflows: u64
+}
+
+#[cfg(any(feature = "time", test))]
+impl<C: Counter> ExtendedCounter<C> {
+	pub(crate) const fn new(counter: C) -> Self {
+		ExtendedCounter {
+			counter,
+			overflows: 0
+		}
+	}
+
+	/// Must be called by the overflow interrupt.
+	pub(crate) fn on_overflow(&mut self) {
+		if self.counter.overflowed() {
+			self.counter.clear_overflow();
+			self.overflows += 1;
+		}
+	}
+
+	/// Current value of the extended counter.
+	///
+	/// Must be called with disabled interrupts, so the overflows are not changed meanwhile.
+	pub(crate) fn now(&self) -> u64 {
+		let mut overflows = self.overflows;
+		let mut count = self.counter.count();
+		if self.counter.overflowed() {
+			// the interrupt was not handled yet,
+			// so it is unknown if the count was read before or after the overflow
+			count = self.counter.count();
+			overflows += 1;
+		}
+		overflows << 16 | count as u64
+	}
+}
+
+/// TC5 in 16 bit mode
+#[cfg(feature = "time")]
+struct Tc5Counter;
+
+#[cfg(feature = "time")]
+impl Tc5Counter {
+	fn regs(&self) -> &pac::tc0::COUNT16 {
+		unsafe { TC::ptr().as_ref().unwrap().count16() }
+	}
+
+	/// Fire the compare interrupt every millisecond, to drive the software timers.
+	fn enable_tick(&self) {
+		let next = self.count().wrapping_add(TICKS_PER_MILLI);
+		let tc = self.regs();
+		tc.cc[0].write(|w| unsafe { w.cc().bits(next) });
+		while tc.syncbusy.read().cc0().bit_is_set() {}
+		tc.intflag.write(|w| w.mc0().set_bit());
+		tc.intenset.write(|w| w.mc0().set_bit());
+	}
+
+	fn disable_tick(&self) {
+		self.regs().intenclr.write(|w| w.mc0().set_bit());
+	}
+
+	/// Return true and move the compare value to the next millisecond,
+	/// if the compare interrupt has fired.
+	fn take_tick(&self) -> bool {
+		let tc = self.regs();
+		if tc.intflag.read().mc0().bit_is_clear() {
+			return false;
+		}
+		tc.intflag.write(|w| w.mc0().set_bit());
+		let next = tc.cc[0].read().cc().bits().wrapping_add(TICKS_PER_MILLI);
+		tc.cc[0].write(|w| unsafe { w.cc().bits(next) });
+		true
+	}
+}
+
+#[cfg(feature = "time")]
+impl Counter for Tc5Counter {
+	fn count(&self) -> u16 {
+		let tc = self.regs();
+		//the counter runs at a different clock domain and must be synchronized before reading
+		tc.ctrlbset.write(|w| w.cmd().readsync());
+		while tc.ctrlbset.read().cmd().bits() != 0 {}
+		tc.count.read().count().bits()
+	}
+
+	fn overflowed(&self) -> bool {
+		self.regs().intflag.read().ovf().bit_is_set()
+	}
+
+	fn clear_overflow(&mut self) {
+		self.regs().intflag.write(|w| w.ovf().set_bit());
+	}
+}
 
 ///return time since [`PyBadge::take()`](crate::PyBadge::take) was called.
 ///Can be used for time measurements.
 ///
-///Does overflow after 50 days uptime at release mode and panic after 50 days at debug mode!
+///Does wrap around after 49 days uptime. Use [`Instant`] for a higher resolution, which does not overflow.
 #[cfg(feature = "time")]
 pub fn uptime() -> Milliseconds {
-	unsafe { COUNT }
+	Milliseconds(Instant::now().since_start().as_millis() as u32)
 }
 
 #[cfg(feature = "time")]
-pub(crate) fn init_counter(mut counter: TimerCounter<TC>) {
-	let freq = Hertz(1000);
-	counter.start(freq);
-	counter.enable_interrupt();
+pub(crate) fn init_counter(_counter: TimerCounter<TC>) {
+	//the TimerCounter has enabled the clock, the mode is set directly,
+	//because it does only support counting to a compare value
+	let tc = Tc5Counter;
+	let tc = tc.regs();
+	tc.ctrla.modify(|_, w| w.enable().clear_bit());
+	while tc.syncbusy.read().enable().bit_is_set() {}
+	tc.ctrla
+		.modify(|_, w| w.mode().count16().prescaler().div16());
+	//count up to 0xFFFF and wrap around
+	tc.wave.write(|w| w.wavegen().nfrq());
+	tc.intenset.write(|w| w.ovf().set_bit());
+	tc.ctrla.modify(|_, w| w.enable().set_bit());
+	while tc.syncbusy.read().enable().bit_is_set() {}
 	unsafe {
 		NVIC::unmask(interrupt::TC5);
 	}
@@ -34,16 +399,95 @@ pub(crate) fn init_counter(mut counter: TimerCounter<TC>) {
 #[cfg(feature = "time")]
 #[interrupt]
 fn TC5() {
-	//clear intfalg, oterwise interrup is fired again at the next cycle
-	unsafe {
-		TC::ptr()
-			.as_ref()
-			.unwrap()
-			.count16()
-			.intflag
-			.modify(|_, w| w.ovf().set_bit());
+	//clears the intflag, oterwise interrup is fired again at the next cycle
+	cortex_m::interrupt::free(|_cs| unsafe { (*addr_of_mut!(CLOCK)).on_overflow() });
+	if cortex_m::interrupt::free(|_cs| Tc5Counter.take_tick()) {
+		timer::on_tick();
 	}
-	unsafe {
-		COUNT.0 += 1;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+
+	/// Counter, which advances by `step` at each read and sets the overflow flag, when it wraps around.
+	struct FakeCounter {
+		value: Cell<u32>,
+		step: u32,
+		overflowed: Cell<bool>
+	}
+
+	impl FakeCounter {
+		fn new(value: u16, step: u32, overflowed: bool) -> Self {
+			FakeCounter {
+				value: Cell::new(value as u32),
+				step,
+				overflowed: Cell::new(overflowed)
+			}
+		}
+	}
+
+	impl Counter for FakeCounter {
+		fn count(&self) -> u16 {
+			let value = self.value.get();
+			let next = value + self.step;
+			if next > 0xFFFF {
+				self.overflowed.set(true);
+			}
+			self.value.set(next & 0xFFFF);
+			value as u16
+		}
+
+		fn overflowed(&self) -> bool {
+			self.overflowed.get()
+		}
+
+		fn clear_overflow(&mut self) {
+			self.overflowed.set(false);
+		}
+	}
+
+	#[test]
+	fn monotonic_across_overflows() {
+		// the interrupt is handled only at every `interrupt_every` read,
+		// like if the interrupts are disabled for some time
+		for (step, interrupt_every) in [(1, 3), (7, 3), (1000, 3), (20000, 1)] {
+			for start in [0, 0x8000, 0xFFF0, 0xFFFF] {
+				let mut counter =
+					ExtendedCounter::new(FakeCounter::new(start, step, false));
+				let mut last = 0;
+				for i in 0..100_000 {
+					let now = counter.now();
+					assert!(now >= last, "step {step}, start {start}: {now} < {last}");
+					last = now;
+					if i % interrupt_every == 0 {
+						counter.on_overflow();
+					}
+				}
+				// about one read per step
+				assert!(last >= 100_000 * step as u64 / 2);
+			}
+		}
+	}
+
+	#[test]
+	fn overflow_pending_during_now() {
+		// the overflow interrupt was not handled yet
+		let counter = ExtendedCounter::new(FakeCounter::new(5, 0, true));
+		assert_eq!(counter.now(), 1 << 16 | 5);
+
+		// the counter wraps around between the first read and the check of the flag,
+		// so the count must be read again
+		let counter = ExtendedCounter::new(FakeCounter::new(0xFFFF, 1, false));
+		assert_eq!(counter.now(), 1 << 16);
+
+		// the interrupt does count the overflow, the next read is the same
+		let mut counter = ExtendedCounter::new(FakeCounter::new(0xFFFF, 1, false));
+		let before = counter.now();
+		counter.on_overflow();
+		assert_eq!(counter.now(), before + 1);
+		counter.on_overflow();
+		assert_eq!(counter.now(), before + 2);
 	}
 }
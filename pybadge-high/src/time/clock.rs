@@ -0,0 +1,127 @@
+//! Implementation of the clock and timer traits of the embedded ecosystem,
+//! so crates, which need a monotonic time source, can use the [`Instant`] clock.
+
+use super::{Duration, Instant};
+use embedded_hal::timer::{CountDown, Periodic};
+use embedded_hal_1::delay::DelayNs;
+use embedded_time::{clock, fraction::Fraction};
+use void::Void;
+
+/// Monotonic clock with a resolution of one microsecond.
+///
+/// Implements the [`embedded_time::Clock`] and the [`DelayNs`] trait.
+/// The delay is busy waiting, like [`Delay`](crate::Delay), but can be used independent of it
+/// and by multiple drivers at the same time, because the clock can be copied.
+/// ```
+/// use pybadge_high::time::Clock;
+///
+/// let mut driver = SomeDriver::new(spi, Clock);
+/// let now: fugit::TimerInstantU64<1_000_000> = Clock.now();
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Clock;
+
+impl Clock {
+	/// Current time as [`fugit`] instant.
+	pub fn now(&self) -> fugit::TimerInstantU64<1_000_000> {
+		Instant::now().into()
+	}
+}
+
+impl embedded_time::Clock for Clock {
+	type T = u64;
+	const SCALING_FACTOR: Fraction = Fraction::new(1, 1_000_000);
+
+	fn try_now(&self) -> Result<embedded_time::Instant<Self>, clock::Error> {
+		Ok(embedded_time::Instant::new(
+			Instant::now().since_start().as_micros()
+		))
+	}
+}
+
+impl DelayNs for Clock {
+	fn delay_ns(&mut self, ns: u32) {
+		// one additional microsecond, because the current microsecond has already started
+		let end = Instant::now() + Duration::from_micros(ns.div_ceil(1000) as u64 + 1);
+		while Instant::now() < end {}
+	}
+}
+
+/// Periodic timer, which implements the [`CountDown`] trait of embedded-hal 0.2.
+///
+/// Unlike the hardware timers it is not limited to 16 bit,
+/// so any period can be used.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CountDownTimer {
+	period: Duration,
+	end: Instant
+}
+
+impl CountDownTimer {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl CountDown for CountDownTimer {
+	type Time = Duration;
+
+	fn start<T>(&mut self, count: T)
+	where
+		T: Into<Duration>
+	{
+		self.period = count.into();
+		self.end = Instant::now() + self.period;
+	}
+
+	fn wait(&mut self) -> nb::Result<(), Void> {
+		if Instant::now() < self.end {
+			return Err(nb::Error::WouldBlock);
+		}
+		// the next period starts at the end of the last one, so no time gets lost by late calls
+		self.end += self.period;
+		Ok(())
+	}
+}
+
+impl Periodic for CountDownTimer {}
+
+impl From<Instant> for fugit::TimerInstantU64<1_000_000> {
+	fn from(instant: Instant) -> Self {
+		fugit::TimerInstantU64::from_ticks(instant.since_start().as_micros())
+	}
+}
+
+impl From<fugit::TimerInstantU64<1_000_000>> for Instant {
+	fn from(instant: fugit::TimerInstantU64<1_000_000>) -> Self {
+		Instant::from_micros(instant.ticks())
+	}
+}
+
+impl From<Duration> for fugit::MicrosDurationU64 {
+	fn from(duration: Duration) -> Self {
+		fugit::MicrosDurationU64::from_ticks(duration.as_micros())
+	}
+}
+
+impl<const NOM: u32, const DENOM: u32> From<fugit::Duration<u64, NOM, DENOM>>
+	for Duration
+{
+	fn from(duration: fugit::Duration<u64, NOM, DENOM>) -> Self {
+		Duration::from_micros(duration.to_micros())
+	}
+}
+
+impl<const NOM: u32, const DENOM: u32> From<fugit::Duration<u32, NOM, DENOM>>
+	for Duration
+{
+	fn from(duration: fugit::Duration<u32, NOM, DENOM>) -> Self {
+		Duration::from_micros(duration.to_micros() as u64)
+	}
+}
+
+impl From<Duration> for embedded_time::duration::Microseconds<u64> {
+	fn from(duration: Duration) -> Self {
+		embedded_time::duration::Microseconds(duration.as_micros())
+	}
+}
@@ -0,0 +1,168 @@
+//! Software timers, which are driven by the TC5 interrupt.
+//!
+//! While at least one timer is active, the compare channel of TC5 fires every millisecond
+//! and advances the timer wheel.
+
+use super::{
+	wheel::{Handle, Wheel},
+	Duration, Instant, Tc5Counter
+};
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, Mutex};
+use heapless::{Deque, Vec};
+
+/// Maximum number of timers, which can be active at the same time.
+pub const MAX_TIMERS: usize = 16;
+/// number of slots of the wheel
+const SLOTS: usize = 32;
+/// Maximum number of callbacks, which wait for [`Timer::run_pending()`].
+const MAX_PENDING: usize = 32;
+
+type TimerWheel = Wheel<(fn(), TimerContext), MAX_TIMERS, SLOTS>;
+type Pending = Deque<fn(), MAX_PENDING>;
+
+static WHEEL: Mutex<RefCell<TimerWheel>> = Mutex::new(RefCell::new(Wheel::new()));
+static PENDING: Mutex<RefCell<Pending>> = Mutex::new(RefCell::new(Deque::new()));
+
+/// Where the callback of a [`Timer`] is called.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimerContext {
+	/// Directly at the timer interrupt.
+	///
+	/// The callback should be short, because it blocks the clock and other timers.
+	Interrupt,
+	/// At the next call of [`Timer::run_pending()`], usually at the main loop.
+	///
+	/// Up to 32 callbacks can wait, further callbacks are dropped.
+	MainLoop
+}
+
+/// Call a function after some time or periodically.
+///
+/// The timers have a resolution of one millisecond
+/// and do only work after [`PyBadge::take()`](crate::PyBadge::take) was called.
+/// ```
+/// use pybadge_high::time::{Duration, Timer, TimerContext};
+///
+/// let blink = Timer::every(
+/// 	Duration::from_millis(500),
+/// 	TimerContext::MainLoop,
+/// 	toggle_led
+/// )
+/// .unwrap();
+/// Timer::after(Duration::from_secs(5), TimerContext::Interrupt, || beep()).unwrap();
+/// loop {
+/// 	Timer::run_pending();
+/// 	if pressed {
+/// 		blink.cancel();
+/// 	}
+/// }
+/// ```
+pub struct Timer;
+
+impl Timer {
+	/// Call `callback` once after `delay`.
+	///
+	/// Returns `None`, if [`MAX_TIMERS`] timers are already active.
+	pub fn after(
+		delay: Duration,
+		context: TimerContext,
+		callback: fn()
+	) -> Option<TimerHandle> {
+		schedule(ticks(delay), 0, context, callback)
+	}
+
+	/// Call `callback` every `period`, until the timer is canceled.
+	///
+	/// If the callback could not be called in time, the missed periods are skipped.
+	/// Returns `None`, if [`MAX_TIMERS`] timers are already active.
+	pub fn every(
+		period: Duration,
+		context: TimerContext,
+		callback: fn()
+	) -> Option<TimerHandle> {
+		let period = ticks(period).max(1);
+		schedule(period, period, context, callback)
+	}
+
+	/// Call the callbacks of the expired [`TimerContext::MainLoop`] timers.
+	pub fn run_pending() {
+		while let Some(callback) =
+			interrupt::free(|cs| PENDING.borrow(cs).borrow_mut().pop_front())
+		{
+			callback();
+		}
+	}
+}
+
+/// Handle of an active timer, to cancel it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimerHandle(Handle);
+
+impl TimerHandle {
+	/// Stop the timer. Returns false, if it has already expired or was canceled.
+	///
+	/// Callbacks, which wait for [`Timer::run_pending()`], are still called.
+	pub fn cancel(self) -> bool {
+		interrupt::free(|cs| WHEEL.borrow(cs).borrow_mut().cancel(self.0))
+	}
+
+	/// Return true, if the timer has not expired and was not canceled.
+	pub fn is_active(&self) -> bool {
+		interrupt::free(|cs| WHEEL.borrow(cs).borrow().is_active(self.0))
+	}
+}
+
+/// A tick of the wheel is one millisecond. Round up, so the timer never expires too early.
+fn ticks(duration: Duration) -> u64 {
+	duration.as_micros().div_ceil(1000)
+}
+
+fn now() -> u64 {
+	Instant::now().since_start().as_millis()
+}
+
+fn schedule(
+	delay: u64,
+	period: u64,
+	context: TimerContext,
+	callback: fn()
+) -> Option<TimerHandle> {
+	let now = now();
+	interrupt::free(|cs| {
+		let mut wheel = WHEEL.borrow(cs).borrow_mut();
+		let was_empty = wheel.is_empty();
+		let handle = wheel.insert(now, (callback, context), delay, period)?;
+		if was_empty {
+			Tc5Counter.enable_tick();
+		}
+		Some(TimerHandle(handle))
+	})
+}
+
+/// Advance the wheel and call the expired timers. Called by the TC5 interrupt every millisecond.
+pub(crate) fn on_tick() {
+	let now = now();
+	let mut expired = Vec::<(fn(), TimerContext), MAX_TIMERS>::new();
+	interrupt::free(|cs| {
+		let mut wheel = WHEEL.borrow(cs).borrow_mut();
+		// can not fail, because each timer does expire at most once
+		wheel.advance(now, |timer| {
+			expired.push(timer).ok();
+		});
+		if wheel.is_empty() {
+			Tc5Counter.disable_tick();
+		}
+	});
+	// the callbacks are called outside of the critical section, so they can start new timers
+	for (callback, context) in expired {
+		match context {
+			TimerContext::Interrupt => callback(),
+			TimerContext::MainLoop => {
+				interrupt::free(|cs| {
+					PENDING.borrow(cs).borrow_mut().push_back(callback).ok()
+				});
+			}
+		}
+	}
+}
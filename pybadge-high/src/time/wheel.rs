@@ -0,0 +1,317 @@
+//! Hashed timer wheel with a fixed capacity.
+//!
+//! Each timer is stored at the slot of its deadline modulo the number of slots,
+//! so a tick does only look at the timers of one slot.
+//! The wheel does not access the hardware, the current tick is passed by the caller.
+
+/// marks the end of a list of entries
+const NONE: u8 = u8::MAX;
+
+#[derive(Clone, Copy)]
+struct Entry<T> {
+	value: Option<T>,
+	/// tick at which the timer expires
+	deadline: u64,
+	/// 0 for a one shot timer
+	period: u64,
+	/// next entry at the same slot
+	next: u8,
+	/// incremented when the entry is freed, so old handles become invalid
+	generation: u16
+}
+
+/// Reference to a timer of the wheel, which stays valid until the timer has expired or was canceled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Handle {
+	index: u8,
+	generation: u16
+}
+
+pub(crate) struct Wheel<T, const CAPACITY: usize, const SLOTS: usize> {
+	entries: [Entry<T>; CAPACITY],
+	/// first entry of each slot
+	heads: [u8; SLOTS],
+	/// last processed tick
+	now: u64,
+	len: usize
+}
+
+impl<T: Copy, const CAPACITY: usize, const SLOTS: usize> Wheel<T, CAPACITY, SLOTS> {
+	pub(crate) const fn new() -> Self {
+		assert!(CAPACITY < NONE as usize);
+		Wheel {
+			entries: [Entry {
+				value: None,
+				deadline: 0,
+				period: 0,
+				next: NONE,
+				generation: 0
+			}; CAPACITY],
+			heads: [NONE; SLOTS],
+			now: 0,
+			len: 0
+		}
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Add a timer, which expires `delay` ticks after `now`
+	/// and afterwards every `period` ticks, if `period` is not 0.
+	///
+	/// Returns `None`, if the wheel is full.
+	pub(crate) fn insert(
+		&mut self,
+		now: u64,
+		value: T,
+		delay: u64,
+		period: u64
+	) -> Option<Handle> {
+		if self.is_empty() {
+			// nothing was missed, while the wheel was not advanced
+			self.now = self.now.max(now);
+		}
+		let index = self
+			.entries
+			.iter()
+			.position(|entry| entry.value.is_none())?;
+		let entry = &mut self.entries[index];
+		entry.value = Some(value);
+		entry.period = period;
+		let deadline = now.max(self.now) + delay.max(1);
+		self.link(index as u8, deadline);
+		self.len += 1;
+		Some(Handle {
+			index: index as u8,
+			generation: self.entries[index].generation
+		})
+	}
+
+	pub(crate) fn is_active(&self, handle: Handle) -> bool {
+		let entry = &self.entries[handle.index as usize];
+		entry.value.is_some() && entry.generation == handle.generation
+	}
+
+	/// Remove the timer. Returns false, if it has already expired or was canceled.
+	pub(crate) fn cancel(&mut self, handle: Handle) -> bool {
+		if !self.is_active(handle) {
+			return false;
+		}
+		self.unlink(handle.index);
+		self.free(handle.index);
+		true
+	}
+
+	/// Process all ticks up to `now` and call `expired` with the value of each expired timer.
+	///
+	/// Each timer expires at most once per call, missed periods are skipped.
+	pub(crate) fn advance(&mut self, now: u64, mut expired: impl FnMut(T)) {
+		if self.is_empty() || now <= self.now {
+			self.now = self.now.max(now);
+			return;
+		}
+		let ticks = now - self.now;
+		// after a long pause each slot is visited once, checking against the current tick
+		let catch_up = ticks > SLOTS as u64;
+		for step in 1..=ticks.min(SLOTS as u64) {
+			let tick = self.now + step;
+			let limit = if catch_up { now } else { tick };
+			self.expire_slot((tick % SLOTS as u64) as usize, limit, now, &mut expired);
+		}
+		self.now = now;
+	}
+
+	/// Expire the timers of `slot` with a deadline up to `tick`.
+	fn expire_slot(
+		&mut self,
+		slot: usize,
+		tick: u64,
+		now: u64,
+		expired: &mut impl FnMut(T)
+	) {
+		// take the whole list and insert the remaining entries again
+		let mut index = core::mem::replace(&mut self.heads[slot], NONE);
+		while index != NONE {
+			let entry = self.entries[index as usize];
+			if entry.deadline > tick {
+				self.link(index, entry.deadline);
+			} else if entry.period == 0 {
+				self.free(index);
+			} else {
+				let mut deadline = entry.deadline + entry.period;
+				if deadline <= now {
+					// keep the phase, but skip the missed periods
+					deadline += ((now - deadline) / entry.period + 1) * entry.period;
+				}
+				self.link(index, deadline);
+			}
+			if entry.deadline <= tick {
+				if let Some(value) = entry.value {
+					expired(value);
+				}
+			}
+			index = entry.next;
+		}
+	}
+
+	/// Add the entry to the front of the slot of `deadline`.
+	fn link(&mut self, index: u8, deadline: u64) {
+		let slot = (deadline % SLOTS as u64) as usize;
+		let entry = &mut self.entries[index as usize];
+		entry.deadline = deadline;
+		entry.next = self.heads[slot];
+		self.heads[slot] = index;
+	}
+
+	fn unlink(&mut self, index: u8) {
+		let slot = (self.entries[index as usize].deadline % SLOTS as u64) as usize;
+		let next = self.entries[index as usize].next;
+		if self.heads[slot] == index {
+			self.heads[slot] = next;
+			return;
+		}
+		let mut current = self.heads[slot];
+		while current != NONE {
+			if self.entries[current as usize].next == index {
+				self.entries[current as usize].next = next;
+				return;
+			}
+			current = self.entries[current as usize].next;
+		}
+	}
+
+	fn free(&mut self, index: u8) {
+		let entry = &mut self.entries[index as usize];
+		entry.value = None;
+		entry.next = NONE;
+		entry.generation = entry.generation.wrapping_add(1);
+		self.len -= 1;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// few slots, so timers wrap around the wheel
+	type TestWheel = Wheel<u32, 8, 4>;
+
+	/// Advance tick by tick and return the tick and value of the expired timers.
+	fn run(wheel: &mut TestWheel, ticks: core::ops::Range<u64>) -> Vec<(u64, u32)> {
+		let mut expired = Vec::new();
+		for tick in ticks {
+			wheel.advance(tick, |value| expired.push((tick, value)));
+		}
+		expired.sort();
+		expired
+	}
+
+	#[test]
+	fn one_shot_and_periodic() {
+		let mut wheel = TestWheel::new();
+		wheel.insert(0, 1, 5, 0).unwrap();
+		wheel.insert(0, 2, 3, 3).unwrap();
+		// longer than the number of slots
+		wheel.insert(0, 3, 9, 0).unwrap();
+		assert_eq!(run(&mut wheel, 1..13), [
+			(3, 2),
+			(5, 1),
+			(6, 2),
+			(9, 2),
+			(9, 3),
+			(12, 2)
+		]);
+		// only the periodic timer is left
+		assert!(!wheel.is_empty());
+	}
+
+	#[test]
+	fn period_multiple_of_slots() {
+		let mut wheel = TestWheel::new();
+		wheel.insert(0, 1, 4, 4).unwrap();
+		wheel.insert(0, 2, 8, 8).unwrap();
+		assert_eq!(run(&mut wheel, 1..17), [
+			(4, 1),
+			(8, 1),
+			(8, 2),
+			(12, 1),
+			(16, 1),
+			(16, 2)
+		]);
+	}
+
+	#[test]
+	fn cancel_and_stale_handles() {
+		let mut wheel = TestWheel::new();
+		let first = wheel.insert(0, 1, 4, 0).unwrap();
+		let second = wheel.insert(0, 2, 4, 0).unwrap();
+		let third = wheel.insert(0, 3, 4, 0).unwrap();
+		assert!(wheel.cancel(second));
+		assert!(!wheel.cancel(second));
+		assert_eq!(run(&mut wheel, 1..5), [(4, 1), (4, 3)]);
+		// expired timers can not be canceled anymore
+		assert!(!wheel.is_active(first));
+		assert!(!wheel.cancel(third));
+		assert!(wheel.is_empty());
+
+		// the entries are reused, but the old handles stay invalid
+		let new = wheel.insert(4, 4, 1, 0).unwrap();
+		assert!(wheel.is_active(new));
+		assert!(!wheel.is_active(first) && !wheel.is_active(second));
+		assert!(!wheel.cancel(first));
+		assert!(wheel.cancel(new));
+		assert!(wheel.is_empty());
+	}
+
+	#[test]
+	fn cancel_inside_slot() {
+		let mut wheel = TestWheel::new();
+		let handles: Vec<_> = (0..5).map(|i| wheel.insert(0, i, 4, 0).unwrap()).collect();
+		assert!(wheel.cancel(handles[2]));
+		let values: Vec<_> = run(&mut wheel, 1..5).into_iter().map(|(_, v)| v).collect();
+		assert_eq!(values, [0, 1, 3, 4]);
+	}
+
+	#[test]
+	fn full() {
+		let mut wheel = TestWheel::new();
+		for i in 0..8 {
+			wheel.insert(0, i, 1, 0).unwrap();
+		}
+		assert!(wheel.insert(0, 8, 1, 0).is_none());
+	}
+
+	#[test]
+	fn catch_up_after_pause() {
+		let mut wheel = TestWheel::new();
+		wheel.insert(0, 1, 2, 0).unwrap();
+		wheel.insert(0, 2, 3, 5).unwrap();
+		wheel.insert(0, 3, 8, 8).unwrap();
+		// more ticks than slots, each timer expires once
+		let mut expired = Vec::new();
+		wheel.advance(100, |value| expired.push(value));
+		expired.sort();
+		assert_eq!(expired, [1, 2, 3]);
+		// the missed periods are skipped, but the phase is kept
+		assert_eq!(run(&mut wheel, 101..111), [(103, 2), (104, 3), (108, 2)]);
+	}
+
+	#[test]
+	fn insert_after_idle() {
+		let mut wheel = TestWheel::new();
+		// the wheel was not advanced, while it was empty
+		wheel.insert(1000, 7, 3, 0).unwrap();
+		assert_eq!(run(&mut wheel, 1001..1006), [(1003, 7)]);
+	}
+
+	#[test]
+	fn expire_once_per_advance() {
+		let mut wheel = TestWheel::new();
+		wheel.insert(0, 1, 1, 1).unwrap();
+		let mut count = 0;
+		wheel.advance(3, |_| count += 1);
+		assert_eq!(count, 1);
+		assert_eq!(run(&mut wheel, 4..7), [(4, 1), (5, 1), (6, 1)]);
+	}
+}
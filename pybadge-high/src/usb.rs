@@ -1,60 +1,654 @@
+#[cfg(feature = "usb_hid")]
+use crate::buttons::{Button, Buttons};
+#[cfg(feature = "time")]
+use crate::time::uptime;
+use crate::time::Milliseconds;
+#[cfg(feature = "usb_mass_storage")]
+use crate::{
+	fs::{BlockDevice, FlashDisk},
+	Flash
+};
+use core::{fmt, ptr::addr_of_mut};
 use cortex_m::peripheral::NVIC;
 use edgebadge::{hal, pac, pins::USB as UsbPins};
-use hal::{clock::GenericClockController, usb::UsbBus};
+use hal::clock::GenericClockController;
+pub use hal::usb::UsbBus;
 use pac::{interrupt, MCLK, USB as UsbPeripherals};
+/// Reexport of the `usb-device` crate, to implement own usb classes.
+pub use usb_device;
 pub use usb_device::UsbError;
-use usb_device::{bus::UsbBusAllocator, prelude::*};
-use usbd_serial::{SerialPort, USB_CLASS_CDC};
+use usb_device::{bus::UsbBusAllocator, class::UsbClass, prelude::*};
+use usbd_serial::USB_CLASS_CDC;
+pub use usbd_serial::{ParityType, StopBits};
+
+#[cfg(feature = "usb_hid")]
+pub mod gamepad;
+#[cfg(feature = "usb_hid")]
+use gamepad::GamepadReport;
+#[cfg(feature = "usb_hid")]
+mod hid;
+#[cfg(feature = "usb_hid")]
+use hid::{BootProtocol, Hid};
+#[cfg(feature = "usb_hid")]
+pub mod keyboard;
+#[cfg(feature = "usb_hid")]
+use keyboard::{KeyboardReport, Keymap};
+#[cfg(feature = "usb_hid")]
+pub mod mouse;
+#[cfg(feature = "usb_hid")]
+use mouse::MouseReport;
+#[cfg(feature = "usb_midi")]
+pub mod midi;
+#[cfg(feature = "usb_midi")]
+use midi::{MidiMessage, MidiPacket};
+#[cfg(feature = "usb_midi")]
+mod midi_streaming;
+#[cfg(feature = "usb_midi")]
+use midi_streaming::MidiStreaming;
+#[cfg(feature = "usb_mass_storage")]
+mod msc;
+#[cfg(feature = "usb_mass_storage")]
+use msc::{MassStorage, Pending};
+mod serial;
+use serial::BufferedSerial;
+
+/// Default of [`Usb::set_write_timeout()`].
+#[cfg(feature = "time")]
+pub const DEFAULT_WRITE_TIMEOUT: Milliseconds = Milliseconds(100);
+
+/// Maximum number of usb classes, which can be used at the same time.
+const MAX_CLASSES: usize = 8;
+/// Maximum number of own classes added by [`UsbBuilder::class()`].
+pub const MAX_CUSTOM_CLASSES: usize = 2;
+
+/// Function, which does create an own usb class.
+///
+/// See [`UsbBuilder::class()`].
+pub type ClassInit =
+	fn(&'static UsbBusAllocator<UsbBus>) -> &'static mut dyn UsbClass<UsbBus>;
 
 static mut USB_ALLOCATOR: Option<UsbBusAllocator<UsbBus>> = None;
 static mut USB_DEV: Option<UsbDevice<UsbBus>> = None;
-static mut USB_SERIAL: Option<SerialPort<UsbBus>> = None;
+static mut USB_SERIAL: Option<BufferedSerial<UsbBus>> = None;
 static mut INTERRUPT_HANDLER: Option<fn()> = None;
+static mut STATE_HANDLER: Option<fn(UsbState)> = None;
+static mut USB_STATE: UsbState = UsbState::Default;
+static mut USB_CUSTOM: heapless::Vec<&mut dyn UsbClass<UsbBus>, MAX_CUSTOM_CLASSES> =
+	heapless::Vec::new();
+#[cfg(feature = "usb_mass_storage")]
+static mut USB_DISK: Option<FlashDisk<Flash>> = None;
+#[cfg(feature = "usb_mass_storage")]
+static mut USB_MSC: Option<MassStorage<UsbBus, &'static mut FlashDisk<Flash>>> = None;
+#[cfg(feature = "usb_hid")]
+static mut USB_KEYBOARD: Option<Hid<UsbBus>> = None;
+#[cfg(feature = "usb_hid")]
+static mut USB_GAMEPAD: Option<Hid<UsbBus>> = None;
+#[cfg(feature = "usb_hid")]
+static mut USB_MOUSE: Option<Hid<UsbBus>> = None;
+#[cfg(feature = "usb_midi")]
+static mut USB_MIDI: Option<MidiStreaming<UsbBus>> = None;
+
+/// State of the connection to the usb host.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UsbState {
+	/// Not connected or not enumerated by the host yet.
+	Default,
+	/// The host has assigned an address, but has not configured the device yet.
+	Addressed,
+	/// Connected and ready to use.
+	Configured,
+	/// The host has suspended the bus, for example because it went to sleep.
+	///
+	/// Reading and writing is not possible until the bus is resumed.
+	Suspended
+}
+
+impl From<UsbDeviceState> for UsbState {
+	fn from(state: UsbDeviceState) -> Self {
+		match state {
+			UsbDeviceState::Default => UsbState::Default,
+			UsbDeviceState::Addressed => UsbState::Addressed,
+			UsbDeviceState::Configured => UsbState::Configured,
+			UsbDeviceState::Suspend => UsbState::Suspended
+		}
+	}
+}
+
+/// Serial settings requested by the host.
+///
+/// The settings have no effect on the usb transfer itself,
+/// but can be used to forward the data to an uart.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct LineCoding {
+	/// Baud rate in bits per second.
+	pub baud_rate: u32,
+	/// Number of data bits, 5, 6, 7, 8 or 16.
+	pub data_bits: u8,
+	pub stop_bits: StopBits,
+	pub parity: ParityType
+}
+
+/// `StopBits` and `ParityType` do not implement `Debug`, so their raw values are printed.
+impl core::fmt::Debug for LineCoding {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.debug_struct("LineCoding")
+			.field("baud_rate", &self.baud_rate)
+			.field("data_bits", &self.data_bits)
+			.field("stop_bits", &(self.stop_bits as u8))
+			.field("parity", &(self.parity as u8))
+			.finish()
+	}
+}
 
-/// USB connection for serial communication.
+/// USB device, with serial communication and the other classes enabled at the [`UsbBuilder`].
 #[non_exhaustive] // prevent the user from creating this struct manual, without calling init.
 				  // to make sure static varibale are Some.
-pub struct Usb {}
+pub struct Usb {
+	#[cfg(feature = "time")]
+	write_timeout: Option<Milliseconds>
+}
 
 impl Usb {
-	/// Polls the UsbBus for new events.
-	/// Return true if serial may have data available for reading, false otherwise.
+	fn new() -> Self {
+		Usb {
+			#[cfg(feature = "time")]
+			write_timeout: Some(DEFAULT_WRITE_TIMEOUT)
+		}
+	}
+
+	/// Polls the UsbBus for new events and all enabled classes.
+	/// Return true if any class may have data available for reading, false otherwise.
 	/// This should be called periodically as often as possible for the best data
 	/// rate, or preferably from an interrupt handler. Must be called at least once every 10
 	/// milliseconds while connected to the USB host to be USB compliant.
 	pub fn poll(&self) -> bool {
-		unsafe {
-			USB_DEV
-				.as_mut()
-				.unwrap()
-				.poll(&mut [USB_SERIAL.as_mut().unwrap()])
+		let usb_dev = unsafe { USB_DEV.as_mut().unwrap() };
+		let mut classes: heapless::Vec<&mut dyn UsbClass<UsbBus>, MAX_CLASSES> =
+			heapless::Vec::new();
+		if let Some(serial) = unsafe { (*addr_of_mut!(USB_SERIAL)).as_mut() } {
+			classes.push(serial).ok();
+		}
+		#[cfg(feature = "usb_mass_storage")]
+		if let Some(msc) = unsafe { USB_MSC.as_mut() } {
+			classes.push(msc).ok();
+		}
+		#[cfg(feature = "usb_hid")]
+		if let Some(keyboard) = unsafe { USB_KEYBOARD.as_mut() } {
+			classes.push(keyboard).ok();
+		}
+		#[cfg(feature = "usb_hid")]
+		if let Some(gamepad) = unsafe { USB_GAMEPAD.as_mut() } {
+			classes.push(gamepad).ok();
+		}
+		#[cfg(feature = "usb_hid")]
+		if let Some(mouse) = unsafe { USB_MOUSE.as_mut() } {
+			classes.push(mouse).ok();
+		}
+		#[cfg(feature = "usb_midi")]
+		if let Some(midi) = unsafe { USB_MIDI.as_mut() } {
+			classes.push(midi).ok();
+		}
+		for class in unsafe { USB_CUSTOM.iter_mut() } {
+			classes.push(&mut **class).ok();
+		}
+		let data = usb_dev.poll(&mut classes);
+		let state = UsbState::from(usb_dev.state());
+		if state != unsafe { USB_STATE } {
+			unsafe { USB_STATE = state };
+			if let Some(handler) = unsafe { STATE_HANDLER } {
+				handler(state);
+			}
 		}
+		data
 	}
 
-	/// Reads bytes from the port into `data` and returns the number of bytes read.
+	/// Current state of the connection to the host.
+	pub fn state(&self) -> UsbState {
+		cortex_m::interrupt::free(|_cs| {
+			unsafe { USB_DEV.as_ref().unwrap() }.state().into()
+		})
+	}
+
+	/// Return true, if the device is connected to a host, which has configured it.
+	pub fn connected(&self) -> bool {
+		self.state() == UsbState::Configured
+	}
+
+	/// Data terminal ready, which is set by the host while a terminal program has opened the
+	/// serial port.
+	///
+	/// Can be used to stop sending data, if nobody is listening.
+	/// Some programs do not set it, for example when the port was opened with hardware flow
+	/// control disabled.
+	/// Returns false if serial was disabled by [`UsbBuilder::serial()`].
+	pub fn dtr(&self) -> bool {
+		cortex_m::interrupt::free(|_cs| serial().is_ok_and(|serial| serial.dtr()))
+	}
+
+	/// Request to send, which is set by the host for hardware flow control.
+	/// Returns false if serial was disabled by [`UsbBuilder::serial()`].
+	pub fn rts(&self) -> bool {
+		cortex_m::interrupt::free(|_cs| serial().is_ok_and(|serial| serial.rts()))
+	}
+
+	/// Baud rate and other serial settings, which were requested by the host.
+	/// Returns `None` if serial was disabled by [`UsbBuilder::serial()`].
+	pub fn line_coding(&self) -> Option<LineCoding> {
+		cortex_m::interrupt::free(|_cs| {
+			let line_coding = serial().ok()?.line_coding();
+			Some(LineCoding {
+				baud_rate: line_coding.data_rate(),
+				data_bits: line_coding.data_bits(),
+				stop_bits: line_coding.stop_bits(),
+				parity: line_coding.parity_type()
+			})
+		})
+	}
+
+	/// Call `handler` each time the state of the connection has changed,
+	/// for example if the host was suspended or resumed.
+	///
+	/// The handler is called by [`poll()`](Usb::poll()), so it runs inside the usb interrupt,
+	/// if the interrupt is enabled.
+	/// ```
+	/// use pybadge_high::usb::UsbState;
+	///
+	/// static mut SUSPENDED: bool = false;
+	///
+	/// usb.set_state_handler(|state| unsafe { SUSPENDED = state == UsbState::Suspended });
+	/// ```
+	pub fn set_state_handler(&mut self, handler: fn(UsbState)) {
+		cortex_m::interrupt::free(|_cs| unsafe { STATE_HANDLER = Some(handler) })
+	}
+
+	/// Reads bytes from the rx buffer into `data` and returns the number of bytes read.
+	///
+	/// Received data is stored at a ring buffer by [`poll()`](Usb::poll()).
+	/// If the buffer is full, the host has to wait until there is space again, so no data is lost.
 	///
 	/// # Errors
 	///
 	/// * [`WouldBlock`](UsbError::WouldBlock) - No bytes available for reading.
-	///
-	/// Other errors from `usb-device` may also be propagated.
+	/// * [`InvalidState`](UsbError::InvalidState) - Serial was disabled by [`UsbBuilder::serial()`].
 	pub fn read(&mut self, data: &mut [u8]) -> Result<usize, UsbError> {
-		unsafe { USB_SERIAL.as_mut().unwrap().read(data) }
+		cortex_m::interrupt::free(|_cs| serial()?.read(data))
+	}
+
+	/// Read a single byte from the rx buffer.
+	///
+	/// Returns `None` if no byte is available or serial was disabled.
+	pub fn try_read_byte(&mut self) -> Option<u8> {
+		cortex_m::interrupt::free(|_cs| serial().ok()?.read_byte())
+	}
+
+	/// Number of received bytes, which can be read without waiting.
+	pub fn available(&self) -> usize {
+		cortex_m::interrupt::free(|_cs| serial().map_or(0, |serial| serial.available()))
+	}
+
+	/// Read a line, which was terminated by `\n` or `\r\n`.
+	///
+	/// The line is stored at `buf` without line ending.
+	/// Returns `None` if no complete line was received yet.
+	/// Characters, which do not fit into `buf`, and invalid UTF-8 are dropped.
+	/// If a line does not fit into the rx buffer, it is returned in parts.
+	/// ```
+	/// let mut buf = [0; 64];
+	/// loop {
+	/// 	if let Some(line) = usb.read_line(&mut buf) {
+	/// 		match line {
+	/// 			"on" => led.on(),
+	/// 			"off" => led.off(),
+	/// 			_ => {}
+	/// 		}
+	/// 	}
+	/// }
+	/// ```
+	pub fn read_line<'a>(&mut self, buf: &'a mut [u8]) -> Option<&'a str> {
+		let len = cortex_m::interrupt::free(|_cs| serial().ok()?.read_line(buf))?;
+		Some(valid_utf8(&buf[..len]))
+	}
+
+	/// Like [`read_line()`](Usb::read_line()), but the line is only removed from the rx buffer,
+	/// if `accept` returns true for it. Otherwise it is kept for the next read.
+	#[cfg(feature = "rtc")]
+	pub(crate) fn read_line_if<'a>(
+		&mut self,
+		buf: &'a mut [u8],
+		accept: impl FnOnce(&str) -> bool
+	) -> Option<&'a str> {
+		let len = cortex_m::interrupt::free(|_cs| {
+			serial()
+				.ok()?
+				.read_line_if(buf, |line| accept(valid_utf8(line)))
+		})?;
+		Some(valid_utf8(&buf[..len]))
 	}
 
-	/// Writes bytes from `data` into the port and returns the number of bytes written.
+	/// Writes bytes from `data` into the tx buffer and returns the number of bytes written.
+	///
+	/// The buffer is send to the host by [`poll()`](Usb::poll()).
+	/// Does not block. Use `write!()` or [`embedded_io::Write`] to wait until all bytes were written.
 	///
 	/// # Errors
 	///
 	/// * [`WouldBlock`](UsbError::WouldBlock) - No bytes could be written because the
-	///   buffers are full.
-	///
-	/// Other errors from `usb-device` may also be propagated.
+	///   buffer is full.
+	/// * [`InvalidState`](UsbError::InvalidState) - Serial was disabled by [`UsbBuilder::serial()`].
 	pub fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
-		unsafe { USB_SERIAL.as_mut().unwrap().write(data) }
+		cortex_m::interrupt::free(|_cs| serial()?.write(data))
+	}
+
+	/// Access the flash, which is shared with the host by
+	/// [`UsbBuilder::mass_storage()`].
+	///
+	/// Waits until the host has finished its current command, so the firmware and the host
+	/// never access the flash at the same time.
+	/// While `f` is running, the host is told that the medium is not ready.
+	/// Afterwards cached data is written to the flash and the host is informed, that the medium
+	/// has changed. So it does reload the filesystem.
+	/// Because the operation system of the host does cache files,
+	/// the filesystem should still only be changed by one side at once.
+	///
+	/// Returns `None` if mass storage was not enabled.
+	/// ```
+	/// use pybadge_high::fs::FileSystem;
+	///
+	/// let mut usb = pybadge
+	/// 	.usb_builder
+	/// 	.mass_storage(pybadge.flash.unwrap())
+	/// 	.build();
+	/// usb.with_disk(|disk| {
+	/// 	let mut fs = FileSystem::mount(disk).unwrap();
+	/// 	fs.write_file("/score.txt", b"42").unwrap();
+	/// });
+	/// ```
+	#[cfg(feature = "usb_mass_storage")]
+	pub fn with_disk<F, R>(&mut self, f: F) -> Option<R>
+	where
+		F: FnOnce(&mut FlashDisk<Flash>) -> R
+	{
+		if unsafe { USB_MSC.is_none() } {
+			return None;
+		}
+		let disk = loop {
+			let disk = cortex_m::interrupt::free(|_cs| {
+				let msc = unsafe { USB_MSC.as_mut().unwrap() };
+				let disk = msc.take_disk();
+				if disk.is_none() {
+					// make progress, even if the interrupt is not enabled
+					self.poll();
+				}
+				disk
+			});
+			match disk {
+				Some(disk) => break disk,
+				None => self.process_disk()
+			}
+		};
+		// the flash is accessed with enabled interrupts, because erasing does take some time
+		let result = f(&mut *disk);
+		// a failed flush is reported to the host by the next command
+		disk.flush().ok();
+		cortex_m::interrupt::free(|_cs| {
+			unsafe { USB_MSC.as_mut().unwrap() }.return_disk(disk);
+		});
+		Some(result)
+	}
+
+	/// Write the data, which was received by the mass storage, to the flash.
+	///
+	/// Erasing the flash takes too long for the usb interrupt,
+	/// so this must be called regularly at the main loop, while mass storage is enabled.
+	/// Otherwise writes of the host do never finish.
+	/// Has no effect if mass storage was not enabled.
+	#[cfg(feature = "usb_mass_storage")]
+	pub fn process_disk(&mut self) {
+		while let Some((disk, pending)) =
+			cortex_m::interrupt::free(|_cs| unsafe { USB_MSC.as_mut() }?.take_pending())
+		{
+			let ok = match pending {
+				Pending::Write(lba, block) => disk.write_block(lba, &block).is_ok(),
+				Pending::Flush => disk.flush().is_ok()
+			};
+			cortex_m::interrupt::free(|_cs| {
+				unsafe { USB_MSC.as_mut().unwrap() }.complete_pending(disk, ok);
+			});
+		}
+	}
+
+	/// Prevent the host from writing to the mass storage.
+	/// Has no effect if mass storage was not enabled.
+	#[cfg(feature = "usb_mass_storage")]
+	pub fn set_mass_storage_read_only(&mut self, read_only: bool) {
+		cortex_m::interrupt::free(|_cs| {
+			if let Some(msc) = unsafe { USB_MSC.as_mut() } {
+				msc.read_only = read_only;
+				msc.media_changed = true;
+			}
+		})
+	}
+
+	/// Press the keys of `report` at the keyboard, until the next report is send.
+	///
+	/// Blocks until the last report was transferred.
+	///
+	/// # Errors
+	///
+	/// * [`InvalidState`](UsbError::InvalidState) - The keyboard was not enabled by [`UsbBuilder::keyboard()`]
+	///   or the host has not configured the device yet.
+	#[cfg(feature = "usb_hid")]
+	pub fn press(&mut self, report: &KeyboardReport) -> Result<(), UsbError> {
+		self.write_hid_report(|| unsafe { USB_KEYBOARD.as_mut() }, &report.to_bytes())
+	}
+
+	/// Release all keys of the keyboard.
+	///
+	/// See [`press()`](Usb::press()) for errors.
+	#[cfg(feature = "usb_hid")]
+	pub fn release_all(&mut self) -> Result<(), UsbError> {
+		self.press(&KeyboardReport::new())
+	}
+
+	/// Type `text` at the keyboard, by pressing and releasing the keys of each character.
+	///
+	/// Characters, which can not be typed at the US layout, are skipped.
+	/// See [`keyboard::char_to_key()`] for supported characters
+	/// and [`press()`](Usb::press()) for errors.
+	#[cfg(feature = "usb_hid")]
+	pub fn type_str(&mut self, text: &str) -> Result<(), UsbError> {
+		for report in text.chars().filter_map(KeyboardReport::from_char) {
+			self.press(&report)?;
+			self.release_all()?;
+		}
+		Ok(())
+	}
+
+	/// Press the keys assigned to the currently pressed buttons.
+	///
+	/// [`Buttons::update()`](crate::buttons::Buttons::update()) should be called before.
+	/// See [`press()`](Usb::press()) for errors.
+	#[cfg(feature = "usb_hid")]
+	pub fn press_buttons(
+		&mut self,
+		buttons: &Buttons,
+		keymap: &Keymap
+	) -> Result<(), UsbError> {
+		let report = keymap.report(buttons.pressed());
+		self.press(&report)
+	}
+
+	/// Move the mouse and press its buttons.
+	///
+	/// Blocks until the last report was transferred.
+	///
+	/// # Errors
+	///
+	/// * [`InvalidState`](UsbError::InvalidState) - The mouse was not enabled by [`UsbBuilder::mouse()`]
+	///   or the host has not configured the device yet.
+	#[cfg(feature = "usb_hid")]
+	pub fn move_mouse(&mut self, report: &MouseReport) -> Result<(), UsbError> {
+		self.write_hid_report(|| unsafe { USB_MOUSE.as_mut() }, &report.to_bytes())
+	}
+
+	#[cfg(feature = "usb_hid")]
+	fn write_hid_report<F>(&self, hid: F, report: &[u8]) -> Result<(), UsbError>
+	where
+		F: Fn() -> Option<&'static mut Hid<'static, UsbBus>>
+	{
+		self.write_blocking(|| hid().ok_or(UsbError::InvalidState)?.write_report(report))
+			.map(|_| ())
+	}
+
+	/// Set how long blocking writes wait for the host, before they give up
+	/// with [`WouldBlock`](UsbError::WouldBlock).
+	///
+	/// `None` does wait forever.
+	/// The default is [`DEFAULT_WRITE_TIMEOUT`].
+	/// A timeout prevents the firmware from hanging, if no program at the host is reading
+	/// the serial port.
+	#[cfg(feature = "time")]
+	pub fn set_write_timeout(&mut self, timeout: Option<Milliseconds>) {
+		self.write_timeout = timeout;
+	}
+
+	/// Call `write` until it does not return [`WouldBlock`](UsbError::WouldBlock) anymore,
+	/// or the write timeout has expired.
+	///
+	/// Returns [`InvalidState`](UsbError::InvalidState) if the device is not configured by the host.
+	fn write_blocking<F, T>(&self, write: F) -> Result<T, UsbError>
+	where
+		F: FnMut() -> Result<T, UsbError>
+	{
+		#[cfg(feature = "time")]
+		let timeout = self.write_timeout;
+		#[cfg(not(feature = "time"))]
+		let timeout = None;
+		self.blocking(timeout, write)
+	}
+
+	/// Call `f` until it does not return [`WouldBlock`](UsbError::WouldBlock) anymore,
+	/// or `timeout` has expired.
+	///
+	/// Returns [`InvalidState`](UsbError::InvalidState) if the device is not configured by the host.
+	#[cfg_attr(not(feature = "time"), allow(unused_variables))]
+	fn blocking<F, T>(
+		&self,
+		timeout: Option<Milliseconds>,
+		mut f: F
+	) -> Result<T, UsbError>
+	where
+		F: FnMut() -> Result<T, UsbError>
+	{
+		#[cfg(feature = "time")]
+		let start = uptime();
+		loop {
+			let result = cortex_m::interrupt::free(|_cs| {
+				if unsafe { USB_DEV.as_ref().unwrap() }.state()
+					!= UsbDeviceState::Configured
+				{
+					return Err(UsbError::InvalidState);
+				}
+				let result = f();
+				if matches!(result, Err(UsbError::WouldBlock)) {
+					// make progress, even if the interrupt is not enabled
+					self.poll();
+				}
+				result
+			});
+			match result {
+				Err(UsbError::WouldBlock) =>
+				{
+					#[cfg(feature = "time")]
+					if let Some(timeout) = timeout {
+						if uptime().0.wrapping_sub(start.0) >= timeout.0 {
+							return Err(UsbError::WouldBlock);
+						}
+					}
+				},
+				result => return result
+			}
+		}
+	}
+
+	/// Wait until the tx buffer was send to the host.
+	///
+	/// # Errors
+	///
+	/// * [`WouldBlock`](UsbError::WouldBlock) - The write timeout has expired.
+	/// * [`InvalidState`](UsbError::InvalidState) - Serial was disabled by [`UsbBuilder::serial()`]
+	///   or the host has not configured the device yet.
+	pub fn flush(&mut self) -> Result<(), UsbError> {
+		self.write_blocking(|| serial()?.flush())
+	}
+
+	/// Send a MIDI message at virtual cable 0.
+	///
+	/// Blocks until the message was send.
+	///
+	/// # Errors
+	///
+	/// * [`InvalidState`](UsbError::InvalidState) - MIDI was not enabled by [`UsbBuilder::midi()`]
+	///   or the host has not configured the device yet.
+	#[cfg(feature = "usb_midi")]
+	pub fn midi_send(&mut self, message: &MidiMessage) -> Result<(), UsbError> {
+		self.midi_send_packets(&[MidiPacket::from_message(0, message)])
+	}
+
+	/// Send a system exclusive message at virtual cable 0.
+	///
+	/// `data` must start with `0xF0` and end with `0xF7`.
+	/// See [`midi_send()`](Usb::midi_send()) for errors.
+	#[cfg(feature = "usb_midi")]
+	pub fn midi_send_sysex(&mut self, data: &[u8]) -> Result<(), UsbError> {
+		let mut packets = heapless::Vec::<MidiPacket, 16>::new();
+		for packet in midi::sysex_packets(0, data) {
+			if packets.push(packet).is_err() {
+				self.midi_send_packets(&packets)?;
+				packets.clear();
+				packets.push(packet).ok();
+			}
+		}
+		self.midi_send_packets(&packets)
+	}
+
+	/// Send raw USB-MIDI event packets.
+	///
+	/// See [`midi_send()`](Usb::midi_send()) for errors.
+	#[cfg(feature = "usb_midi")]
+	pub fn midi_send_packets(
+		&mut self,
+		mut packets: &[MidiPacket]
+	) -> Result<(), UsbError> {
+		while !packets.is_empty() {
+			let count = self.write_blocking(|| {
+				unsafe { USB_MIDI.as_mut() }
+					.ok_or(UsbError::InvalidState)?
+					.write_packets(packets)
+			})?;
+			packets = &packets[count..];
+		}
+		Ok(())
+	}
+
+	/// Oldest received USB-MIDI event packet.
+	///
+	/// Use [`MidiPacket::message()`] to get the MIDI message.
+	/// Returns `None` if no packet was received or MIDI was not enabled.
+	#[cfg(feature = "usb_midi")]
+	pub fn midi_receive(&mut self) -> Option<MidiPacket> {
+		cortex_m::interrupt::free(|_cs| unsafe { USB_MIDI.as_mut() }?.read_packet())
+	}
+
+	/// State of the keyboard leds, set by the host.
+	///
+	/// Bit 0 is num lock, bit 1 caps lock, bit 2 scroll lock.
+	#[cfg(feature = "usb_hid")]
+	pub fn keyboard_leds(&self) -> u8 {
+		cortex_m::interrupt::free(|_cs| {
+			unsafe { USB_KEYBOARD.as_ref() }.map_or(0, |keyboard| keyboard.output())
+		})
 	}
-	//TODO: wrap more function from https://docs.rs/usbd-serial/0.1.1/usbd_serial/struct.SerialPort.html
-	//TODO: wrap more function from https://docs.rs/usb-device/0.2.9/usb_device/device/struct.UsbDevice.html
 
 	///use the given function as interrupt handler.
 	///
@@ -81,6 +675,76 @@ impl Usb {
 	}
 }
 
+/// Write text to the serial port, like `write!(usb, "score: {score}")`.
+///
+/// Blocks until the text was written or the write timeout has expired.
+impl fmt::Write for Usb {
+	fn write_str(&mut self, s: &str) -> fmt::Result {
+		embedded_io::Write::write_all(self, s.as_bytes()).map_err(|_| fmt::Error)
+	}
+}
+
+fn error_kind(err: UsbError) -> embedded_io::ErrorKind {
+	match err {
+		UsbError::WouldBlock => embedded_io::ErrorKind::TimedOut,
+		UsbError::InvalidState => embedded_io::ErrorKind::NotConnected,
+		UsbError::BufferOverflow => embedded_io::ErrorKind::OutOfMemory,
+		UsbError::Unsupported => embedded_io::ErrorKind::Unsupported,
+		_ => embedded_io::ErrorKind::Other
+	}
+}
+
+impl embedded_io::ErrorType for Usb {
+	type Error = embedded_io::ErrorKind;
+}
+
+/// Blocking read of the serial port.
+///
+/// Waits until at least one byte is available.
+/// Returns [`NotConnected`](embedded_io::ErrorKind::NotConnected), if serial was disabled
+/// or the host has not configured the device.
+impl embedded_io::Read for Usb {
+	fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		self.blocking(None, || serial()?.read(buf))
+			.map_err(error_kind)
+	}
+}
+
+/// Blocking write of the serial port.
+///
+/// Polls the device while waiting for the host.
+/// Returns [`TimedOut`](embedded_io::ErrorKind::TimedOut), if the write timeout has expired
+/// and [`NotConnected`](embedded_io::ErrorKind::NotConnected), if serial was disabled
+/// or the host has not configured the device.
+impl embedded_io::Write for Usb {
+	fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		self.write_blocking(|| serial()?.write(buf))
+			.map_err(error_kind)
+	}
+
+	fn flush(&mut self) -> Result<(), Self::Error> {
+		Usb::flush(self).map_err(error_kind)
+	}
+}
+
+/// Builder of the usb device.
+///
+/// Any combination of the classes can be enabled, as long as the endpoints of the
+/// microcontroller are sufficient.
+/// ```
+/// let usb = pybadge
+/// 	.usb_builder
+/// 	.product("Controller")
+/// 	.gamepad()
+/// 	.midi()
+/// 	.build();
+/// ```
 pub struct UsbBuilder {
 	pub usb_vid: u16,
 	pub usb_pid: u16,
@@ -93,11 +757,28 @@ pub struct UsbBuilder {
 	pub(crate) pins: UsbPins,
 	pub(crate) clocks: GenericClockController,
 	pub(crate) mclk: MCLK,
-	pub(crate) peripherals: UsbPeripherals
+	pub(crate) peripherals: UsbPeripherals,
+	#[cfg(feature = "usb_mass_storage")]
+	pub(crate) flash: Option<Flash>,
+	pub(crate) serial: bool,
+	pub(crate) touch_reset: bool,
+	#[cfg(feature = "usb_hid")]
+	pub(crate) keyboard: bool,
+	#[cfg(feature = "usb_hid")]
+	pub(crate) gamepad: bool,
+	#[cfg(feature = "usb_hid")]
+	pub(crate) mouse: bool,
+	#[cfg(feature = "usb_midi")]
+	pub(crate) midi: bool,
+	pub(crate) classes: heapless::Vec<ClassInit, MAX_CUSTOM_CLASSES>
 }
 
 impl UsbBuilder {
-	/// Build the USB serial interface.
+	/// Build the USB device with all enabled classes.
+	///
+	/// If more than one class is enabled, a composite device is created.
+	/// Classes with more than one interface, like serial and MIDI, are grouped by
+	/// interface association descriptors.
 	///
 	/// After building [`Usb::poll()`] must be called at least once every 10
 	/// milliseconds while connected to the USB host to be USB compliant.
@@ -108,23 +789,205 @@ impl UsbBuilder {
 		unsafe {
 			USB_ALLOCATOR = Some(usb_allocator);
 		}
-		unsafe {
-			USB_SERIAL = Some(SerialPort::new(USB_ALLOCATOR.as_ref().unwrap()));
+		let mut classes = 0;
+		// some classes have more than one interface and need an interface association descriptor
+		let mut iad = false;
+		if self.serial {
+			unsafe {
+				USB_SERIAL = Some(BufferedSerial::new(
+					USB_ALLOCATOR.as_ref().unwrap(),
+					self.touch_reset
+				));
+			}
+			classes += 1;
+			iad = true;
 		}
-		unsafe {
-			USB_DEV = Some(
-				UsbDeviceBuilder::new(
+		#[cfg(feature = "usb_mass_storage")]
+		if let Some(flash) = self.flash {
+			unsafe {
+				USB_DISK = Some(FlashDisk::new(flash));
+				USB_MSC = Some(MassStorage::new(
 					USB_ALLOCATOR.as_ref().unwrap(),
-					UsbVidPid(self.usb_vid, self.usb_pid)
-				)
-				.manufacturer(self.manufacturer)
-				.product(self.product)
-				.serial_number(self.serial_number)
-				.device_class(USB_CLASS_CDC)
-				.build()
-			);
+					USB_DISK.as_mut().unwrap()
+				));
+			}
+			classes += 1;
 		}
-		Usb {}
+		#[cfg(feature = "usb_hid")]
+		if self.keyboard {
+			unsafe {
+				USB_KEYBOARD = Some(Hid::new(
+					USB_ALLOCATOR.as_ref().unwrap(),
+					keyboard::REPORT_DESCRIPTOR,
+					BootProtocol::Keyboard,
+					10
+				));
+			}
+			classes += 1;
+		}
+		#[cfg(feature = "usb_hid")]
+		if self.gamepad {
+			unsafe {
+				USB_GAMEPAD = Some(Hid::new(
+					USB_ALLOCATOR.as_ref().unwrap(),
+					gamepad::REPORT_DESCRIPTOR,
+					BootProtocol::None,
+					10
+				));
+			}
+			classes += 1;
+		}
+		#[cfg(feature = "usb_hid")]
+		if self.mouse {
+			unsafe {
+				USB_MOUSE = Some(Hid::new(
+					USB_ALLOCATOR.as_ref().unwrap(),
+					mouse::REPORT_DESCRIPTOR,
+					BootProtocol::Mouse,
+					10
+				));
+			}
+			classes += 1;
+		}
+		#[cfg(feature = "usb_midi")]
+		if self.midi {
+			unsafe {
+				USB_MIDI = Some(MidiStreaming::new(USB_ALLOCATOR.as_ref().unwrap()));
+			}
+			classes += 1;
+			iad = true;
+		}
+		for init in &self.classes {
+			let class = init(unsafe { USB_ALLOCATOR.as_ref().unwrap() });
+			unsafe { USB_CUSTOM.push(class).ok() };
+			classes += 1;
+			// the interfaces of own classes are unknown
+			iad = true;
+		}
+		let device_builder = UsbDeviceBuilder::new(
+			unsafe { USB_ALLOCATOR.as_ref().unwrap() },
+			UsbVidPid(self.usb_vid, self.usb_pid)
+		)
+		.manufacturer(self.manufacturer)
+		.product(self.product)
+		.serial_number(self.serial_number);
+		let device_builder = match (self.serial, classes, iad) {
+			(true, 1, _) => device_builder.device_class(USB_CLASS_CDC),
+			// composite device, the classes are described by interface association descriptors
+			(_, _, true) => device_builder
+				.device_class(0xEF)
+				.device_sub_class(0x02)
+				.device_protocol(0x01),
+			// each class is defined by its single interface
+			(_, _, false) => device_builder
+		};
+		unsafe {
+			USB_DEV = Some(device_builder.build());
+		}
+		Usb::new()
+	}
+
+	/// Enable or disable serial communication.
+	///
+	/// Serial is enabled by default.
+	/// Disable it, if the device should only show up as gamepad, keyboard, etc.
+	pub fn serial(mut self, enable: bool) -> Self {
+		self.serial = enable;
+		self
+	}
+
+	/// Enable or disable the restart into the bootloader, if the host opens the serial port with
+	/// 1200 baud and closes it again.
+	///
+	/// This allows tools to flash a new program, without pressing the reset button twice.
+	/// It is enabled by default and has no effect if serial is disabled.
+	pub fn touch_reset(mut self, enable: bool) -> Self {
+		self.touch_reset = enable;
+		self
+	}
+
+	/// Add a gamepad to the device.
+	///
+	/// The eight front buttons are reported to the host at each
+	/// [`Buttons::update()`](crate::buttons::Buttons::update()).
+	/// The d-pad is reported as hat switch.
+	/// ```
+	/// let usb = pybadge.usb_builder.gamepad().serial(false).build();
+	/// ```
+	#[cfg(feature = "usb_hid")]
+	pub fn gamepad(mut self) -> Self {
+		self.gamepad = true;
+		self
+	}
+
+	/// Add an own usb class, like a vendor specific one.
+	///
+	/// `init` is called by [`build()`](UsbBuilder::build()) with the allocator of the bus.
+	/// The class must be stored at a static, to be accessible after building.
+	/// Like all classes of this crate, it is polled by [`Usb::poll()`].
+	/// The usb interrupt should be disabled by [`cortex_m::interrupt::free()`], while accessing the class.
+	/// Classes with more than one interface must write an interface association descriptor,
+	/// to be enumerated correctly by windows.
+	/// ```
+	/// use pybadge_high::usb::{usb_device::bus::UsbBusAllocator, UsbBus};
+	///
+	/// static mut VENDOR: Option<MyClass<'static, UsbBus>> = None;
+	///
+	/// let usb = pybadge
+	/// 	.usb_builder
+	/// 	.class(|alloc| unsafe { VENDOR.insert(MyClass::new(alloc)) })
+	/// 	.build();
+	/// ```
+	///
+	/// # Panics
+	///
+	/// If more than [`MAX_CUSTOM_CLASSES`] are added.
+	pub fn class(mut self, init: ClassInit) -> Self {
+		self.classes.push(init).expect("too many usb classes");
+		self
+	}
+
+	/// Add a MIDI interface to the device.
+	///
+	/// Messages are send by [`Usb::midi_send()`] and received by [`Usb::midi_receive()`].
+	/// [`MonoSynth`](midi::MonoSynth) can play received notes at the speaker.
+	#[cfg(feature = "usb_midi")]
+	pub fn midi(mut self) -> Self {
+		self.midi = true;
+		self
+	}
+
+	/// Add a mouse to the device.
+	///
+	/// The mouse is compatible with the boot protocol.
+	/// Movement is send by [`Usb::move_mouse()`].
+	/// [`DpadMouse`](mouse::DpadMouse) can be used to control the mouse by the d-pad.
+	#[cfg(feature = "usb_hid")]
+	pub fn mouse(mut self) -> Self {
+		self.mouse = true;
+		self
+	}
+
+	/// Add a keyboard to the device, additional to serial.
+	///
+	/// The keyboard is compatible with the boot protocol, so it does also work at the BIOS.
+	/// Keys are send by [`Usb::press()`], [`Usb::type_str()`] and [`Usb::press_buttons()`].
+	#[cfg(feature = "usb_hid")]
+	pub fn keyboard(mut self) -> Self {
+		self.keyboard = true;
+		self
+	}
+
+	/// Expose the [`Flash`] as USB mass storage device to the host, additional to serial.
+	///
+	/// The flash should be formatted with FAT like by [`FileSystem::format()`](crate::fs::FileSystem::format()),
+	/// or by the host.
+	/// [`Usb::process_disk()`] must be called at the main loop, to write the data of the host to the flash.
+	/// Use [`Usb::with_disk()`] to access the flash from the firmware afterwards.
+	#[cfg(feature = "usb_mass_storage")]
+	pub fn mass_storage(mut self, flash: Flash) -> Self {
+		self.flash = Some(flash);
+		self
 	}
 
 	//exist no dervie macro for this (usb_vid, get_usb_vid, set_usb_vid)?
@@ -155,12 +1018,84 @@ impl UsbBuilder {
 	}
 }
 
+/// Report the state of the buttons to the host, if the gamepad is enabled.
+#[cfg(feature = "usb_hid")]
+pub(crate) fn report_buttons<I>(pressed: I)
+where
+	I: IntoIterator<Item = Button>
+{
+	cortex_m::interrupt::free(|_cs| {
+		if let Some(gamepad) = unsafe { USB_GAMEPAD.as_mut() } {
+			gamepad.set_report(&GamepadReport::from_buttons(pressed).to_bytes());
+		}
+	})
+}
+
+/// Print the panic message repeatedly over serial.
+///
+/// The program does not run anymore, so the panic handler must poll the device itself.
+#[cfg(feature = "panic-usb")]
+pub(crate) struct PanicReport<'a> {
+	message: &'a [u8],
+	/// position of the next byte to send
+	position: usize
+}
+
+#[cfg(feature = "panic-usb")]
+impl<'a> PanicReport<'a> {
+	/// Returns `None` if serial was not build.
+	pub(crate) fn new(message: &'a str) -> Option<Self> {
+		unsafe {
+			USB_DEV.as_ref()?;
+			(*addr_of_mut!(USB_SERIAL)).as_ref()?;
+		}
+		Usb::new().disable_interrupt();
+		Some(PanicReport {
+			message: message.as_bytes(),
+			position: 0
+		})
+	}
+
+	/// Poll the device and continue sending the message.
+	pub(crate) fn poll(&mut self) {
+		let usb = Usb::new();
+		usb.poll();
+		if self.position < self.message.len() && usb.dtr() {
+			if let Ok(len) =
+				serial().and_then(|serial| serial.write(&self.message[self.position..]))
+			{
+				self.position += len;
+			}
+		}
+	}
+
+	/// Send the message again, if it was send completely.
+	pub(crate) fn repeat(&mut self) {
+		if self.position >= self.message.len() {
+			self.position = 0;
+		}
+	}
+}
+
+/// The valid UTF-8 at the start of `bytes`.
+fn valid_utf8(bytes: &[u8]) -> &str {
+	match core::str::from_utf8(bytes) {
+		Ok(text) => text,
+		Err(err) => core::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap()
+	}
+}
+
+/// Serial port, if it was enabled.
+fn serial() -> Result<&'static mut BufferedSerial<'static, UsbBus>, UsbError> {
+	unsafe { (*addr_of_mut!(USB_SERIAL)).as_mut() }.ok_or(UsbError::InvalidState)
+}
+
 fn handle_interrupt() {
 	// Disable interrupts while accessing USB_SERIAL and USB_BUS to prevent possible
 	// race conditions
 	cortex_m::interrupt::free(|_cs| {
-		Usb {}.poll(); //unsave see poll function
-				 //should I prefer panic instead? So the user get a respons
+		Usb::new().poll(); //unsave see poll function
+					 //should I prefer panic instead? So the user get a respons
 		if let Some(handler) = unsafe { INTERRUPT_HANDLER } {
 			handler();
 		}
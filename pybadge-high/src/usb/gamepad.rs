@@ -0,0 +1,122 @@
+//! Gamepad reports of the eight front buttons.
+//!
+//! Nothing of this module does access the hardware.
+//! The reports are send automatically by [`Buttons::update()`](crate::buttons::Buttons::update()),
+//! if the gamepad was enabled by [`UsbBuilder::gamepad()`](super::UsbBuilder::gamepad()).
+
+use crate::buttons::Button;
+
+/// Report descriptor of a gamepad with four buttons and the d-pad as hat switch.
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+	0x05, 0x01, // usage page (generic desktop)
+	0x09, 0x05, // usage (gamepad)
+	0xA1, 0x01, // collection (application)
+	0x05, 0x09, //   usage page (buttons)
+	0x19, 0x01, //   usage minimum (1)
+	0x29, 0x04, //   usage maximum (4)
+	0x15, 0x00, //   logical minimum (0)
+	0x25, 0x01, //   logical maximum (1)
+	0x75, 0x01, //   report size (1)
+	0x95, 0x04, //   report count (4)
+	0x81, 0x02, //   input (data, variable, absolute), buttons
+	0x75, 0x04, //   report size (4)
+	0x95, 0x01, //   report count (1)
+	0x81, 0x01, //   input (constant), padding
+	0x05, 0x01, //   usage page (generic desktop)
+	0x09, 0x39, //   usage (hat switch)
+	0x15, 0x00, //   logical minimum (0)
+	0x25, 0x07, //   logical maximum (7)
+	0x35, 0x00, //   physical minimum (0)
+	0x46, 0x3B, 0x01, //   physical maximum (315)
+	0x65, 0x14, //   unit (degrees)
+	0x75, 0x04, //   report size (4)
+	0x95, 0x01, //   report count (1)
+	0x81, 0x42, //   input (data, variable, absolute, null state), hat switch
+	0x65, 0x00, //   unit (none)
+	0x75, 0x04, //   report size (4)
+	0x95, 0x01, //   report count (1)
+	0x81, 0x01, //   input (constant), padding
+	0xC0  // end collection
+];
+
+/// Direction of the hat switch, clockwise starting at up.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(u8)]
+pub enum Hat {
+	Up = 0,
+	UpRight = 1,
+	Right = 2,
+	DownRight = 3,
+	Down = 4,
+	DownLeft = 5,
+	Left = 6,
+	UpLeft = 7,
+	/// Nothing pressed.
+	#[default]
+	Center = 8
+}
+
+impl Hat {
+	/// Direction of the pressed d-pad buttons.
+	///
+	/// Opposite buttons cancel each other out.
+	pub fn from_dpad(up: bool, down: bool, left: bool, right: bool) -> Self {
+		let vertical = up as i8 - down as i8;
+		let horizontal = right as i8 - left as i8;
+		match (vertical, horizontal) {
+			(1, 0) => Hat::Up,
+			(1, 1) => Hat::UpRight,
+			(0, 1) => Hat::Right,
+			(-1, 1) => Hat::DownRight,
+			(-1, 0) => Hat::Down,
+			(-1, -1) => Hat::DownLeft,
+			(0, -1) => Hat::Left,
+			(1, -1) => Hat::UpLeft,
+			_ => Hat::Center
+		}
+	}
+}
+
+/// State of the gamepad, which is send to the host.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct GamepadReport {
+	pub a: bool,
+	pub b: bool,
+	pub select: bool,
+	pub start: bool,
+	pub hat: Hat
+}
+
+impl GamepadReport {
+	/// Report of the pressed buttons.
+	pub fn from_buttons<I>(pressed: I) -> Self
+	where
+		I: IntoIterator<Item = Button>
+	{
+		let (mut up, mut down, mut left, mut right) = (false, false, false, false);
+		let mut report = GamepadReport::default();
+		for button in pressed {
+			match button {
+				Button::A => report.a = true,
+				Button::B => report.b = true,
+				Button::Select => report.select = true,
+				Button::Start => report.start = true,
+				Button::Up => up = true,
+				Button::Down => down = true,
+				Button::Left => left = true,
+				Button::Right => right = true
+			}
+		}
+		report.hat = Hat::from_dpad(up, down, left, right);
+		report
+	}
+
+	/// Report as send over USB.
+	pub fn to_bytes(&self) -> [u8; 2] {
+		let buttons = self.a as u8
+			| (self.b as u8) << 1
+			| (self.select as u8) << 2
+			| (self.start as u8) << 3;
+		[buttons, self.hat as u8]
+	}
+}
@@ -0,0 +1,207 @@
+//! Minimal USB human interface device class, used by the keyboard, gamepad and mouse.
+//!
+//! See <https://www.usb.org/sites/default/files/hid1_11.pdf>
+
+use core::cmp::min;
+use usb_device::{
+	class_prelude::*,
+	control::{Recipient, Request, RequestType},
+	Result
+};
+
+const CLASS_HID: u8 = 0x03;
+const SUBCLASS_NONE: u8 = 0x00;
+const SUBCLASS_BOOT: u8 = 0x01;
+
+const DESCRIPTOR_HID: u8 = 0x21;
+const DESCRIPTOR_REPORT: u8 = 0x22;
+
+const REQ_GET_REPORT: u8 = 0x01;
+const REQ_GET_IDLE: u8 = 0x02;
+const REQ_GET_PROTOCOL: u8 = 0x03;
+const REQ_SET_REPORT: u8 = 0x09;
+const REQ_SET_IDLE: u8 = 0x0A;
+const REQ_SET_PROTOCOL: u8 = 0x0B;
+
+const MAX_REPORT_SIZE: usize = 64;
+
+/// Protocol of boot devices, which are usable by the BIOS.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub(crate) enum BootProtocol {
+	None = 0,
+	Keyboard = 1,
+	Mouse = 2
+}
+
+pub(crate) struct Hid<'a, B: UsbBus> {
+	interface: InterfaceNumber,
+	write_ep: EndpointIn<'a, B>,
+	report_descriptor: &'static [u8],
+	boot_protocol: BootProtocol,
+	/// the host has selected the report protocol, instead of the boot protocol
+	report_protocol: bool,
+	idle: u8,
+	/// last input report send to the host
+	report: [u8; MAX_REPORT_SIZE],
+	report_len: usize,
+	/// report, which could not be send yet, because the endpoint was busy
+	pending: [u8; MAX_REPORT_SIZE],
+	pending_len: Option<usize>,
+	/// last output report received from the host, like the keyboard leds
+	output: u8
+}
+
+impl<'a, B: UsbBus> Hid<'a, B> {
+	pub(crate) fn new(
+		alloc: &'a UsbBusAllocator<B>,
+		report_descriptor: &'static [u8],
+		boot_protocol: BootProtocol,
+		poll_ms: u8
+	) -> Self {
+		Self {
+			interface: alloc.interface(),
+			write_ep: alloc.interrupt(MAX_REPORT_SIZE as u16, poll_ms),
+			report_descriptor,
+			boot_protocol,
+			report_protocol: true,
+			idle: 0,
+			report: [0; MAX_REPORT_SIZE],
+			report_len: 0,
+			pending: [0; MAX_REPORT_SIZE],
+			pending_len: None,
+			output: 0
+		}
+	}
+
+	/// Send an input report to the host.
+	///
+	/// Returns [`WouldBlock`](UsbError::WouldBlock), if the last report was not transferred yet.
+	pub(crate) fn write_report(&mut self, report: &[u8]) -> Result<usize> {
+		let len = self.write_ep.write(report)?;
+		self.report_len = min(len, MAX_REPORT_SIZE);
+		self.report[..self.report_len].copy_from_slice(&report[..self.report_len]);
+		Ok(len)
+	}
+
+	/// Send an input report to the host, as soon as the endpoint is ready.
+	///
+	/// An older report, which was not send yet, is replaced.
+	pub(crate) fn set_report(&mut self, report: &[u8]) {
+		let len = min(report.len(), MAX_REPORT_SIZE);
+		self.pending[..len].copy_from_slice(&report[..len]);
+		self.pending_len = Some(len);
+		self.send_pending();
+	}
+
+	fn send_pending(&mut self) {
+		if let Some(len) = self.pending_len {
+			let pending = self.pending;
+			if self.write_report(&pending[..len]).is_ok() {
+				self.pending_len = None;
+			}
+		}
+	}
+
+	/// Last output report received from the host.
+	pub(crate) fn output(&self) -> u8 {
+		self.output
+	}
+
+	fn hid_descriptor(&self) -> [u8; 7] {
+		let [len_low, len_high] = (self.report_descriptor.len() as u16).to_le_bytes();
+		// HID 1.11, no country code, one report descriptor
+		[0x11, 0x01, 0x00, 0x01, DESCRIPTOR_REPORT, len_low, len_high]
+	}
+
+	fn is_own_request(&self, req: &Request) -> bool {
+		req.recipient == Recipient::Interface
+			&& req.index == u8::from(self.interface) as u16
+	}
+}
+
+impl<B: UsbBus> UsbClass<B> for Hid<'_, B> {
+	fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+		let subclass = match self.boot_protocol {
+			BootProtocol::None => SUBCLASS_NONE,
+			_ => SUBCLASS_BOOT
+		};
+		writer.interface(
+			self.interface,
+			CLASS_HID,
+			subclass,
+			self.boot_protocol as u8
+		)?;
+		writer.write(DESCRIPTOR_HID, &self.hid_descriptor())?;
+		writer.endpoint(&self.write_ep)?;
+		Ok(())
+	}
+
+	fn reset(&mut self) {
+		self.report_protocol = true;
+		self.idle = 0;
+		self.output = 0;
+	}
+
+	fn poll(&mut self) {
+		self.send_pending();
+	}
+
+	fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+		if addr == self.write_ep.address() {
+			self.send_pending();
+		}
+	}
+
+	fn control_in(&mut self, xfer: ControlIn<B>) {
+		let req = *xfer.request();
+		if !self.is_own_request(&req) {
+			return;
+		}
+		match (req.request_type, req.request) {
+			(RequestType::Standard, Request::GET_DESCRIPTOR) => {
+				match (req.value >> 8) as u8 {
+					DESCRIPTOR_REPORT => {
+						xfer.accept_with_static(self.report_descriptor).ok()
+					},
+					DESCRIPTOR_HID => xfer.accept_with(&self.hid_descriptor()).ok(),
+					_ => xfer.reject().ok()
+				};
+			},
+			(RequestType::Class, REQ_GET_REPORT) => {
+				xfer.accept_with(&self.report[..self.report_len]).ok();
+			},
+			(RequestType::Class, REQ_GET_IDLE) => {
+				xfer.accept_with(&[self.idle]).ok();
+			},
+			(RequestType::Class, REQ_GET_PROTOCOL) => {
+				xfer.accept_with(&[self.report_protocol as u8]).ok();
+			},
+			_ => {}
+		}
+	}
+
+	fn control_out(&mut self, xfer: ControlOut<B>) {
+		let req = *xfer.request();
+		if !(req.request_type == RequestType::Class && self.is_own_request(&req)) {
+			return;
+		}
+		match req.request {
+			REQ_SET_REPORT => {
+				if let Some(output) = xfer.data().first() {
+					self.output = *output;
+				}
+				xfer.accept().ok();
+			},
+			REQ_SET_IDLE => {
+				self.idle = (req.value >> 8) as u8;
+				xfer.accept().ok();
+			},
+			REQ_SET_PROTOCOL => {
+				self.report_protocol = req.value != 0;
+				xfer.accept().ok();
+			},
+			_ => {}
+		}
+	}
+}
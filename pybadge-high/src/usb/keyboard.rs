@@ -0,0 +1,461 @@
+//! Keyboard reports and the translation of text to key presses.
+//!
+//! Nothing of this module does access the hardware.
+//! The reports are send by [`Usb::press()`](super::Usb::press()) and [`Usb::type_str()`](super::Usb::type_str()).
+
+use crate::buttons::Button;
+use core::ops::{BitOr, BitOrAssign};
+
+/// Report descriptor of a keyboard, compatible with the boot protocol.
+///
+/// One byte modifiers, one reserved byte and up to six pressed keys.
+/// The host can set the five keyboard leds.
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+	0x05, 0x01, // usage page (generic desktop)
+	0x09, 0x06, // usage (keyboard)
+	0xA1, 0x01, // collection (application)
+	0x05, 0x07, //   usage page (keyboard)
+	0x19, 0xE0, //   usage minimum (left control)
+	0x29, 0xE7, //   usage maximum (right gui)
+	0x15, 0x00, //   logical minimum (0)
+	0x25, 0x01, //   logical maximum (1)
+	0x75, 0x01, //   report size (1)
+	0x95, 0x08, //   report count (8)
+	0x81, 0x02, //   input (data, variable, absolute), modifiers
+	0x95, 0x01, //   report count (1)
+	0x75, 0x08, //   report size (8)
+	0x81, 0x01, //   input (constant), reserved
+	0x95, 0x05, //   report count (5)
+	0x75, 0x01, //   report size (1)
+	0x05, 0x08, //   usage page (leds)
+	0x19, 0x01, //   usage minimum (num lock)
+	0x29, 0x05, //   usage maximum (kana)
+	0x91, 0x02, //   output (data, variable, absolute), leds
+	0x95, 0x01, //   report count (1)
+	0x75, 0x03, //   report size (3)
+	0x91, 0x01, //   output (constant), padding
+	0x95, 0x06, //   report count (6)
+	0x75, 0x08, //   report size (8)
+	0x15, 0x00, //   logical minimum (0)
+	0x25, 0x65, //   logical maximum (101)
+	0x05, 0x07, //   usage page (keyboard)
+	0x19, 0x00, //   usage minimum (0)
+	0x29, 0x65, //   usage maximum (101)
+	0x81, 0x00, //   input (data, array), keys
+	0xC0  // end collection
+];
+
+/// Usage code of a key at the HID keyboard page.
+///
+/// The names are based on the US layout.
+/// The host does translate the code to a character by its configured layout.
+/// So for example [`Key::Z`] results in `y` at a german layout.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Key(pub u8);
+
+impl Key {
+	/// No key pressed.
+	pub const NONE: Key = Key(0x00);
+	pub const A: Key = Key(0x04);
+	pub const B: Key = Key(0x05);
+	pub const C: Key = Key(0x06);
+	pub const D: Key = Key(0x07);
+	pub const E: Key = Key(0x08);
+	pub const F: Key = Key(0x09);
+	pub const G: Key = Key(0x0A);
+	pub const H: Key = Key(0x0B);
+	pub const I: Key = Key(0x0C);
+	pub const J: Key = Key(0x0D);
+	pub const K: Key = Key(0x0E);
+	pub const L: Key = Key(0x0F);
+	pub const M: Key = Key(0x10);
+	pub const N: Key = Key(0x11);
+	pub const O: Key = Key(0x12);
+	pub const P: Key = Key(0x13);
+	pub const Q: Key = Key(0x14);
+	pub const R: Key = Key(0x15);
+	pub const S: Key = Key(0x16);
+	pub const T: Key = Key(0x17);
+	pub const U: Key = Key(0x18);
+	pub const V: Key = Key(0x19);
+	pub const W: Key = Key(0x1A);
+	pub const X: Key = Key(0x1B);
+	pub const Y: Key = Key(0x1C);
+	pub const Z: Key = Key(0x1D);
+	pub const NUM_1: Key = Key(0x1E);
+	pub const NUM_2: Key = Key(0x1F);
+	pub const NUM_3: Key = Key(0x20);
+	pub const NUM_4: Key = Key(0x21);
+	pub const NUM_5: Key = Key(0x22);
+	pub const NUM_6: Key = Key(0x23);
+	pub const NUM_7: Key = Key(0x24);
+	pub const NUM_8: Key = Key(0x25);
+	pub const NUM_9: Key = Key(0x26);
+	pub const NUM_0: Key = Key(0x27);
+	pub const ENTER: Key = Key(0x28);
+	pub const ESCAPE: Key = Key(0x29);
+	pub const BACKSPACE: Key = Key(0x2A);
+	pub const TAB: Key = Key(0x2B);
+	pub const SPACE: Key = Key(0x2C);
+	pub const MINUS: Key = Key(0x2D);
+	pub const EQUAL: Key = Key(0x2E);
+	pub const LEFT_BRACKET: Key = Key(0x2F);
+	pub const RIGHT_BRACKET: Key = Key(0x30);
+	pub const BACKSLASH: Key = Key(0x31);
+	pub const SEMICOLON: Key = Key(0x33);
+	pub const QUOTE: Key = Key(0x34);
+	pub const GRAVE: Key = Key(0x35);
+	pub const COMMA: Key = Key(0x36);
+	pub const PERIOD: Key = Key(0x37);
+	pub const SLASH: Key = Key(0x38);
+	pub const CAPS_LOCK: Key = Key(0x39);
+	pub const F1: Key = Key(0x3A);
+	pub const F2: Key = Key(0x3B);
+	pub const F3: Key = Key(0x3C);
+	pub const F4: Key = Key(0x3D);
+	pub const F5: Key = Key(0x3E);
+	pub const F6: Key = Key(0x3F);
+	pub const F7: Key = Key(0x40);
+	pub const F8: Key = Key(0x41);
+	pub const F9: Key = Key(0x42);
+	pub const F10: Key = Key(0x43);
+	pub const F11: Key = Key(0x44);
+	pub const F12: Key = Key(0x45);
+	pub const PRINT_SCREEN: Key = Key(0x46);
+	pub const SCROLL_LOCK: Key = Key(0x47);
+	pub const PAUSE: Key = Key(0x48);
+	pub const INSERT: Key = Key(0x49);
+	pub const HOME: Key = Key(0x4A);
+	pub const PAGE_UP: Key = Key(0x4B);
+	pub const DELETE: Key = Key(0x4C);
+	pub const END: Key = Key(0x4D);
+	pub const PAGE_DOWN: Key = Key(0x4E);
+	pub const RIGHT: Key = Key(0x4F);
+	pub const LEFT: Key = Key(0x50);
+	pub const DOWN: Key = Key(0x51);
+	pub const UP: Key = Key(0x52);
+	pub const NUM_LOCK: Key = Key(0x53);
+	pub const APPLICATION: Key = Key(0x65);
+}
+
+/// Modifier keys, like shift or control.
+///
+/// Can be combined with `|`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Modifiers(pub u8);
+
+impl Modifiers {
+	pub const NONE: Modifiers = Modifiers(0x00);
+	pub const LEFT_CTRL: Modifiers = Modifiers(0x01);
+	pub const LEFT_SHIFT: Modifiers = Modifiers(0x02);
+	pub const LEFT_ALT: Modifiers = Modifiers(0x04);
+	/// Windows, Command or Super key
+	pub const LEFT_GUI: Modifiers = Modifiers(0x08);
+	pub const RIGHT_CTRL: Modifiers = Modifiers(0x10);
+	pub const RIGHT_SHIFT: Modifiers = Modifiers(0x20);
+	pub const RIGHT_ALT: Modifiers = Modifiers(0x40);
+	pub const RIGHT_GUI: Modifiers = Modifiers(0x80);
+
+	/// Check if all modifiers of `other` are included.
+	pub fn contains(self, other: Modifiers) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+impl BitOr for Modifiers {
+	type Output = Modifiers;
+	fn bitor(self, rhs: Modifiers) -> Modifiers {
+		Modifiers(self.0 | rhs.0)
+	}
+}
+
+impl BitOrAssign for Modifiers {
+	fn bitor_assign(&mut self, rhs: Modifiers) {
+		self.0 |= rhs.0;
+	}
+}
+
+/// State of the keyboard, which is send to the host.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct KeyboardReport {
+	pub modifiers: Modifiers,
+	/// Pressed keys, unused slots are [`Key::NONE`].
+	pub keys: [Key; 6]
+}
+
+impl KeyboardReport {
+	/// Report without any pressed key.
+	pub const fn new() -> Self {
+		KeyboardReport {
+			modifiers: Modifiers::NONE,
+			keys: [Key::NONE; 6]
+		}
+	}
+
+	/// Report of the key and modifiers needed to type `c` at the US layout.
+	///
+	/// Returns `None` if `c` can not be typed.
+	pub fn from_char(c: char) -> Option<Self> {
+		let (modifiers, key) = char_to_key(c)?;
+		let mut report = KeyboardReport::new();
+		report.modifiers = modifiers;
+		report.keys[0] = key;
+		Some(report)
+	}
+
+	/// Add `key` to the pressed keys.
+	///
+	/// Returns false, if already six keys are pressed.
+	pub fn press(&mut self, key: Key) -> bool {
+		if self.keys.contains(&key) {
+			return true;
+		}
+		match self.keys.iter_mut().find(|slot| **slot == Key::NONE) {
+			Some(slot) => {
+				*slot = key;
+				true
+			},
+			None => false
+		}
+	}
+
+	/// Remove `key` from the pressed keys.
+	pub fn release(&mut self, key: Key) {
+		for slot in self.keys.iter_mut().filter(|slot| **slot == key) {
+			*slot = Key::NONE;
+		}
+	}
+
+	/// Report as send over USB.
+	pub fn to_bytes(&self) -> [u8; 8] {
+		let mut bytes = [0; 8];
+		bytes[0] = self.modifiers.0;
+		for (byte, key) in bytes[2..].iter_mut().zip(self.keys) {
+			*byte = key.0;
+		}
+		bytes
+	}
+}
+
+/// Key and modifiers needed to type `c` at the US layout.
+///
+/// Supports printable ASCII characters, `\n`, `\t`, backspace (`\x08`) and escape (`\x1b`).
+pub fn char_to_key(c: char) -> Option<(Modifiers, Key)> {
+	let shift = Modifiers::LEFT_SHIFT;
+	let none = Modifiers::NONE;
+	let key = match c {
+		'a'..='z' => (none, Key(Key::A.0 + (c as u8 - b'a'))),
+		'A'..='Z' => (shift, Key(Key::A.0 + (c as u8 - b'A'))),
+		'1'..='9' => (none, Key(Key::NUM_1.0 + (c as u8 - b'1'))),
+		'0' => (none, Key::NUM_0),
+		'!' => (shift, Key::NUM_1),
+		'@' => (shift, Key::NUM_2),
+		'#' => (shift, Key::NUM_3),
+		'$' => (shift, Key::NUM_4),
+		'%' => (shift, Key::NUM_5),
+		'^' => (shift, Key::NUM_6),
+		'&' => (shift, Key::NUM_7),
+		'*' => (shift, Key::NUM_8),
+		'(' => (shift, Key::NUM_9),
+		')' => (shift, Key::NUM_0),
+		'\n' => (none, Key::ENTER),
+		'\x1b' => (none, Key::ESCAPE),
+		'\x08' => (none, Key::BACKSPACE),
+		'\t' => (none, Key::TAB),
+		' ' => (none, Key::SPACE),
+		'-' => (none, Key::MINUS),
+		'_' => (shift, Key::MINUS),
+		'=' => (none, Key::EQUAL),
+		'+' => (shift, Key::EQUAL),
+		'[' => (none, Key::LEFT_BRACKET),
+		'{' => (shift, Key::LEFT_BRACKET),
+		']' => (none, Key::RIGHT_BRACKET),
+		'}' => (shift, Key::RIGHT_BRACKET),
+		'\\' => (none, Key::BACKSLASH),
+		'|' => (shift, Key::BACKSLASH),
+		';' => (none, Key::SEMICOLON),
+		':' => (shift, Key::SEMICOLON),
+		'\'' => (none, Key::QUOTE),
+		'"' => (shift, Key::QUOTE),
+		'`' => (none, Key::GRAVE),
+		'~' => (shift, Key::GRAVE),
+		',' => (none, Key::COMMA),
+		'<' => (shift, Key::COMMA),
+		'.' => (none, Key::PERIOD),
+		'>' => (shift, Key::PERIOD),
+		'/' => (none, Key::SLASH),
+		'?' => (shift, Key::SLASH),
+		_ => return None
+	};
+	Some(key)
+}
+
+/// Assignment of the [`Button`]s to keys.
+///
+/// The default uses the arrow keys for the d-pad, `Z` and `X` for A and B,
+/// `Enter` for Start and `Escape` for Select, like most emulators.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Keymap {
+	pub a: Key,
+	pub b: Key,
+	pub start: Key,
+	pub select: Key,
+	pub up: Key,
+	pub down: Key,
+	pub left: Key,
+	pub right: Key
+}
+
+impl Default for Keymap {
+	fn default() -> Self {
+		Keymap {
+			a: Key::Z,
+			b: Key::X,
+			start: Key::ENTER,
+			select: Key::ESCAPE,
+			up: Key::UP,
+			down: Key::DOWN,
+			left: Key::LEFT,
+			right: Key::RIGHT
+		}
+	}
+}
+
+impl Keymap {
+	/// Key assigned to `button`.
+	pub fn key(&self, button: Button) -> Key {
+		match button {
+			Button::A => self.a,
+			Button::B => self.b,
+			Button::Start => self.start,
+			Button::Select => self.select,
+			Button::Up => self.up,
+			Button::Down => self.down,
+			Button::Left => self.left,
+			Button::Right => self.right
+		}
+	}
+
+	/// Report with the keys of all pressed buttons.
+	///
+	/// Buttons assigned to [`Key::NONE`] are ignored.
+	pub fn report<I>(&self, pressed: I) -> KeyboardReport
+	where
+		I: IntoIterator<Item = Button>
+	{
+		let mut report = KeyboardReport::new();
+		for key in pressed.into_iter().map(|button| self.key(button)) {
+			if key != Key::NONE {
+				report.press(key);
+			}
+		}
+		report
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Sum the bits of all main items with the given tag, like input (0x80) or output (0x90).
+	fn report_bits(descriptor: &[u8], tag: u8) -> u32 {
+		let (mut size, mut count, mut bits) = (0, 0, 0);
+		let mut items = descriptor;
+		while let Some((&prefix, rest)) = items.split_first() {
+			let len = match prefix & 0x03 {
+				3 => 4,
+				len => len as usize
+			};
+			let value = rest[..len]
+				.iter()
+				.rev()
+				.fold(0, |value, byte| value << 8 | *byte as u32);
+			match prefix & 0xFC {
+				0x74 => size = value,
+				0x94 => count = value,
+				item if item == tag => bits += size * count,
+				_ => {}
+			}
+			items = &rest[len..];
+		}
+		bits
+	}
+
+	#[test]
+	fn report_descriptor() {
+		assert_eq!(report_bits(REPORT_DESCRIPTOR, 0x80), 8 * 8);
+		assert_eq!(report_bits(REPORT_DESCRIPTOR, 0x90), 8);
+		assert_eq!(
+			report_bits(REPORT_DESCRIPTOR, 0x80) as usize,
+			KeyboardReport::new().to_bytes().len() * 8
+		);
+		let collections = REPORT_DESCRIPTOR
+			.iter()
+			.filter(|byte| **byte == 0xA1)
+			.count();
+		assert_eq!(collections, 1);
+		assert_eq!(REPORT_DESCRIPTOR.last(), Some(&0xC0));
+	}
+
+	#[test]
+	fn chars() {
+		assert_eq!(char_to_key('a'), Some((Modifiers::NONE, Key::A)));
+		assert_eq!(char_to_key('z'), Some((Modifiers::NONE, Key::Z)));
+		assert_eq!(char_to_key('1'), Some((Modifiers::NONE, Key::NUM_1)));
+		assert_eq!(char_to_key('9'), Some((Modifiers::NONE, Key::NUM_9)));
+		assert_eq!(char_to_key('0'), Some((Modifiers::NONE, Key::NUM_0)));
+		assert_eq!(char_to_key('\n'), Some((Modifiers::NONE, Key::ENTER)));
+		assert_eq!(char_to_key('\x08'), Some((Modifiers::NONE, Key::BACKSPACE)));
+		for c in ' '..='~' {
+			assert!(char_to_key(c).is_some(), "{c:?}");
+		}
+	}
+
+	#[test]
+	fn shifted_chars() {
+		let shift = Modifiers::LEFT_SHIFT;
+		assert_eq!(char_to_key('A'), Some((shift, Key::A)));
+		assert_eq!(char_to_key('Z'), Some((shift, Key::Z)));
+		assert_eq!(char_to_key('!'), Some((shift, Key::NUM_1)));
+		assert_eq!(char_to_key(')'), Some((shift, Key::NUM_0)));
+		assert_eq!(char_to_key('?'), Some((shift, Key::SLASH)));
+		assert_eq!(char_to_key('~'), Some((shift, Key::GRAVE)));
+		assert_eq!(KeyboardReport::from_char('Z').unwrap().to_bytes(), [
+			0x02, 0, 0x1D, 0, 0, 0, 0, 0
+		]);
+	}
+
+	#[test]
+	fn unmapped_chars() {
+		for c in ['\0', '\r', '\x7F', 'ä', '€', '😀'] {
+			assert_eq!(char_to_key(c), None, "{c:?}");
+			assert_eq!(KeyboardReport::from_char(c), None, "{c:?}");
+		}
+	}
+
+	#[test]
+	fn press_and_release() {
+		let mut report = KeyboardReport::new();
+		for key in 0..6 {
+			assert!(report.press(Key(Key::A.0 + key)));
+		}
+		assert!(report.press(Key::A));
+		assert!(!report.press(Key::Z));
+		report.release(Key::B);
+		assert!(report.press(Key::Z));
+		assert_eq!(report.to_bytes(), [
+			0, 0, 0x04, 0x1D, 0x06, 0x07, 0x08, 0x09
+		]);
+	}
+
+	#[test]
+	fn keymap() {
+		let report = Keymap::default().report([Button::A, Button::Up]);
+		assert_eq!(report.to_bytes(), [0, 0, 0x1D, 0x52, 0, 0, 0, 0]);
+		let keymap = Keymap {
+			a: Key::NONE,
+			..Keymap::default()
+		};
+		assert_eq!(keymap.report([Button::A]), KeyboardReport::new());
+	}
+}
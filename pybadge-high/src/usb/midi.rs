@@ -0,0 +1,507 @@
+//! MIDI messages and their packing into USB-MIDI event packets.
+//!
+//! Nothing of this module does access the hardware, except [`MonoSynth::play()`].
+//! The packets are send and received by [`Usb::midi_send()`](super::Usb::midi_send()) and
+//! [`Usb::midi_receive()`](super::Usb::midi_receive()).
+//!
+//! See <https://www.usb.org/sites/default/files/midi10.pdf>
+
+#[cfg(feature = "pwm_sound")]
+use crate::{time::Hertz, PwmSound};
+
+/// Channel message of the MIDI protocol.
+///
+/// Channels are counted from 0 to 15, all other values from 0 to 127.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MidiMessage {
+	NoteOff {
+		channel: u8,
+		note: u8,
+		velocity: u8
+	},
+	NoteOn {
+		channel: u8,
+		note: u8,
+		velocity: u8
+	},
+	PolyPressure {
+		channel: u8,
+		note: u8,
+		pressure: u8
+	},
+	ControlChange {
+		channel: u8,
+		control: u8,
+		value: u8
+	},
+	ProgramChange {
+		channel: u8,
+		program: u8
+	},
+	ChannelPressure {
+		channel: u8,
+		pressure: u8
+	},
+	/// `value` has 14 bits, 0x2000 is the center.
+	PitchBend {
+		channel: u8,
+		value: u16
+	}
+}
+
+impl MidiMessage {
+	/// Message as send over a MIDI cable.
+	///
+	/// Returns the bytes and their count.
+	pub fn to_bytes(&self) -> ([u8; 3], usize) {
+		let (status, channel, data) = match *self {
+			MidiMessage::NoteOff {
+				channel,
+				note,
+				velocity
+			} => (0x80, channel, [note, velocity]),
+			MidiMessage::NoteOn {
+				channel,
+				note,
+				velocity
+			} => (0x90, channel, [note, velocity]),
+			MidiMessage::PolyPressure {
+				channel,
+				note,
+				pressure
+			} => (0xA0, channel, [note, pressure]),
+			MidiMessage::ControlChange {
+				channel,
+				control,
+				value
+			} => (0xB0, channel, [control, value]),
+			MidiMessage::ProgramChange { channel, program } => {
+				(0xC0, channel, [program, 0])
+			},
+			MidiMessage::ChannelPressure { channel, pressure } => {
+				(0xD0, channel, [pressure, 0])
+			},
+			MidiMessage::PitchBend { channel, value } => (0xE0, channel, [
+				value as u8 & 0x7F,
+				(value >> 7) as u8 & 0x7F
+			])
+		};
+		let len = match status {
+			0xC0 | 0xD0 => 2,
+			_ => 3
+		};
+		(
+			[status | channel & 0x0F, data[0] & 0x7F, data[1] & 0x7F],
+			len
+		)
+	}
+
+	/// Parse a message from the bytes of a MIDI cable.
+	///
+	/// Returns `None` if `bytes` does not start with a channel message.
+	pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+		let status = *bytes.first()?;
+		let channel = status & 0x0F;
+		let data = |i: usize| bytes.get(i).map(|byte| byte & 0x7F);
+		let message = match status & 0xF0 {
+			0x80 => MidiMessage::NoteOff {
+				channel,
+				note: data(1)?,
+				velocity: data(2)?
+			},
+			0x90 => MidiMessage::NoteOn {
+				channel,
+				note: data(1)?,
+				velocity: data(2)?
+			},
+			0xA0 => MidiMessage::PolyPressure {
+				channel,
+				note: data(1)?,
+				pressure: data(2)?
+			},
+			0xB0 => MidiMessage::ControlChange {
+				channel,
+				control: data(1)?,
+				value: data(2)?
+			},
+			0xC0 => MidiMessage::ProgramChange {
+				channel,
+				program: data(1)?
+			},
+			0xD0 => MidiMessage::ChannelPressure {
+				channel,
+				pressure: data(1)?
+			},
+			0xE0 => MidiMessage::PitchBend {
+				channel,
+				value: data(1)? as u16 | (data(2)? as u16) << 7
+			},
+			_ => return None
+		};
+		Some(message)
+	}
+}
+
+/// USB-MIDI event packet.
+///
+/// Byte 0 contains the virtual cable number and the code index number (CIN),
+/// followed by up to three bytes of the MIDI cable.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct MidiPacket(pub [u8; 4]);
+
+impl MidiPacket {
+	/// Packet of a channel message.
+	pub fn from_message(cable: u8, message: &MidiMessage) -> Self {
+		let (bytes, _) = message.to_bytes();
+		// for channel messages the CIN is the high nibble of the status
+		MidiPacket([cable << 4 | bytes[0] >> 4, bytes[0], bytes[1], bytes[2]])
+	}
+
+	/// Virtual cable number.
+	pub fn cable(&self) -> u8 {
+		self.0[0] >> 4
+	}
+
+	/// Code index number, which describes the kind of the packet.
+	pub fn code_index(&self) -> u8 {
+		self.0[0] & 0x0F
+	}
+
+	/// Channel message of the packet.
+	///
+	/// Returns `None` for system exclusive and other messages.
+	pub fn message(&self) -> Option<MidiMessage> {
+		match self.code_index() {
+			0x8..=0xE => MidiMessage::from_bytes(&self.0[1..]),
+			_ => None
+		}
+	}
+
+	/// Bytes of a system exclusive message, which are contained in this packet.
+	///
+	/// A system exclusive message is spread over several packets.
+	/// The first one starts with `0xF0` and the last one does end with `0xF7`.
+	/// Returns `None` if the packet is not part of a system exclusive message.
+	pub fn sysex_data(&self) -> Option<&[u8]> {
+		let len = match self.code_index() {
+			0x4 | 0x7 => 3,
+			0x6 => 2,
+			0x5 => 1,
+			_ => return None
+		};
+		Some(&self.0[1..1 + len])
+	}
+}
+
+/// Split a system exclusive message into packets.
+///
+/// `data` must start with `0xF0` and end with `0xF7`.
+pub fn sysex_packets(cable: u8, data: &[u8]) -> impl Iterator<Item = MidiPacket> + '_ {
+	let count = data.chunks(3).count();
+	data.chunks(3).enumerate().map(move |(i, chunk)| {
+		let code_index = if i + 1 == count {
+			// end of the message with 1, 2 or 3 bytes
+			0x4 + chunk.len() as u8
+		} else {
+			0x4
+		};
+		let mut packet = [cable << 4 | code_index, 0, 0, 0];
+		packet[1..1 + chunk.len()].copy_from_slice(chunk);
+		MidiPacket(packet)
+	})
+}
+
+/// Frequencies of the notes 0 to 11 in milli hertz.
+const FREQUENCIES: [u32; 12] = [
+	8176, 8662, 9177, 9723, 10301, 10913, 11562, 12250, 12978, 13750, 14568, 15434
+];
+
+/// Frequency of a MIDI note in hertz, at equal temperament with A4 (note 69) at 440 hertz.
+pub fn note_frequency(note: u8) -> u32 {
+	let note = note.min(127);
+	let milli_hertz = FREQUENCIES[(note % 12) as usize] << (note / 12);
+	(milli_hertz + 500) / 1000
+}
+
+/// Monophonic synthesizer, which does play the last pressed note.
+///
+/// If the note is released, the previous still pressed note is played again.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct MonoSynth {
+	/// Only react to notes of this channel, or of all channels if `None`.
+	pub channel: Option<u8>,
+	/// pressed notes, the last one is played
+	notes: heapless::Vec<u8, 16>
+}
+
+impl MonoSynth {
+	pub const fn new() -> Self {
+		MonoSynth {
+			channel: None,
+			notes: heapless::Vec::new()
+		}
+	}
+
+	fn listens(&self, channel: u8) -> bool {
+		match self.channel {
+			Some(own) => own == channel,
+			None => true
+		}
+	}
+
+	/// Currently played note.
+	pub fn note(&self) -> Option<u8> {
+		self.notes.last().copied()
+	}
+
+	/// Update the pressed notes by `message`.
+	///
+	/// Returns true, if the played note has changed.
+	pub fn handle(&mut self, message: &MidiMessage) -> bool {
+		let old = self.note();
+		match *message {
+			MidiMessage::NoteOn {
+				channel,
+				note,
+				velocity
+			} if self.listens(channel) => {
+				self.notes.retain(|pressed| *pressed != note);
+				if velocity > 0 {
+					if self.notes.is_full() {
+						self.notes.remove(0);
+					}
+					self.notes.push(note).ok();
+				}
+			},
+			MidiMessage::NoteOff { channel, note, .. } if self.listens(channel) => {
+				self.notes.retain(|pressed| *pressed != note);
+			},
+			// all notes off
+			MidiMessage::ControlChange {
+				channel,
+				control: 123,
+				..
+			} if self.listens(channel) => self.notes.clear(),
+			_ => {}
+		}
+		self.note() != old
+	}
+
+	/// Update the pressed notes by `message` and play the current note at the speaker.
+	#[cfg(feature = "pwm_sound")]
+	pub fn play(&mut self, message: &MidiMessage, speaker: &mut PwmSound) {
+		if !self.handle(message) {
+			return;
+		}
+		match self.note() {
+			Some(note) => {
+				speaker.set_freq(Hertz(note_frequency(note)));
+				speaker.enable();
+			},
+			None => speaker.disable()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn channel_messages() {
+		let messages = [
+			(
+				MidiMessage::NoteOff {
+					channel: 1,
+					note: 60,
+					velocity: 64
+				},
+				[0x08, 0x81, 60, 64]
+			),
+			(
+				MidiMessage::NoteOn {
+					channel: 2,
+					note: 60,
+					velocity: 100
+				},
+				[0x09, 0x92, 60, 100]
+			),
+			(
+				MidiMessage::PolyPressure {
+					channel: 3,
+					note: 61,
+					pressure: 5
+				},
+				[0x0A, 0xA3, 61, 5]
+			),
+			(
+				MidiMessage::ControlChange {
+					channel: 4,
+					control: 7,
+					value: 127
+				},
+				[0x0B, 0xB4, 7, 127]
+			),
+			(
+				MidiMessage::ProgramChange {
+					channel: 0,
+					program: 5
+				},
+				[0x0C, 0xC0, 5, 0]
+			),
+			(
+				MidiMessage::ChannelPressure {
+					channel: 14,
+					pressure: 9
+				},
+				[0x0D, 0xDE, 9, 0]
+			),
+			(
+				MidiMessage::PitchBend {
+					channel: 15,
+					value: 0x2001
+				},
+				[0x0E, 0xEF, 0x01, 0x40]
+			)
+		];
+		for (message, bytes) in messages {
+			let packet = MidiPacket::from_message(0, &message);
+			assert_eq!(packet.0, bytes, "{message:?}");
+			assert_eq!(packet.message(), Some(message));
+			assert_eq!(MidiMessage::from_bytes(&bytes[1..]), Some(message));
+		}
+		let (_, len) = MidiMessage::ProgramChange {
+			channel: 0,
+			program: 5
+		}
+		.to_bytes();
+		assert_eq!(len, 2);
+	}
+
+	#[test]
+	fn cable_and_code_index() {
+		let message = MidiMessage::NoteOn {
+			channel: 0,
+			note: 60,
+			velocity: 1
+		};
+		let packet = MidiPacket::from_message(3, &message);
+		assert_eq!(packet.0[0], 0x39);
+		assert_eq!(packet.cable(), 3);
+		assert_eq!(packet.code_index(), 0x9);
+	}
+
+	#[test]
+	fn out_of_range_values_are_masked() {
+		let message = MidiMessage::NoteOn {
+			channel: 0x12,
+			note: 0xFF,
+			velocity: 0x80
+		};
+		assert_eq!(message.to_bytes(), ([0x92, 0x7F, 0x00], 3));
+		assert_eq!(
+			MidiMessage::from_bytes(&[0x90, 0xFF, 0x80]),
+			Some(MidiMessage::NoteOn {
+				channel: 0,
+				note: 0x7F,
+				velocity: 0
+			})
+		);
+	}
+
+	#[test]
+	fn invalid_bytes() {
+		assert_eq!(MidiMessage::from_bytes(&[]), None);
+		assert_eq!(MidiMessage::from_bytes(&[0x90, 60]), None);
+		assert_eq!(MidiMessage::from_bytes(&[0xC0]), None);
+		assert_eq!(MidiMessage::from_bytes(&[0xF8, 0, 0]), None);
+		assert_eq!(MidiMessage::from_bytes(&[0x3C, 0x40, 0]), None);
+		assert_eq!(MidiPacket([0x0F, 0xF8, 0, 0]).message(), None);
+		assert_eq!(MidiPacket([0x04, 0xF0, 1, 2]).message(), None);
+		assert_eq!(MidiPacket([0x09, 0x90, 60, 1]).sysex_data(), None);
+	}
+
+	fn sysex(data: &[u8]) -> Vec<[u8; 4]> {
+		sysex_packets(2, data).map(|packet| packet.0).collect()
+	}
+
+	#[test]
+	fn sysex_chunks() {
+		assert_eq!(sysex(&[0xF0, 0xF7]), [[0x26, 0xF0, 0xF7, 0]]);
+		assert_eq!(sysex(&[0xF0, 1, 0xF7]), [[0x27, 0xF0, 1, 0xF7]]);
+		assert_eq!(sysex(&[0xF0, 1, 2, 0xF7]), [[0x24, 0xF0, 1, 2], [
+			0x25, 0xF7, 0, 0
+		]]);
+		assert_eq!(sysex(&[0xF0, 1, 2, 3, 0xF7]), [[0x24, 0xF0, 1, 2], [
+			0x26, 3, 0xF7, 0
+		]]);
+		assert_eq!(sysex(&[0xF0, 1, 2, 3, 4, 0xF7]), [[0x24, 0xF0, 1, 2], [
+			0x27, 3, 4, 0xF7
+		]]);
+	}
+
+	#[test]
+	fn sysex_roundtrip() {
+		for len in 0..20 {
+			let mut data = vec![0xF0];
+			data.extend((0..len).map(|i| i as u8));
+			data.push(0xF7);
+			let packets: Vec<MidiPacket> = sysex_packets(0, &data).collect();
+			assert_eq!(packets.len(), (data.len() + 2) / 3);
+			let mut joined = Vec::new();
+			for packet in &packets {
+				joined.extend_from_slice(packet.sysex_data().unwrap());
+			}
+			assert_eq!(joined, data);
+			assert!(packets.iter().all(|packet| packet.message().is_none()));
+		}
+	}
+
+	#[test]
+	fn frequencies() {
+		assert_eq!(note_frequency(0), 8);
+		assert_eq!(note_frequency(60), 262);
+		assert_eq!(note_frequency(69), 440);
+		assert_eq!(note_frequency(81), 880);
+		assert_eq!(note_frequency(127), 12544);
+		assert_eq!(note_frequency(255), 12544);
+	}
+
+	#[test]
+	fn mono_synth() {
+		let note_on = |note, velocity| MidiMessage::NoteOn {
+			channel: 0,
+			note,
+			velocity
+		};
+		let mut synth = MonoSynth::new();
+		assert!(synth.handle(&note_on(60, 1)));
+		assert!(synth.handle(&note_on(64, 1)));
+		assert_eq!(synth.note(), Some(64));
+		// pressing a note again plays it
+		assert!(synth.handle(&note_on(60, 1)));
+		assert_eq!(synth.note(), Some(60));
+		assert!(synth.handle(&MidiMessage::NoteOff {
+			channel: 0,
+			note: 60,
+			velocity: 0
+		}));
+		assert_eq!(synth.note(), Some(64));
+		// velocity 0 is a note off
+		assert!(synth.handle(&note_on(64, 0)));
+		assert_eq!(synth.note(), None);
+		synth.channel = Some(1);
+		assert!(!synth.handle(&note_on(60, 1)));
+		assert!(synth.handle(&MidiMessage::NoteOn {
+			channel: 1,
+			note: 62,
+			velocity: 1
+		}));
+		assert!(synth.handle(&MidiMessage::ControlChange {
+			channel: 1,
+			control: 123,
+			value: 0
+		}));
+		assert_eq!(synth.note(), None);
+	}
+}
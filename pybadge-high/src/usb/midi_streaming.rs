@@ -0,0 +1,172 @@
+//! USB audio class with a single MIDI streaming interface.
+//!
+//! See <https://www.usb.org/sites/default/files/midi10.pdf>
+
+use super::midi::MidiPacket;
+use heapless::Deque;
+use usb_device::{class_prelude::*, Result};
+
+const CLASS_AUDIO: u8 = 0x01;
+const SUBCLASS_AUDIO_CONTROL: u8 = 0x01;
+const SUBCLASS_MIDI_STREAMING: u8 = 0x03;
+
+const CS_INTERFACE: u8 = 0x24;
+const CS_ENDPOINT: u8 = 0x25;
+const HEADER: u8 = 0x01;
+const MS_GENERAL: u8 = 0x01;
+const MIDI_IN_JACK: u8 = 0x02;
+const MIDI_OUT_JACK: u8 = 0x03;
+const EMBEDDED: u8 = 0x01;
+const EXTERNAL: u8 = 0x02;
+
+/// ids of the jacks, the embedded ones are connected to the endpoints
+const JACK_IN_EMBEDDED: u8 = 1;
+const JACK_IN_EXTERNAL: u8 = 2;
+const JACK_OUT_EMBEDDED: u8 = 3;
+const JACK_OUT_EXTERNAL: u8 = 4;
+
+/// total length of the class specific midi streaming descriptors:
+/// header, 4 jacks, 2 standard and 2 class specific endpoint descriptors
+const MS_TOTAL_LENGTH: u16 = 7 + 6 + 6 + 9 + 9 + 7 + 5 + 7 + 5;
+
+const PACKET_SIZE: usize = 64;
+const RX_PACKETS: usize = 32;
+
+pub(crate) struct MidiStreaming<'a, B: UsbBus> {
+	audio_control: InterfaceNumber,
+	midi_streaming: InterfaceNumber,
+	read_ep: EndpointOut<'a, B>,
+	write_ep: EndpointIn<'a, B>,
+	rx: Deque<MidiPacket, RX_PACKETS>
+}
+
+impl<'a, B: UsbBus> MidiStreaming<'a, B> {
+	pub(crate) fn new(alloc: &'a UsbBusAllocator<B>) -> Self {
+		Self {
+			audio_control: alloc.interface(),
+			midi_streaming: alloc.interface(),
+			read_ep: alloc.bulk(PACKET_SIZE as u16),
+			write_ep: alloc.bulk(PACKET_SIZE as u16),
+			rx: Deque::new()
+		}
+	}
+
+	/// Send up to 16 packets in one transfer and return the number of send packets.
+	pub(crate) fn write_packets(&mut self, packets: &[MidiPacket]) -> Result<usize> {
+		let mut buf = [0_u8; PACKET_SIZE];
+		let count = packets.len().min(PACKET_SIZE / 4);
+		for (chunk, packet) in buf.chunks_mut(4).zip(&packets[..count]) {
+			chunk.copy_from_slice(&packet.0);
+		}
+		self.write_ep.write(&buf[..count * 4])?;
+		Ok(count)
+	}
+
+	/// Oldest received packet.
+	pub(crate) fn read_packet(&mut self) -> Option<MidiPacket> {
+		let packet = self.rx.pop_front();
+		// there may be space for packets waiting at the endpoint now
+		self.receive();
+		packet
+	}
+
+	fn receive(&mut self) {
+		if self.rx.capacity() - self.rx.len() < PACKET_SIZE / 4 {
+			// keep the packets at the endpoint, until there is enough space
+			return;
+		}
+		let mut buf = [0_u8; PACKET_SIZE];
+		let Ok(len) = self.read_ep.read(&mut buf) else {
+			return;
+		};
+		for chunk in buf[..len].chunks_exact(4) {
+			// empty packets are used as padding
+			if chunk[0] & 0x0F != 0 {
+				let packet = MidiPacket([chunk[0], chunk[1], chunk[2], chunk[3]]);
+				self.rx.push_back(packet).ok();
+			}
+		}
+	}
+}
+
+impl<B: UsbBus> UsbClass<B> for MidiStreaming<'_, B> {
+	fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+		writer.iad(self.audio_control, 2, CLASS_AUDIO, 0x00, 0x00)?;
+
+		writer.interface(
+			self.audio_control,
+			CLASS_AUDIO,
+			SUBCLASS_AUDIO_CONTROL,
+			0x00
+		)?;
+		// audio class 1.0, total length 9, one streaming interface
+		writer.write(CS_INTERFACE, &[
+			HEADER,
+			0x00,
+			0x01,
+			0x09,
+			0x00,
+			0x01,
+			self.midi_streaming.into()
+		])?;
+
+		writer.interface(
+			self.midi_streaming,
+			CLASS_AUDIO,
+			SUBCLASS_MIDI_STREAMING,
+			0x00
+		)?;
+		let [len_low, len_high] = MS_TOTAL_LENGTH.to_le_bytes();
+		writer.write(CS_INTERFACE, &[MS_GENERAL, 0x00, 0x01, len_low, len_high])?;
+		writer.write(CS_INTERFACE, &[
+			MIDI_IN_JACK,
+			EMBEDDED,
+			JACK_IN_EMBEDDED,
+			0x00
+		])?;
+		writer.write(CS_INTERFACE, &[
+			MIDI_IN_JACK,
+			EXTERNAL,
+			JACK_IN_EXTERNAL,
+			0x00
+		])?;
+		// one input pin each, connected to the in jack of the other side
+		writer.write(CS_INTERFACE, &[
+			MIDI_OUT_JACK,
+			EMBEDDED,
+			JACK_OUT_EMBEDDED,
+			0x01,
+			JACK_IN_EXTERNAL,
+			0x01,
+			0x00
+		])?;
+		writer.write(CS_INTERFACE, &[
+			MIDI_OUT_JACK,
+			EXTERNAL,
+			JACK_OUT_EXTERNAL,
+			0x01,
+			JACK_IN_EMBEDDED,
+			0x01,
+			0x00
+		])?;
+		writer.endpoint(&self.read_ep)?;
+		writer.write(CS_ENDPOINT, &[MS_GENERAL, 0x01, JACK_IN_EMBEDDED])?;
+		writer.endpoint(&self.write_ep)?;
+		writer.write(CS_ENDPOINT, &[MS_GENERAL, 0x01, JACK_OUT_EMBEDDED])?;
+		Ok(())
+	}
+
+	fn reset(&mut self) {
+		self.rx.clear();
+	}
+
+	fn poll(&mut self) {
+		self.receive();
+	}
+
+	fn endpoint_out(&mut self, addr: EndpointAddress) {
+		if addr == self.read_ep.address() {
+			self.receive();
+		}
+	}
+}
@@ -0,0 +1,253 @@
+//! Mouse reports and the control of the mouse by the d-pad.
+//!
+//! Nothing of this module does access the hardware.
+//! The reports are send by [`Usb::move_mouse()`](super::Usb::move_mouse()).
+
+use crate::buttons::Button;
+use core::cmp::min;
+
+/// Report descriptor of a mouse with three buttons and a scroll wheel.
+///
+/// The first three bytes are compatible with the boot protocol.
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+	0x05, 0x01, // usage page (generic desktop)
+	0x09, 0x02, // usage (mouse)
+	0xA1, 0x01, // collection (application)
+	0x09, 0x01, //   usage (pointer)
+	0xA1, 0x00, //   collection (physical)
+	0x05, 0x09, //     usage page (buttons)
+	0x19, 0x01, //     usage minimum (1)
+	0x29, 0x03, //     usage maximum (3)
+	0x15, 0x00, //     logical minimum (0)
+	0x25, 0x01, //     logical maximum (1)
+	0x95, 0x03, //     report count (3)
+	0x75, 0x01, //     report size (1)
+	0x81, 0x02, //     input (data, variable, absolute), buttons
+	0x95, 0x01, //     report count (1)
+	0x75, 0x05, //     report size (5)
+	0x81, 0x01, //     input (constant), padding
+	0x05, 0x01, //     usage page (generic desktop)
+	0x09, 0x30, //     usage (x)
+	0x09, 0x31, //     usage (y)
+	0x09, 0x38, //     usage (wheel)
+	0x15, 0x81, //     logical minimum (-127)
+	0x25, 0x7F, //     logical maximum (127)
+	0x75, 0x08, //     report size (8)
+	0x95, 0x03, //     report count (3)
+	0x81, 0x06, //     input (data, variable, relative), movement
+	0xC0, //   end collection
+	0xC0  // end collection
+];
+
+/// Movement of the mouse since the last report.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct MouseReport {
+	pub left: bool,
+	pub right: bool,
+	pub middle: bool,
+	/// Movement to the right in pixels.
+	pub x: i8,
+	/// Movement downwards in pixels.
+	pub y: i8,
+	/// Scroll upwards.
+	pub wheel: i8
+}
+
+impl MouseReport {
+	/// Report as send over USB.
+	pub fn to_bytes(&self) -> [u8; 4] {
+		let buttons =
+			self.left as u8 | (self.right as u8) << 1 | (self.middle as u8) << 2;
+		[buttons, self.x as u8, self.y as u8, self.wheel as u8]
+	}
+}
+
+/// Control the mouse by the d-pad.
+///
+/// The longer a direction is held, the faster the pointer does move,
+/// until `max_speed` is reached.
+/// A is the left and B the right mouse button.
+/// ```
+/// use pybadge_high::usb::mouse::DpadMouse;
+///
+/// let mut usb = pybadge.usb_builder.mouse().build();
+/// let mut mouse = DpadMouse::new();
+/// loop {
+/// 	pybadge.buttons.update();
+/// 	let report = mouse.update(pybadge.buttons.pressed(), 10);
+/// 	usb.move_mouse(&report).ok();
+/// 	pybadge.delay.delay_ms(10_u8);
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DpadMouse {
+	/// Speed in pixels per second, when a direction is pressed.
+	pub start_speed: u32,
+	/// Maximum speed in pixels per second.
+	pub max_speed: u32,
+	/// Increase of the speed in pixels per second, for each second the direction is held.
+	pub acceleration: u32,
+	/// how long the d-pad is already held
+	held_ms: u32,
+	/// movement, which was not reported yet, in 1/1000 pixels
+	rest_x: i32,
+	rest_y: i32
+}
+
+impl Default for DpadMouse {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl DpadMouse {
+	pub const fn new() -> Self {
+		DpadMouse {
+			start_speed: 100,
+			max_speed: 1000,
+			acceleration: 800,
+			held_ms: 0,
+			rest_x: 0,
+			rest_y: 0
+		}
+	}
+
+	/// Current speed in pixels per second.
+	pub fn speed(&self) -> u32 {
+		let speed = self.start_speed as u64
+			+ self.acceleration as u64 * self.held_ms as u64 / 1000;
+		min(speed, self.max_speed as u64) as u32
+	}
+
+	/// Calculate the report for the pressed buttons.
+	///
+	/// `elapsed_ms` is the time since the last call in milliseconds.
+	pub fn update<I>(&mut self, pressed: I, elapsed_ms: u32) -> MouseReport
+	where
+		I: IntoIterator<Item = Button>
+	{
+		let mut report = MouseReport::default();
+		let (mut dx, mut dy) = (0, 0);
+		for button in pressed {
+			match button {
+				Button::A => report.left = true,
+				Button::B => report.right = true,
+				Button::Up => dy -= 1,
+				Button::Down => dy += 1,
+				Button::Left => dx -= 1,
+				Button::Right => dx += 1,
+				Button::Start | Button::Select => {}
+			}
+		}
+		if dx == 0 && dy == 0 {
+			self.held_ms = 0;
+			self.rest_x = 0;
+			self.rest_y = 0;
+			return report;
+		}
+		self.held_ms = self.held_ms.saturating_add(elapsed_ms);
+		// pixels per second * milliseconds = 1/1000 pixels
+		// a report can not move more than 127 pixels
+		let distance = (self.speed() as u64 * elapsed_ms as u64).min(127_000) as i32;
+		report.x = Self::take_pixels(&mut self.rest_x, dx * distance);
+		report.y = Self::take_pixels(&mut self.rest_y, dy * distance);
+		report
+	}
+
+	/// Remove the whole pixels from `rest`, after adding `distance`.
+	fn take_pixels(rest: &mut i32, distance: i32) -> i8 {
+		*rest += distance;
+		let pixels = (*rest / 1000).clamp(-127, 127);
+		*rest = (*rest - pixels * 1000).clamp(-999, 999);
+		pixels as i8
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn to_bytes() {
+		assert_eq!(MouseReport::default().to_bytes(), [0, 0, 0, 0]);
+		let report = MouseReport {
+			left: true,
+			right: false,
+			middle: true,
+			x: -1,
+			y: 127,
+			wheel: -127
+		};
+		assert_eq!(report.to_bytes(), [0b101, 0xFF, 0x7F, 0x81]);
+		let report = MouseReport {
+			right: true,
+			..MouseReport::default()
+		};
+		assert_eq!(report.to_bytes(), [0b010, 0, 0, 0]);
+	}
+
+	#[test]
+	fn speed() {
+		let mut mouse = DpadMouse::new();
+		assert_eq!(mouse.speed(), 100);
+		mouse.update([Button::Right], 500);
+		assert_eq!(mouse.speed(), 100 + 400);
+		mouse.update([Button::Right], 500);
+		assert_eq!(mouse.speed(), 100 + 800);
+		mouse.update([Button::Right], 500);
+		assert_eq!(mouse.speed(), 1000);
+		mouse.update([Button::A], 10);
+		assert_eq!(mouse.speed(), 100);
+	}
+
+	#[test]
+	fn update() {
+		let mut mouse = DpadMouse::new();
+		assert_eq!(mouse.update([Button::A], 10).to_bytes(), [1, 0, 0, 0]);
+		assert_eq!(mouse.update([Button::B], 10).to_bytes(), [2, 0, 0, 0]);
+		assert_eq!(
+			mouse.update([Button::Start, Button::Select], 10),
+			MouseReport::default()
+		);
+		// 100 pixels per second for 10 milliseconds
+		let report = mouse.update([Button::Right, Button::Down], 10);
+		assert_eq!((report.x, report.y), (1, 1));
+		let report = mouse.update([Button::Left, Button::Right, Button::Up], 10);
+		assert_eq!((report.x, report.y), (0, -1));
+		// a report can not move more than 127 pixels
+		mouse.update([], 10);
+		let report = mouse.update([Button::Left, Button::Up], 1000);
+		assert_eq!((report.x, report.y), (-127, -127));
+	}
+
+	#[test]
+	fn sub_pixel_movement() {
+		let mut mouse = DpadMouse {
+			acceleration: 0,
+			..DpadMouse::new()
+		};
+		// 0.3 pixels per update
+		let moved: Vec<i8> = (0..10).map(|_| mouse.update([Button::Left], 3).x).collect();
+		assert_eq!(moved, [0, 0, 0, -1, 0, 0, -1, 0, 0, -1]);
+		// releasing the d-pad drops the remainder
+		assert_eq!(mouse.update([Button::Left], 2).x, 0);
+		mouse.update([], 10);
+		assert_eq!(mouse.update([Button::Left], 3).x, 0);
+		assert_eq!(mouse.update([Button::Left], 3).x, 0);
+		assert_eq!(mouse.update([Button::Left], 3).x, 0);
+		assert_eq!(mouse.update([Button::Left], 3).x, -1);
+	}
+
+	#[test]
+	fn take_pixels() {
+		let mut rest = 0;
+		assert_eq!(DpadMouse::take_pixels(&mut rest, 1500), 1);
+		assert_eq!(rest, 500);
+		assert_eq!(DpadMouse::take_pixels(&mut rest, -2000), -1);
+		assert_eq!(rest, -500);
+		assert_eq!(DpadMouse::take_pixels(&mut rest, 400), 0);
+		assert_eq!(rest, -100);
+		assert_eq!(DpadMouse::take_pixels(&mut rest, 200_000), 127);
+		assert_eq!(rest, 999);
+	}
+}
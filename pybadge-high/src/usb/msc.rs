@@ -0,0 +1,482 @@
+//! USB mass storage class (bulk-only transport) with a minimal SCSI command set.
+//!
+//! See <https://www.usb.org/sites/default/files/usbmassbulk_10.pdf>
+
+use crate::fs::{BlockDevice, BLOCK_SIZE};
+use core::cmp::min;
+use usb_device::{
+	class_prelude::*,
+	control::{Recipient, RequestType},
+	Result
+};
+
+const CLASS_MSC: u8 = 0x08;
+const SUBCLASS_SCSI: u8 = 0x06;
+const PROTOCOL_BULK_ONLY: u8 = 0x50;
+
+const REQ_BULK_ONLY_RESET: u8 = 0xFF;
+const REQ_GET_MAX_LUN: u8 = 0xFE;
+
+const PACKET_SIZE: usize = 64;
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CBW_SIZE: usize = 31;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+const CSW_SIZE: usize = 13;
+
+const STATUS_PASSED: u8 = 0x00;
+const STATUS_FAILED: u8 = 0x01;
+
+const TEST_UNIT_READY: u8 = 0x00;
+const REQUEST_SENSE: u8 = 0x03;
+const INQUIRY: u8 = 0x12;
+const MODE_SENSE_6: u8 = 0x1A;
+const START_STOP_UNIT: u8 = 0x1B;
+const PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1E;
+const READ_FORMAT_CAPACITIES: u8 = 0x23;
+const READ_CAPACITY_10: u8 = 0x25;
+const READ_10: u8 = 0x28;
+const WRITE_10: u8 = 0x2A;
+const VERIFY_10: u8 = 0x2F;
+const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+const MODE_SENSE_10: u8 = 0x5A;
+
+/// sense key, additional sense code, additional sense code qualifier
+type Sense = (u8, u8, u8);
+const SENSE_NONE: Sense = (0x00, 0x00, 0x00);
+const SENSE_INVALID_COMMAND: Sense = (0x05, 0x20, 0x00);
+const SENSE_OUT_OF_RANGE: Sense = (0x05, 0x21, 0x00);
+const SENSE_WRITE_PROTECTED: Sense = (0x07, 0x27, 0x00);
+const SENSE_WRITE_ERROR: Sense = (0x03, 0x0C, 0x00);
+const SENSE_READ_ERROR: Sense = (0x03, 0x11, 0x00);
+const SENSE_MEDIUM_CHANGED: Sense = (0x06, 0x28, 0x00);
+/// logical unit is in process of becoming ready
+const SENSE_NOT_READY: Sense = (0x02, 0x04, 0x01);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+	/// waiting for the next command
+	Command,
+	/// sending data to the host
+	DataIn,
+	/// receiving data from the host
+	DataOut,
+	/// sending the status of the command
+	Status
+}
+
+/// Write access to the disk, which is too slow for the usb interrupt.
+///
+/// Erasing a sector of the flash takes tens of milliseconds,
+/// so it is done by [`Usb::process_disk()`](crate::usb::Usb::process_disk()) at the main loop.
+#[derive(Clone, Copy)]
+pub(crate) enum Pending {
+	/// write a received block
+	Write(u32, [u8; BLOCK_SIZE]),
+	/// write the cached data, at the end of a WRITE(10) or at SYNCHRONIZE CACHE(10)
+	Flush
+}
+
+pub(crate) struct MassStorage<'a, B: UsbBus, D: BlockDevice> {
+	interface: InterfaceNumber,
+	read_ep: EndpointOut<'a, B>,
+	write_ep: EndpointIn<'a, B>,
+	/// `None` while the disk is accessed outside of the usb interrupt
+	disk: Option<D>,
+	block_count: u32,
+	/// no packets are received, until the write is done
+	pending: Option<Pending>,
+	/// the host has sent all data of the current WRITE(10)
+	data_done: bool,
+	pub(crate) read_only: bool,
+	/// the firmware has changed the disk, the host must drop its cache
+	pub(crate) media_changed: bool,
+	state: State,
+	/// a packet was written to `write_ep` and is not transferred yet
+	in_flight: bool,
+	/// the data phase was ended early by a zero length packet
+	data_end_sent: bool,
+	tag: u32,
+	/// number of bytes the host does expect at the data phase
+	data_len: u32,
+	/// number of bytes transferred at the data phase
+	transferred: u32,
+	status: u8,
+	sense: Sense,
+	/// block of the next read/write and the number of blocks left
+	lba: u32,
+	blocks_left: u32,
+	buf: [u8; BLOCK_SIZE],
+	/// valid bytes at `buf`
+	buf_len: usize,
+	/// next byte of `buf` to send
+	buf_pos: usize
+}
+
+impl<'a, B: UsbBus, D: BlockDevice> MassStorage<'a, B, D> {
+	pub(crate) fn new(alloc: &'a UsbBusAllocator<B>, disk: D) -> Self {
+		Self {
+			interface: alloc.interface(),
+			read_ep: alloc.bulk(PACKET_SIZE as u16),
+			write_ep: alloc.bulk(PACKET_SIZE as u16),
+			block_count: disk.block_count(),
+			disk: Some(disk),
+			pending: None,
+			data_done: false,
+			read_only: false,
+			media_changed: false,
+			state: State::Command,
+			in_flight: false,
+			data_end_sent: false,
+			tag: 0,
+			data_len: 0,
+			transferred: 0,
+			status: STATUS_PASSED,
+			sense: SENSE_NONE,
+			lba: 0,
+			blocks_left: 0,
+			buf: [0; BLOCK_SIZE],
+			buf_len: 0,
+			buf_pos: 0
+		}
+	}
+
+	/// Take the disk, so the firmware can access it.
+	///
+	/// Returns `None`, if a command is processed at the moment.
+	/// Meanwhile commands of the host, which need the disk, fail with "not ready".
+	pub(crate) fn take_disk(&mut self) -> Option<D> {
+		if self.state != State::Command || self.pending.is_some() {
+			return None;
+		}
+		self.disk.take()
+	}
+
+	/// Return the disk after [`take_disk()`](MassStorage::take_disk()),
+	/// and inform the host, that the content has changed.
+	pub(crate) fn return_disk(&mut self, disk: D) {
+		self.disk = Some(disk);
+		self.media_changed = true;
+	}
+
+	/// Take the disk for the pending write.
+	pub(crate) fn take_pending(&mut self) -> Option<(D, Pending)> {
+		let pending = self.pending?;
+		Some((self.disk.take()?, pending))
+	}
+
+	/// Return the disk after [`take_pending()`](MassStorage::take_pending())
+	/// and continue the command.
+	pub(crate) fn complete_pending(&mut self, disk: D, ok: bool) {
+		self.disk = Some(disk);
+		let pending = self.pending.take();
+		if !ok {
+			self.sense = SENSE_WRITE_ERROR;
+			self.status = STATUS_FAILED;
+			self.blocks_left = 0;
+		}
+		match (self.state, pending) {
+			(State::DataOut, Some(Pending::Write(..))) => {
+				if ok {
+					self.lba += 1;
+					self.blocks_left -= 1;
+				}
+				if self.data_done {
+					self.pending = Some(Pending::Flush);
+					return;
+				}
+			},
+			(State::DataOut | State::Status, Some(Pending::Flush)) => {
+				self.state = State::Status;
+				self.send();
+			},
+			// the host has reset the device meanwhile
+			_ => {}
+		}
+		// the next packet was kept at the endpoint, while the write was pending
+		self.receive();
+	}
+
+	fn handle_command(&mut self, cbw: &[u8]) {
+		self.tag = u32::from_le_bytes(cbw[4..8].try_into().unwrap());
+		self.data_len = u32::from_le_bytes(cbw[8..12].try_into().unwrap());
+		let direction_in = cbw[12] & 0x80 != 0;
+		let cb = &cbw[15..31];
+		self.transferred = 0;
+		self.data_end_sent = false;
+		self.status = STATUS_PASSED;
+		self.buf_len = 0;
+		self.buf_pos = 0;
+		self.blocks_left = 0;
+		self.data_done = false;
+
+		let block_count = self.block_count;
+		let ready = self.disk.is_some();
+		let result: core::result::Result<usize, Sense> = match cb[0] {
+			TEST_UNIT_READY => {
+				if !ready {
+					Err(SENSE_NOT_READY)
+				} else if self.media_changed {
+					self.media_changed = false;
+					Err(SENSE_MEDIUM_CHANGED)
+				} else {
+					Ok(0)
+				}
+			},
+			REQUEST_SENSE => {
+				let (key, asc, ascq) = self.sense;
+				self.buf[..18].copy_from_slice(&[
+					0x70, 0, key, 0, 0, 0, 0, 10, 0, 0, 0, 0, asc, ascq, 0, 0, 0, 0
+				]);
+				self.sense = SENSE_NONE;
+				Ok(18)
+			},
+			INQUIRY => {
+				self.buf[..8].copy_from_slice(&[0x00, 0x80, 0x04, 0x02, 31, 0, 0, 0]);
+				self.buf[8..16].copy_from_slice(b"pybadge ");
+				self.buf[16..32].copy_from_slice(b"External Flash  ");
+				self.buf[32..36].copy_from_slice(b"1.0 ");
+				Ok(36)
+			},
+			MODE_SENSE_6 => {
+				let write_protect = if self.read_only { 0x80 } else { 0 };
+				self.buf[..4].copy_from_slice(&[3, 0, write_protect, 0]);
+				Ok(4)
+			},
+			MODE_SENSE_10 => {
+				let write_protect = if self.read_only { 0x80 } else { 0 };
+				self.buf[..8].copy_from_slice(&[0, 6, 0, write_protect, 0, 0, 0, 0]);
+				Ok(8)
+			},
+			START_STOP_UNIT | PREVENT_ALLOW_MEDIUM_REMOVAL | VERIFY_10 => Ok(0),
+			SYNCHRONIZE_CACHE_10 if !ready => Err(SENSE_NOT_READY),
+			SYNCHRONIZE_CACHE_10 => {
+				self.pending = Some(Pending::Flush);
+				Ok(0)
+			},
+			READ_FORMAT_CAPACITIES => {
+				self.buf[..4].copy_from_slice(&[0, 0, 0, 8]);
+				self.buf[4..8].copy_from_slice(&block_count.to_be_bytes());
+				// formatted media, block size as 24 bit
+				self.buf[8..12]
+					.copy_from_slice(&(0x0200_0000 | BLOCK_SIZE as u32).to_be_bytes());
+				Ok(12)
+			},
+			READ_CAPACITY_10 => {
+				self.buf[..4].copy_from_slice(&(block_count - 1).to_be_bytes());
+				self.buf[4..8].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+				Ok(8)
+			},
+			READ_10 | WRITE_10 if !ready => Err(SENSE_NOT_READY),
+			READ_10 | WRITE_10 => {
+				let lba = u32::from_be_bytes(cb[2..6].try_into().unwrap());
+				let count = u16::from_be_bytes([cb[7], cb[8]]) as u32;
+				if lba as u64 + count as u64 > block_count as u64 {
+					Err(SENSE_OUT_OF_RANGE)
+				} else if cb[0] == WRITE_10 && self.read_only {
+					Err(SENSE_WRITE_PROTECTED)
+				} else {
+					self.lba = lba;
+					self.blocks_left = count;
+					Ok(0)
+				}
+			},
+			_ => Err(SENSE_INVALID_COMMAND)
+		};
+
+		match result {
+			Ok(len) => {
+				self.buf_len = min(len, self.data_len as usize);
+				if cb[0] == READ_10 && self.blocks_left > 0 {
+					self.load_next_block();
+				}
+			},
+			Err(sense) => {
+				self.sense = sense;
+				self.status = STATUS_FAILED;
+				self.blocks_left = 0;
+			}
+		}
+		self.state = match (self.data_len, direction_in) {
+			(0, _) => State::Status,
+			(_, true) => State::DataIn,
+			(_, false) => State::DataOut
+		};
+		self.send();
+	}
+
+	/// Read the next block of a READ(10) command to `buf`.
+	fn load_next_block(&mut self) {
+		self.buf_pos = 0;
+		self.buf_len = 0;
+		let mut block = [0_u8; BLOCK_SIZE];
+		let result = match self.disk.as_mut() {
+			Some(disk) => disk.read_block(self.lba, &mut block).is_ok(),
+			None => false
+		};
+		if result {
+			self.buf = block;
+			self.buf_len = BLOCK_SIZE;
+			self.lba += 1;
+			self.blocks_left -= 1;
+		} else {
+			self.sense = SENSE_READ_ERROR;
+			self.status = STATUS_FAILED;
+			self.blocks_left = 0;
+		}
+	}
+
+	/// Send the next packet of the data or status phase.
+	fn send(&mut self) {
+		if self.in_flight || self.pending.is_some() {
+			return;
+		}
+		match self.state {
+			State::DataIn => {
+				if self.buf_pos >= self.buf_len && self.blocks_left > 0 {
+					self.load_next_block();
+				}
+				let left = self.data_len - self.transferred;
+				let len =
+					min(min(PACKET_SIZE, self.buf_len - self.buf_pos), left as usize);
+				if len == 0 {
+					// a short packet does end the data phase early
+					if left > 0
+						&& !self.data_end_sent
+						&& self.transferred % PACKET_SIZE as u32 == 0
+					{
+						if self.write_ep.write(&[]).is_ok() {
+							self.in_flight = true;
+							self.data_end_sent = true;
+						}
+						return;
+					}
+					self.state = State::Status;
+					return self.send();
+				}
+				let packet = &self.buf[self.buf_pos..self.buf_pos + len];
+				if self.write_ep.write(packet).is_ok() {
+					self.in_flight = true;
+					self.buf_pos += len;
+					self.transferred += len as u32;
+				}
+			},
+			State::Status => {
+				let mut csw = [0_u8; CSW_SIZE];
+				csw[0..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+				csw[4..8].copy_from_slice(&self.tag.to_le_bytes());
+				let residue = self.data_len.saturating_sub(self.transferred);
+				csw[8..12].copy_from_slice(&residue.to_le_bytes());
+				csw[12] = self.status;
+				if self.write_ep.write(&csw).is_ok() {
+					self.in_flight = true;
+				}
+			},
+			State::Command | State::DataOut => {}
+		}
+	}
+
+	/// Receive the next packet of the command or data phase.
+	fn receive(&mut self) {
+		if matches!(self.state, State::DataIn | State::Status) || self.pending.is_some() {
+			// keep the packet at the endpoint, until the current command or write is finished
+			return;
+		}
+		let mut packet = [0_u8; PACKET_SIZE];
+		let Ok(len) = self.read_ep.read(&mut packet) else {
+			return;
+		};
+		match self.state {
+			State::Command => {
+				if len == CBW_SIZE
+					&& u32::from_le_bytes(packet[0..4].try_into().unwrap())
+						== CBW_SIGNATURE
+				{
+					self.handle_command(&packet[..CBW_SIZE]);
+				}
+			},
+			State::DataOut => {
+				self.transferred += len as u32;
+				if self.blocks_left > 0 {
+					let len = min(len, BLOCK_SIZE - self.buf_len);
+					self.buf[self.buf_len..self.buf_len + len]
+						.copy_from_slice(&packet[..len]);
+					self.buf_len += len;
+					if self.buf_len == BLOCK_SIZE {
+						self.buf_len = 0;
+						self.pending = Some(Pending::Write(self.lba, self.buf));
+					}
+				}
+				if self.transferred >= self.data_len || len < PACKET_SIZE {
+					// write the cached sector, after the host has send all data
+					self.data_done = true;
+					if self.pending.is_none() {
+						self.pending = Some(Pending::Flush);
+					}
+				}
+			},
+			State::DataIn | State::Status => {}
+		}
+	}
+}
+
+impl<B: UsbBus, D: BlockDevice> UsbClass<B> for MassStorage<'_, B, D> {
+	fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+		// a single interface does not need an interface association descriptor
+		writer.interface(self.interface, CLASS_MSC, SUBCLASS_SCSI, PROTOCOL_BULK_ONLY)?;
+		writer.endpoint(&self.read_ep)?;
+		writer.endpoint(&self.write_ep)?;
+		Ok(())
+	}
+
+	fn reset(&mut self) {
+		self.state = State::Command;
+		self.in_flight = false;
+	}
+
+	fn poll(&mut self) {
+		self.send();
+		self.receive();
+	}
+
+	fn control_in(&mut self, xfer: ControlIn<B>) {
+		let req = xfer.request();
+		if req.request_type == RequestType::Class
+			&& req.recipient == Recipient::Interface
+			&& req.index == u8::from(self.interface) as u16
+			&& req.request == REQ_GET_MAX_LUN
+		{
+			// only one logical unit
+			xfer.accept_with(&[0]).ok();
+		}
+	}
+
+	fn control_out(&mut self, xfer: ControlOut<B>) {
+		let req = xfer.request();
+		if req.request_type == RequestType::Class
+			&& req.recipient == Recipient::Interface
+			&& req.index == u8::from(self.interface) as u16
+			&& req.request == REQ_BULK_ONLY_RESET
+		{
+			self.reset();
+			xfer.accept().ok();
+		}
+	}
+
+	fn endpoint_out(&mut self, addr: EndpointAddress) {
+		if addr == self.read_ep.address() {
+			self.receive();
+		}
+	}
+
+	fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+		if addr != self.write_ep.address() {
+			return;
+		}
+		self.in_flight = false;
+		if self.state == State::Status {
+			self.state = State::Command;
+		} else {
+			self.send();
+		}
+	}
+}
@@ -0,0 +1,235 @@
+//! Serial port with receive and transmit ring buffers.
+//!
+//! The buffers are filled and drained at each poll of the usb device,
+//! so no data gets lost between two calls of the application.
+
+use core::cmp::min;
+use heapless::Deque;
+use usb_device::{class_prelude::*, Result};
+use usbd_serial::{LineCoding, SerialPort};
+
+const RX_SIZE: usize = 256;
+const TX_SIZE: usize = 256;
+
+/// size of a full speed bulk packet
+const PACKET_SIZE: usize = 64;
+
+/// Closing the port at this baud rate does restart into the bootloader.
+const TOUCH_BAUD_RATE: u32 = 1200;
+
+pub(crate) struct BufferedSerial<'a, B: UsbBus> {
+	port: SerialPort<'a, B>,
+	rx: Deque<u8, RX_SIZE>,
+	tx: Deque<u8, TX_SIZE>,
+	touch_reset: bool,
+	/// dtr at the last control request
+	last_dtr: bool
+}
+
+impl<'a, B: UsbBus> BufferedSerial<'a, B> {
+	pub(crate) fn new(alloc: &'a UsbBusAllocator<B>, touch_reset: bool) -> Self {
+		Self {
+			port: SerialPort::new(alloc),
+			rx: Deque::new(),
+			tx: Deque::new(),
+			touch_reset,
+			last_dtr: false
+		}
+	}
+
+	/// Restart into the bootloader, if the host has closed the port after setting the baud rate
+	/// to 1200, like it is done for Arduino boards.
+	fn check_touch_reset(&mut self) {
+		let dtr = self.port.dtr();
+		let closed = self.last_dtr && !dtr;
+		self.last_dtr = dtr;
+		if self.touch_reset
+			&& closed && self.port.line_coding().data_rate() == TOUCH_BAUD_RATE
+		{
+			// give the hardware time to acknowledge the request, before the device disappears
+			cortex_m::asm::delay(12_000_000);
+			crate::reset_into_bootloader();
+		}
+	}
+
+	/// Move received data from the port into the rx buffer.
+	fn receive(&mut self) {
+		loop {
+			// do only read as much as fits into the buffer, the rest is kept at the port
+			let free = min(self.rx.capacity() - self.rx.len(), PACKET_SIZE);
+			if free == 0 {
+				return;
+			}
+			let mut buf = [0_u8; PACKET_SIZE];
+			let len = match self.port.read(&mut buf[..free]) {
+				Ok(len) if len > 0 => len,
+				_ => return
+			};
+			for byte in &buf[..len] {
+				// can not fail, because only the free space was read
+				self.rx.push_back(*byte).ok();
+			}
+		}
+	}
+
+	/// Move data from the tx buffer to the port.
+	fn send(&mut self) {
+		while !self.tx.is_empty() {
+			let (front, _) = self.tx.as_slices();
+			let len = match self.port.write(front) {
+				Ok(len) if len > 0 => len,
+				_ => break
+			};
+			for _ in 0..len {
+				self.tx.pop_front();
+			}
+		}
+		// WouldBlock only means, that the last packet is still in transfer
+		self.port.flush().ok();
+	}
+
+	/// Number of bytes at the rx buffer.
+	pub(crate) fn available(&mut self) -> usize {
+		self.receive();
+		self.rx.len()
+	}
+
+	pub(crate) fn read_byte(&mut self) -> Option<u8> {
+		self.receive();
+		self.rx.pop_front()
+	}
+
+	pub(crate) fn read(&mut self, data: &mut [u8]) -> Result<usize> {
+		self.receive();
+		if self.rx.is_empty() {
+			return Err(UsbError::WouldBlock);
+		}
+		let mut len = 0;
+		while len < data.len() {
+			match self.rx.pop_front() {
+				Some(byte) => data[len] = byte,
+				None => break
+			}
+			len += 1;
+		}
+		Ok(len)
+	}
+
+	/// Copy a whole line without the line ending to `buf` and remove it from the rx buffer.
+	///
+	/// If the rx buffer is full without containing a line ending,
+	/// its content is returned as line, because it would never be completed otherwise.
+	/// Bytes, which do not fit into `buf`, are dropped.
+	pub(crate) fn read_line(&mut self, buf: &mut [u8]) -> Option<usize> {
+		self.read_line_if(buf, |_| true)
+	}
+
+	/// Like [`read_line()`](Self::read_line()),
+	/// but the line is only removed from the rx buffer, if `accept` returns true for it.
+	pub(crate) fn read_line_if(
+		&mut self,
+		buf: &mut [u8],
+		accept: impl FnOnce(&[u8]) -> bool
+	) -> Option<usize> {
+		self.receive();
+		let end = match self.rx.iter().position(|byte| *byte == b'\n') {
+			Some(end) => end + 1,
+			None if self.rx.len() == self.rx.capacity() => self.rx.len(),
+			None => return None
+		};
+		let mut len = 0;
+		for byte in self.rx.iter().take(end) {
+			if *byte != b'\n' && len < buf.len() {
+				buf[len] = *byte;
+				len += 1;
+			}
+		}
+		if len > 0 && buf[len - 1] == b'\r' {
+			len -= 1;
+		}
+		if !accept(&buf[..len]) {
+			return None;
+		}
+		for _ in 0..end {
+			self.rx.pop_front();
+		}
+		Some(len)
+	}
+
+	pub(crate) fn write(&mut self, data: &[u8]) -> Result<usize> {
+		let free = self.tx.capacity() - self.tx.len();
+		if free == 0 && !data.is_empty() {
+			self.send();
+			return Err(UsbError::WouldBlock);
+		}
+		let len = min(free, data.len());
+		for byte in &data[..len] {
+			self.tx.push_back(*byte).ok();
+		}
+		self.send();
+		Ok(len)
+	}
+
+	pub(crate) fn dtr(&self) -> bool {
+		self.port.dtr()
+	}
+
+	pub(crate) fn rts(&self) -> bool {
+		self.port.rts()
+	}
+
+	pub(crate) fn line_coding(&self) -> &LineCoding {
+		self.port.line_coding()
+	}
+
+	/// Returns [`WouldBlock`](UsbError::WouldBlock) until all data was transferred.
+	pub(crate) fn flush(&mut self) -> Result<()> {
+		self.send();
+		if !self.tx.is_empty() {
+			return Err(UsbError::WouldBlock);
+		}
+		self.port.flush()
+	}
+}
+
+impl<B: UsbBus> UsbClass<B> for BufferedSerial<'_, B> {
+	fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> Result<()> {
+		self.port.get_configuration_descriptors(writer)
+	}
+
+	fn reset(&mut self) {
+		self.port.reset();
+		self.rx.clear();
+		self.tx.clear();
+		self.last_dtr = false;
+	}
+
+	fn poll(&mut self) {
+		self.port.poll();
+		self.receive();
+		self.send();
+	}
+
+	fn control_in(&mut self, xfer: ControlIn<B>) {
+		self.port.control_in(xfer);
+	}
+
+	fn control_out(&mut self, xfer: ControlOut<B>) {
+		self.port.control_out(xfer);
+		self.check_touch_reset();
+	}
+
+	fn endpoint_setup(&mut self, addr: EndpointAddress) {
+		self.port.endpoint_setup(addr);
+	}
+
+	fn endpoint_out(&mut self, addr: EndpointAddress) {
+		self.port.endpoint_out(addr);
+		self.receive();
+	}
+
+	fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+		self.port.endpoint_in_complete(addr);
+		self.send();
+	}
+}
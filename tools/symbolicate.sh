@@ -0,0 +1,49 @@
+#!/bin/sh
+# Resolve the addresses of the backtrace at the bluescreen to functions and source lines.
+#
+# usage: symbolicate.sh ELF [ADDRESS...]
+# If no address is given, they are read from stdin, so the lines can be typed in from a photo.
+# The program should be build with `debug = true`, otherwise only the function names are available.
+# A different addr2line can be selected by the ADDR2LINE environment variable.
+set -eu
+
+if [ $# -lt 1 ]; then
+	echo "usage: $0 ELF [ADDRESS...]" >&2
+	exit 1
+fi
+elf="$1"
+shift
+
+if [ -z "${ADDR2LINE:-}" ]; then
+	for tool in llvm-addr2line arm-none-eabi-addr2line addr2line; do
+		if command -v "$tool" >/dev/null 2>&1; then
+			ADDR2LINE="$tool"
+			break
+		fi
+	done
+fi
+if [ -z "${ADDR2LINE:-}" ]; then
+	echo "no addr2line found, install llvm or arm-none-eabi-binutils" >&2
+	exit 1
+fi
+
+symbolicate() {
+	for address in "$@"; do
+		# the bluescreen prints the addresses as hex without prefix
+		address="0x${address#0x}"
+		printf '%s: ' "$address"
+		"$ADDR2LINE" --exe="$elf" --functions --demangle --inlines --pretty-print "$address"
+	done
+}
+
+if [ $# -gt 0 ]; then
+	symbolicate "$@"
+else
+	while read -r line; do
+		case "$line" in
+			backtrace:*) continue ;;
+		esac
+		# shellcheck disable=SC2086 # split the line into addresses
+		symbolicate $line
+	done
+fi